@@ -0,0 +1,223 @@
+//! Minimal arbitrary precision integer for unbounded cells. It only supports
+//! what the brainfuck machine needs: addition, subtraction and conversions.
+
+use std::cmp::Ordering;
+
+/// Sign and magnitude. The magnitude is stored as little endian 32 bit limbs
+/// without trailing zero limbs, so zero has no limbs and is never negative.
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    #[inline]
+    pub fn zero() -> Self {
+        BigInt::default()
+    }
+
+    #[inline]
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// least significant byte of the two's complement representation
+    pub fn least_byte(&self) -> u8 {
+        let byte = self.limbs.first().cloned().unwrap_or(0) as u8;
+        if self.negative {
+            byte.wrapping_neg()
+        } else {
+            byte
+        }
+    }
+
+    pub fn to_isize(&self) -> Option<isize> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+        let mut mag = 0u64;
+        for (index, limb) in self.limbs.iter().enumerate() {
+            mag |= (*limb as u64) << (index * 32);
+        }
+        if self.negative {
+            if mag <= isize::MAX as u64 + 1 {
+                Some((mag as isize).wrapping_neg())
+            } else {
+                None
+            }
+        } else if mag <= isize::MAX as u64 {
+            Some(mag as isize)
+        } else {
+            None
+        }
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            BigInt::from_parts(self.negative, add_mag(&self.limbs, &other.limbs))
+        } else {
+            match cmp_mag(&self.limbs, &other.limbs) {
+                Ordering::Less    => BigInt::from_parts(other.negative, sub_mag(&other.limbs, &self.limbs)),
+                Ordering::Equal   => BigInt::zero(),
+                Ordering::Greater => BigInt::from_parts(self.negative, sub_mag(&self.limbs, &other.limbs)),
+            }
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.limbs.clone())
+    }
+
+    fn from_parts(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        while let Some(0) = limbs.last() {
+            limbs.pop();
+        }
+        let negative = negative && !limbs.is_empty();
+        BigInt { negative, limbs }
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    if a.len() != b.len() {
+        return a.len().cmp(&b.len());
+    }
+    for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+        if x != y {
+            return x.cmp(y);
+        }
+    }
+    Ordering::Equal
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (index, limb) in long.iter().enumerate() {
+        let sum = *limb as u64 + short.get(index).cloned().unwrap_or(0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry != 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// a - b, requires |a| >= |b|
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (index, limb) in a.iter().enumerate() {
+        let mut diff = *limb as i64 - b.get(index).cloned().unwrap_or(0) as i64 - borrow;
+        if diff < 0 {
+            diff += 1 << 32;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        result.push(diff as u32);
+    }
+    result
+}
+
+impl From<isize> for BigInt {
+    fn from(value: isize) -> Self {
+        let mag = value.unsigned_abs() as u64;
+        BigInt::from_parts(value < 0, vec![mag as u32, (mag >> 32) as u32])
+    }
+}
+
+impl std::fmt::Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_zero() {
+            return f.write_str("0");
+        }
+
+        // repeatedly divide by 10^9 and collect the remainders
+        let mut mag = self.limbs.clone();
+        let mut chunks = Vec::new();
+        while !mag.is_empty() {
+            let mut rem = 0u64;
+            for limb in mag.iter_mut().rev() {
+                let cur = (rem << 32) | *limb as u64;
+                *limb = (cur / 1_000_000_000) as u32;
+                rem = cur % 1_000_000_000;
+            }
+            while let Some(0) = mag.last() {
+                mag.pop();
+            }
+            chunks.push(rem as u32);
+        }
+
+        if self.negative {
+            f.write_str("-")?;
+        }
+        let mut iter = chunks.iter().rev();
+        if let Some(first) = iter.next() {
+            write!(f, "{}", first)?;
+        }
+        for chunk in iter {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+/// The string passed to `BigInt::from_str` isn't a decimal number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl std::fmt::Display for ParseBigIntError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("invalid decimal number")
+    }
+}
+
+impl std::error::Error for ParseBigIntError {}
+
+// decimal with an optional sign, like the Display output
+impl std::str::FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match value.as_bytes().first() {
+            Some(b'-') => (true, &value[1..]),
+            Some(b'+') => (false, &value[1..]),
+            _ => (false, value),
+        };
+        if digits.is_empty() {
+            return Err(ParseBigIntError);
+        }
+
+        let mut limbs: Vec<u32> = Vec::new();
+        for c in digits.bytes() {
+            if !c.is_ascii_digit() {
+                return Err(ParseBigIntError);
+            }
+            // limbs = limbs * 10 + digit
+            let mut carry = (c - b'0') as u64;
+            for limb in limbs.iter_mut() {
+                let cur = *limb as u64 * 10 + carry;
+                *limb = cur as u32;
+                carry = cur >> 32;
+            }
+            if carry != 0 {
+                limbs.push(carry as u32);
+            }
+        }
+
+        Ok(BigInt::from_parts(negative, limbs))
+    }
+}
@@ -0,0 +1,332 @@
+//! Bytecode file format (`.bfb`) of an optimized program, so it can be run
+//! again without parsing and optimizing it.
+//!
+//! The header "BFBC", a version byte (1), the cell size in bits (0 for big
+//! cells), a signedness byte (0 or 1), the optimization flags, the tape edge
+//! (0 grow, 1 wrap, 2 error), the tape size and the FNV-1a hash of the source
+//! as 8 little endian bytes, followed by the number of instructions and the
+//! instructions. Numbers are LEB128 varints and signed numbers are zigzag
+//! encoded first, like in the binary trace format. Each instruction is an
+//! opcode byte, its operand and its source span (start line, start column,
+//! end line, end column):
+//!
+//! ```text
+//!  0 move OFFSET       5 read             10 debug_dump
+//!  1 add DELTA         6 write
+//!  2 set VALUE         7 loop start
+//!  3 add_to OFFSET     8 loop end
+//!  4 sub_from OFFSET   9 write LENGTH BYTES
+//! ```
+//!
+//! Cell values are encoded like in the binary trace format.
+//!
+//! Optimization flags: fold 1, set 2, add_to 4, write 8, deadcode 16,
+//! constexpr 32, skip 64, trap overflow 128.
+
+use std::io::Write;
+use super::{Brainfuck, BrainfuckInteger, Error, Instruct, Span, TapeEdge, TapeOptions};
+use super::optimize::Options;
+use super::span::Pos;
+use super::tape::MAX_TAPE_SIZE;
+use super::trace::{write_value, write_varint, zigzag};
+
+pub const BYTECODE_VERSION: u8 = 1;
+
+const MAGIC: &[u8] = b"BFBC";
+
+/// What a bytecode file was compiled for.
+pub struct Header {
+    /// cell size in bits, 0 for big cells
+    pub cell_bits: u8,
+    pub signed: bool,
+    /// the options the program was optimized with, without `constexpr_echo`
+    pub options: Options,
+    pub source_hash: u64,
+}
+
+impl Header {
+    /// The cell size in the syntax of `--cell-size`, e.g. `8u` or `big`.
+    pub fn cell_size(&self) -> String {
+        if self.cell_bits == 0 {
+            return "big".to_string();
+        }
+        format!("{}{}", self.cell_bits, if self.signed { "" } else { "u" })
+    }
+
+    fn flags(&self) -> [bool; 8] {
+        let options = &self.options;
+        [options.fold, options.set, options.add_to, options.write, options.deadcode,
+         options.constexpr, options.skip, options.trap_overflow]
+    }
+}
+
+/// FNV-1a hash of the source, recorded in the header to tell which source
+/// a bytecode file was compiled from.
+pub fn source_hash(source: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in source {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// True if data starts like a bytecode file.
+pub fn is_bytecode(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Writes the program, which was optimized with options.
+pub fn write<Int: BrainfuckInteger>(code: &Brainfuck<Int>, options: &Options, source_hash: u64, out: &mut dyn Write) -> std::io::Result<()> {
+    let header = Header {
+        cell_bits:   (Int::size().unwrap_or(0) * 8) as u8,
+        signed:      Int::is_signed(),
+        options:     Options { constexpr_echo: false, ..*options },
+        source_hash,
+    };
+
+    let mut buf = Vec::new();
+    buf.extend_from_slice(MAGIC);
+    buf.push(BYTECODE_VERSION);
+    buf.push(header.cell_bits);
+    buf.push(header.signed as u8);
+    let mut flags = 0u64;
+    for (bit, enabled) in header.flags().iter().enumerate() {
+        if *enabled {
+            flags |= 1 << bit;
+        }
+    }
+    write_varint(&mut buf, flags);
+    buf.push(match options.tape.edge {
+        TapeEdge::Grow  => 0,
+        TapeEdge::Wrap  => 1,
+        TapeEdge::Error => 2,
+    });
+    write_varint(&mut buf, options.tape.size as u64);
+    buf.extend_from_slice(&source_hash.to_le_bytes());
+    write_varint(&mut buf, code.len() as u64);
+
+    for (index, instr) in code.iter().enumerate() {
+        match *instr {
+            Instruct::Move(off) => {
+                buf.push(0);
+                write_varint(&mut buf, zigzag(off as i64));
+            },
+            Instruct::Add(val) => {
+                buf.push(1);
+                write_varint(&mut buf, zigzag(val as i64));
+            },
+            Instruct::Set(ref val) => {
+                buf.push(2);
+                write_value(&mut buf, val);
+            },
+            Instruct::AddTo(off) => {
+                buf.push(3);
+                write_varint(&mut buf, zigzag(off as i64));
+            },
+            Instruct::SubFrom(off) => {
+                buf.push(4);
+                write_varint(&mut buf, zigzag(off as i64));
+            },
+            Instruct::Read         => buf.push(5),
+            Instruct::Write        => buf.push(6),
+            Instruct::LoopStart(_) => buf.push(7),
+            Instruct::LoopEnd(_)   => buf.push(8),
+            Instruct::WriteStr(ref data) => {
+                buf.push(9);
+                write_varint(&mut buf, data.len() as u64);
+                buf.extend_from_slice(data);
+            },
+            Instruct::DebugDump    => buf.push(10),
+        }
+        let span = code.span(index);
+        write_varint(&mut buf, span.start.lineno as u64);
+        write_varint(&mut buf, span.start.column as u64);
+        write_varint(&mut buf, span.end.lineno as u64);
+        write_varint(&mut buf, span.end.column as u64);
+    }
+
+    out.write_all(&buf)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> std::result::Result<&'a [u8], Error> {
+        if self.data.len() - self.pos < len {
+            return Err(Error::IllegalBytecode("unexpected end of file".to_string()));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> std::result::Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn varint(&mut self) -> std::result::Result<u64, Error> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift > 63 {
+                return Err(Error::IllegalBytecode("varint too long".to_string()));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn signed(&mut self) -> std::result::Result<isize, Error> {
+        let value = self.varint()?;
+        let value = ((value >> 1) as i64) ^ -((value & 1) as i64);
+        if value < isize::MIN as i64 || value > isize::MAX as i64 {
+            return Err(Error::IllegalBytecode(format!("number out of range: {}", value)));
+        }
+        Ok(value as isize)
+    }
+
+    fn len(&mut self) -> std::result::Result<usize, Error> {
+        let len = self.varint()?;
+        if len > (self.data.len() - self.pos) as u64 {
+            return Err(Error::IllegalBytecode("unexpected end of file".to_string()));
+        }
+        Ok(len as usize)
+    }
+
+    fn value<Int: BrainfuckInteger>(&mut self) -> std::result::Result<Int, Error> {
+        if Int::size().is_some() {
+            let value = self.signed()?;
+            let cell = Int::from_delta(value);
+            if cell.to_delta() != Some(value) {
+                return Err(Error::IllegalBytecode(format!("cell value out of range: {}", value)));
+            }
+            return Ok(cell);
+        }
+        let len = self.len()?;
+        let text = self.bytes(len)?;
+        std::str::from_utf8(text).ok().and_then(|text| text.parse().ok())
+            .ok_or_else(|| Error::IllegalBytecode("illegal cell value".to_string()))
+    }
+
+    fn span(&mut self) -> std::result::Result<Span, Error> {
+        let start = Pos::new(self.varint()? as usize, self.varint()? as usize);
+        let end = Pos::new(self.varint()? as usize, self.varint()? as usize);
+        Ok(Span { start, end })
+    }
+}
+
+fn read_header_from(reader: &mut Reader) -> std::result::Result<Header, Error> {
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(Error::IllegalBytecode("not a bytecode file".to_string()));
+    }
+    let version = reader.byte()?;
+    if version != BYTECODE_VERSION {
+        return Err(Error::IllegalBytecode(format!("unsupported version {}", version)));
+    }
+    let cell_bits = reader.byte()?;
+    if ![0, 8, 16, 32, 64].contains(&cell_bits) {
+        return Err(Error::IllegalBytecode(format!("illegal cell size {}", cell_bits)));
+    }
+    let signed = reader.byte()? != 0;
+    let flags = reader.varint()?;
+    let flag = |bit: usize| flags & (1 << bit) != 0;
+    let edge = match reader.byte()? {
+        0 => TapeEdge::Grow,
+        1 => TapeEdge::Wrap,
+        2 => TapeEdge::Error,
+        edge => return Err(Error::IllegalBytecode(format!("illegal tape edge {}", edge))),
+    };
+    let size = reader.varint()?;
+    if edge != TapeEdge::Grow && (size == 0 || size > MAX_TAPE_SIZE as u64) {
+        return Err(Error::IllegalBytecode(format!("illegal tape size {}", size)));
+    }
+    let size = size as usize;
+    let mut hash = [0u8; 8];
+    hash.copy_from_slice(reader.bytes(8)?);
+
+    Ok(Header {
+        cell_bits,
+        signed,
+        options: Options {
+            fold:           flag(0),
+            set:            flag(1),
+            add_to:         flag(2),
+            write:          flag(3),
+            deadcode:       flag(4),
+            constexpr:      flag(5),
+            constexpr_echo: false,
+            skip:           flag(6),
+            tape:           TapeOptions::new(size, edge),
+            trap_overflow:  flag(7),
+        },
+        source_hash: u64::from_le_bytes(hash),
+    })
+}
+
+/// Reads only the header of a bytecode file.
+pub fn read_header(data: &[u8]) -> std::result::Result<Header, Error> {
+    read_header_from(&mut Reader { data, pos: 0 })
+}
+
+/// Reads a bytecode file written for the cell type Int.
+pub fn read<Int: BrainfuckInteger>(data: &[u8]) -> std::result::Result<(Header, Brainfuck<Int>), Error> {
+    let mut reader = Reader { data, pos: 0 };
+    let header = read_header_from(&mut reader)?;
+    if header.cell_bits as usize != Int::size().unwrap_or(0) * 8 || header.signed != Int::is_signed() {
+        return Err(Error::IllegalBytecode(format!("compiled for a different cell size: {}", header.cell_size())));
+    }
+
+    let mut code = Brainfuck::new();
+    let mut depth = 0usize;
+    let count = reader.varint()?;
+    for _ in 0..count {
+        let opcode = reader.byte()?;
+        let instr = match opcode {
+            0  => Instruct::Move(reader.signed()?),
+            1  => Instruct::Add(reader.signed()?),
+            2  => Instruct::Set(reader.value()?),
+            3  => Instruct::AddTo(reader.signed()?),
+            4  => Instruct::SubFrom(reader.signed()?),
+            5  => Instruct::Read,
+            6  => Instruct::Write,
+            7  => {
+                depth += 1;
+                Instruct::LoopStart(0)
+            },
+            8  => {
+                if depth == 0 {
+                    return Err(Error::IllegalBytecode("unmatched loop end".to_string()));
+                }
+                depth -= 1;
+                Instruct::LoopEnd(0)
+            },
+            9  => {
+                let len = reader.len()?;
+                Instruct::WriteStr(reader.bytes(len)?.to_vec())
+            },
+            10 => Instruct::DebugDump,
+            _  => return Err(Error::IllegalBytecode(format!("illegal opcode {}", opcode))),
+        };
+        let span = reader.span()?;
+        // push() links the loops
+        code.push(&instr, span);
+    }
+
+    if depth != 0 {
+        return Err(Error::IllegalBytecode("unmatched loop start".to_string()));
+    }
+    if reader.pos != data.len() {
+        return Err(Error::IllegalBytecode("trailing data".to_string()));
+    }
+    code.verify().map_err(|err| Error::IllegalBytecode(err.to_string()))?;
+
+    Ok((header, code))
+}
@@ -0,0 +1,112 @@
+use std::fs::File;
+use std::io::Write;
+use super::super::{Brainfuck, BrainfuckInteger, Instruct, TapeOptions};
+use super::super::indent::indent;
+use super::generate_c_write_str::generate_c_write_str;
+use super::generate_c_bignum_runtime::generate_c_bignum_runtime;
+
+/// Cells without fixed width can't live in registers, so the whole program is
+/// generated as C code that calls into the bignum runtime.
+pub fn generate<Int: BrainfuckInteger>(code: &Brainfuck<Int>, binary_file: &str, tape: &TapeOptions) -> std::io::Result<Vec<String>> {
+    let c_filename = format!("{}.c", binary_file);
+    let mut out = File::create(&c_filename)?;
+
+    generate_c_bignum_runtime(&mut out, tape)?;
+
+    // Set values that don't fit into a long long are parsed at startup
+    let mut consts = Vec::new();
+    for instr in code.iter() {
+        if let Instruct::Set(ref val) = *instr {
+            if val.to_delta().is_none() {
+                consts.push(format!("{:?}", val));
+            }
+        }
+    }
+
+    if !consts.is_empty() {
+        write!(out, "\nstatic bigint_t consts[{}];\n", consts.len())?;
+    }
+
+    out.write_all(b"\nint main(void) {\n    bf_init();\n")?;
+    for (index, val) in consts.iter().enumerate() {
+        writeln!(out, "    big_from_str(&consts[{}], \"{}\");", index, val)?;
+    }
+    out.write_all(b"\n")?;
+
+    let mut const_index = 0usize;
+    let mut nesting = 1usize;
+    for (pc, instr) in code.iter().enumerate() {
+        let span = code.span(pc);
+        match *instr {
+            Instruct::Move(off) => {
+                indent(&mut out, nesting)?;
+                writeln!(out, "ptr = bf_index({}, {}, {});", off, span.start.lineno, span.start.column)?;
+            },
+
+            Instruct::Add(val) => {
+                let val = Int::wrap_delta(val);
+                if val != 0 {
+                    indent(&mut out, nesting)?;
+                    writeln!(out, "big_add_small(&mem[ptr], {}LL);", val)?;
+                }
+            },
+
+            Instruct::Set(ref val) => {
+                indent(&mut out, nesting)?;
+                if let Some(val) = val.to_delta() {
+                    writeln!(out, "big_set_small(&mem[ptr], {}LL);", val)?;
+                } else {
+                    writeln!(out, "big_copy(&mem[ptr], &consts[{}]);", const_index)?;
+                    const_index += 1;
+                }
+            },
+
+            Instruct::AddTo(off) | Instruct::SubFrom(off) => {
+                let negate = if let Instruct::SubFrom(_) = *instr { 1 } else { 0 };
+                indent(&mut out, nesting)?;
+                out.write_all(b"if (!big_is_zero(&mem[ptr])) {\n")?;
+                indent(&mut out, nesting + 1)?;
+                writeln!(out, "size_t target = bf_index({}, {}, {});", off, span.start.lineno, span.start.column)?;
+                indent(&mut out, nesting + 1)?;
+                writeln!(out, "big_add(&mem[target], &mem[ptr], {});", negate)?;
+                indent(&mut out, nesting)?;
+                out.write_all(b"}\n")?;
+            },
+
+            Instruct::Read => {
+                indent(&mut out, nesting)?;
+                out.write_all(b"bf_read(&mem[ptr]);\n")?;
+            },
+
+            Instruct::Write => {
+                indent(&mut out, nesting)?;
+                out.write_all(b"putchar(big_low_byte(&mem[ptr]));\n")?;
+            },
+
+            Instruct::LoopStart(_) => {
+                indent(&mut out, nesting)?;
+                out.write_all(b"while (!big_is_zero(&mem[ptr])) {\n")?;
+                nesting += 1;
+            },
+
+            Instruct::LoopEnd(_) => {
+                nesting -= 1;
+                indent(&mut out, nesting)?;
+                out.write_all(b"}\n")?;
+            },
+
+            Instruct::WriteStr(ref data) => {
+                generate_c_write_str(&mut out, data, nesting)?;
+            },
+
+            Instruct::DebugDump => {
+                indent(&mut out, nesting)?;
+                out.write_all(b"dbg();\n")?;
+            },
+        }
+    }
+
+    out.write_all(b"\n    fflush(stdout);\n    return 0;\n}\n")?;
+
+    Ok(vec![c_filename])
+}
\ No newline at end of file
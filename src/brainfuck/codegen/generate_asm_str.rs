@@ -1,8 +1,8 @@
 use std::io::Write;
 
-pub fn generate_asm_str(out: &mut Write, name: &str, data: &[u8]) -> std::io::Result<()> {
+pub fn generate_asm_str(out: &mut dyn Write, name: &str, data: &[u8]) -> std::io::Result<()> {
     write!(out, "{:-8}db ", format!("{}:", name))?;
-    if data.len() > 0 {
+    if !data.is_empty() {
         let indent = " ".to_string()
             .repeat(std::cmp::max(name.len() + 1, 8) + 3)
             .into_bytes();
@@ -71,5 +71,5 @@ pub fn generate_asm_str(out: &mut Write, name: &str, data: &[u8]) -> std::io::Re
         out.write_all(b"\"\"\n")?;
     }
 
-    return Ok(());
+    Ok(())
 }
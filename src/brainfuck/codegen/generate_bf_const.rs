@@ -0,0 +1,177 @@
+use std::io::Write;
+
+// Code for a constant is one of:
+//
+//  * a run of + or -
+//  * a multiplication: scratch = top, [cell += base * scratch], cell += digit
+//  * nested multiplications (Horner's method) with the partial result moving
+//    back and forth between the cell and the scratch cell, so it ends up in
+//    the cell. Only possible if the cell starts at 0.
+//
+// The multiplication loops count the source cell towards 0 from whatever side
+// it is on, so no loop runs more often than the value of the partial result.
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConstOptions {
+    /// the current cell is 0
+    pub zero: bool,
+    /// offset of a cell whose value doesn't matter
+    pub scratch: Option<isize>,
+    /// the scratch cell is known to be 0 already
+    pub scratch_zero: bool,
+}
+
+struct Plan {
+    base: i128,
+    // most significant first, the first one is the top
+    digits: Vec<i128>,
+}
+
+fn plain_len(value: i128) -> usize {
+    value.unsigned_abs() as usize
+}
+
+// digits of value in base with n multiplications, the lower digits balanced
+// around 0
+fn digits(value: i128, base: i128, n: usize) -> Vec<i128> {
+    let mut digits = Vec::with_capacity(n + 1);
+    let mut rest = value;
+    for _ in 0..n {
+        let mut digit = rest.rem_euclid(base);
+        if digit > base / 2 {
+            digit -= base;
+        }
+        digits.push(digit);
+        rest = (rest - digit) / base;
+    }
+    digits.push(rest);
+    digits.reverse();
+    digits
+}
+
+fn plan_len(plan: &Plan, dist: usize, options: &ConstOptions) -> usize {
+    let steps = plan.digits.len() - 1;
+    let mut len = 0;
+    if !options.scratch_zero {
+        // go there, [-]
+        len += dist + 3;
+        if steps.is_multiple_of(2) {
+            len += dist;
+        }
+    } else if steps % 2 == 1 {
+        len += dist;
+    }
+
+    let mut partial = 0i128;
+    for (index, digit) in plan.digits.iter().enumerate() {
+        if index > 0 {
+            if partial != 0 {
+                // [-> +++ <]>
+                len += 3 + 2 * dist + plan.base as usize;
+            }
+            len += dist;
+            partial *= plan.base;
+        }
+        len += digit.unsigned_abs() as usize;
+        partial += digit;
+    }
+    len
+}
+
+fn best_plan(value: i128, dist: usize, options: &ConstOptions) -> Option<(Plan, usize)> {
+    let mut best: Option<(Plan, usize)> = None;
+    let max_base = std::cmp::min(value.unsigned_abs(), 256) as i128;
+    for base in 2..=max_base {
+        let mut n = 1;
+        loop {
+            let plan = Plan { base, digits: digits(value, base, n) };
+            let top = plan.digits[0];
+            let len = plan_len(&plan, dist, options);
+            if best.as_ref().map(|(_, best_len)| len < *best_len).unwrap_or(true) {
+                best = Some((plan, len));
+            }
+            // nesting needs a zero cell and doesn't help once the top digit
+            // is a single digit
+            if top.abs() < base || !options.zero {
+                break;
+            }
+            n += 1;
+        }
+    }
+    best
+}
+
+/// Length of the code generate_bf_const writes.
+pub fn bf_const_len(value: i128, options: &ConstOptions) -> usize {
+    let plain = plain_len(value);
+    match options.scratch {
+        Some(scratch) => match best_plan(value, scratch.unsigned_abs(), options) {
+            Some((_, len)) if len < plain => len,
+            _ => plain,
+        },
+        None => plain,
+    }
+}
+
+pub(crate) fn write_add(out: &mut dyn Write, value: i128) -> std::io::Result<()> {
+    let c = if value > 0 { b"+" } else { b"-" };
+    for _ in 0..value.unsigned_abs() {
+        out.write_all(c)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_move(out: &mut dyn Write, off: isize) -> std::io::Result<()> {
+    let c = if off > 0 { b">" } else { b"<" };
+    for _ in 0..off.unsigned_abs() {
+        out.write_all(c)?;
+    }
+    Ok(())
+}
+
+/// Writes code that adds value to the current cell and leaves the pointer
+/// there. Returns true if the scratch cell was used, which leaves it at 0.
+pub fn generate_bf_const(out: &mut dyn Write, value: i128, options: &ConstOptions) -> std::io::Result<bool> {
+    let scratch = match options.scratch {
+        Some(scratch) => scratch,
+        None => return write_add(out, value).map(|_| false),
+    };
+    let plan = match best_plan(value, scratch.unsigned_abs(), options) {
+        Some((plan, len)) if len < plain_len(value) => plan,
+        _ => return write_add(out, value).map(|_| false),
+    };
+
+    let steps = plan.digits.len() - 1;
+    // where the partial result is, relative to the cell
+    let mut pos = if steps % 2 == 1 { scratch } else { 0 };
+    if !options.scratch_zero {
+        write_move(out, scratch)?;
+        out.write_all(b"[-]")?;
+        write_move(out, pos - scratch)?;
+    } else {
+        write_move(out, pos)?;
+    }
+
+    let mut partial = 0i128;
+    for (index, digit) in plan.digits.iter().enumerate() {
+        if index > 0 {
+            let target = if pos == 0 { scratch } else { 0 };
+            if partial != 0 {
+                let (count, add) = if partial > 0 { (b"-", plan.base) } else { (b"+", -plan.base) };
+                out.write_all(b"[")?;
+                out.write_all(count)?;
+                write_move(out, target - pos)?;
+                write_add(out, add)?;
+                write_move(out, pos - target)?;
+                out.write_all(b"]")?;
+            }
+            write_move(out, target - pos)?;
+            pos = target;
+            partial *= plan.base;
+        }
+        write_add(out, *digit)?;
+        partial += digit;
+    }
+
+    Ok(true)
+}
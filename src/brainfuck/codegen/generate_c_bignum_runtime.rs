@@ -0,0 +1,349 @@
+use std::io::Write;
+use super::super::{TapeOptions, TapeEdge};
+
+pub fn generate_c_bignum_runtime(runtime: &mut dyn Write, tape: &TapeOptions) -> std::io::Result<()> {
+        let tape_edge = match tape.edge {
+            TapeEdge::Grow  => "TAPE_GROW",
+            TapeEdge::Wrap  => "TAPE_WRAP",
+            TapeEdge::Error => "TAPE_ERROR",
+        };
+
+        write!(runtime, r##"#include <stdio.h>
+#include <stdlib.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+
+#define TAPE_SIZE {0}
+#define {1}
+"##, tape.size, tape_edge)?;
+
+        runtime.write_all(br##"
+#define BF_FN static __attribute__((unused))
+
+// sign and magnitude, little endian 32 bit limbs, no trailing zero limbs
+typedef struct bigint {
+    int neg;
+    size_t len;
+    size_t cap;
+    uint32_t *limbs;
+} bigint_t;
+
+static bigint_t *mem = NULL;
+static size_t mem_size = 0;
+static size_t ptr = 0;
+// index of the cell the program started at, grows with memory underflows
+static size_t origin = 0;
+
+BF_FN void big_reserve(bigint_t *a, size_t cap) {
+    if (a->cap < cap) {
+        size_t new_cap = a->cap ? a->cap * 2 : 4;
+        if (new_cap < cap) {
+            new_cap = cap;
+        }
+        uint32_t *limbs = realloc(a->limbs, new_cap * sizeof(uint32_t));
+        if (limbs == NULL) {
+            perror("realloc");
+            abort();
+        }
+        a->limbs = limbs;
+        a->cap = new_cap;
+    }
+}
+
+BF_FN void big_trim(bigint_t *a) {
+    while (a->len > 0 && a->limbs[a->len - 1] == 0) {
+        -- a->len;
+    }
+    if (a->len == 0) {
+        a->neg = 0;
+    }
+}
+
+BF_FN inline int big_is_zero(const bigint_t *a) {
+    return a->len == 0;
+}
+
+BF_FN void big_set_small(bigint_t *a, long long value) {
+    unsigned long long mag = value < 0 ? -(unsigned long long)value : (unsigned long long)value;
+    big_reserve(a, 2);
+    a->neg = value < 0;
+    a->limbs[0] = (uint32_t)mag;
+    a->limbs[1] = (uint32_t)(mag >> 32);
+    a->len = 2;
+    big_trim(a);
+}
+
+BF_FN void big_copy(bigint_t *dst, const bigint_t *src) {
+    big_reserve(dst, src->len);
+    if (src->len > 0) {
+        memcpy(dst->limbs, src->limbs, src->len * sizeof(uint32_t));
+    }
+    dst->len = src->len;
+    dst->neg = src->neg;
+}
+
+BF_FN int cmp_mag(const bigint_t *a, const bigint_t *b) {
+    if (a->len != b->len) {
+        return a->len < b->len ? -1 : 1;
+    }
+    for (size_t i = a->len; i > 0; -- i) {
+        if (a->limbs[i - 1] != b->limbs[i - 1]) {
+            return a->limbs[i - 1] < b->limbs[i - 1] ? -1 : 1;
+        }
+    }
+    return 0;
+}
+
+// |dst| += |src|
+BF_FN void add_mag(bigint_t *dst, const bigint_t *src) {
+    size_t len = dst->len > src->len ? dst->len : src->len;
+    big_reserve(dst, len + 1);
+    uint64_t carry = 0;
+    for (size_t i = 0; i < len; ++ i) {
+        uint64_t sum = carry;
+        if (i < dst->len) sum += dst->limbs[i];
+        if (i < src->len) sum += src->limbs[i];
+        dst->limbs[i] = (uint32_t)sum;
+        carry = sum >> 32;
+    }
+    dst->limbs[len] = (uint32_t)carry;
+    dst->len = len + 1;
+}
+
+// |dst| = |dst| - |src|, requires |dst| >= |src|
+BF_FN void sub_mag(bigint_t *dst, const bigint_t *src) {
+    int64_t borrow = 0;
+    for (size_t i = 0; i < dst->len; ++ i) {
+        int64_t diff = (int64_t)dst->limbs[i] - (i < src->len ? (int64_t)src->limbs[i] : 0) - borrow;
+        borrow = diff < 0;
+        dst->limbs[i] = (uint32_t)(diff + (borrow << 32));
+    }
+}
+
+// |dst| = |src| - |dst|, requires |src| > |dst|
+BF_FN void rsub_mag(bigint_t *dst, const bigint_t *src) {
+    big_reserve(dst, src->len);
+    int64_t borrow = 0;
+    for (size_t i = 0; i < src->len; ++ i) {
+        int64_t diff = (int64_t)src->limbs[i] - (i < dst->len ? (int64_t)dst->limbs[i] : 0) - borrow;
+        borrow = diff < 0;
+        dst->limbs[i] = (uint32_t)(diff + (borrow << 32));
+    }
+    dst->len = src->len;
+}
+
+// dst += src or dst -= src
+BF_FN void big_add(bigint_t *dst, const bigint_t *src, int negate) {
+    if (dst == src) {
+        bigint_t copy = { 0, 0, 0, NULL };
+        big_copy(&copy, src);
+        big_add(dst, &copy, negate);
+        free(copy.limbs);
+        return;
+    }
+
+    int src_neg = src->neg ^ negate;
+    if (dst->len == 0 || dst->neg == src_neg) {
+        add_mag(dst, src);
+        dst->neg = src_neg;
+    } else if (cmp_mag(dst, src) >= 0) {
+        sub_mag(dst, src);
+    } else {
+        rsub_mag(dst, src);
+        dst->neg = src_neg;
+    }
+    big_trim(dst);
+}
+
+BF_FN void big_add_small(bigint_t *dst, long long value) {
+    uint32_t limbs[2];
+    bigint_t src = { 0, 0, 2, limbs };
+    big_set_small(&src, value);
+    big_add(dst, &src, 0);
+}
+
+BF_FN void big_from_str(bigint_t *a, const char *str) {
+    int neg = *str == '-';
+    if (neg) {
+        ++ str;
+    }
+    a->len = 0;
+    for (; *str; ++ str) {
+        uint64_t carry = (uint64_t)(*str - '0');
+        for (size_t i = 0; i < a->len; ++ i) {
+            uint64_t prod = (uint64_t)a->limbs[i] * 10 + carry;
+            a->limbs[i] = (uint32_t)prod;
+            carry = prod >> 32;
+        }
+        if (carry) {
+            big_reserve(a, a->len + 1);
+            a->limbs[a->len ++] = (uint32_t)carry;
+        }
+    }
+    a->neg = neg;
+    big_trim(a);
+}
+
+// least significant byte of the two's complement representation
+BF_FN int big_low_byte(const bigint_t *a) {
+    int low = a->len ? (int)(a->limbs[0] & 0xFF) : 0;
+    return a->neg ? (256 - low) & 0xFF : low;
+}
+
+BF_FN void bf_read(bigint_t *a) {
+    fflush(stdout);
+    int ch = getchar();
+    big_set_small(a, ch == EOF ? -1 : ch);
+}
+
+#ifdef TAPE_ERROR
+static void bf_tape_error(size_t lineno, size_t column, ptrdiff_t index) __attribute__((noreturn));
+
+static void bf_tape_error(size_t lineno, size_t column, ptrdiff_t index) {
+    fflush(stdout);
+    fprintf(stderr,
+        "error:%zu:%zu: pointer moved out of tape bounds: cell %td is not in 0...%zu\n",
+        lineno, column, index, (size_t)TAPE_SIZE - 1);
+    // the exit status of runtime errors in the interpreter
+    exit(7);
+}
+#endif
+
+// index of the cell at off relative to ptr
+BF_FN size_t bf_index(ptrdiff_t off, size_t lineno, size_t column) {
+    ptrdiff_t index = (ptrdiff_t)ptr + off;
+#if defined(TAPE_WRAP)
+    (void)lineno;
+    (void)column;
+    index %= (ptrdiff_t)TAPE_SIZE;
+    if (index < 0) {
+        index += TAPE_SIZE;
+    }
+#elif defined(TAPE_ERROR)
+    if (index < 0 || index >= (ptrdiff_t)TAPE_SIZE) {
+        bf_tape_error(lineno, column, index);
+    }
+#else
+    (void)lineno;
+    (void)column;
+    if (index < 0 || (size_t)index >= mem_size) {
+        // grow the tape, underflows move everything to the right
+        size_t shift = index < 0 ? (size_t)-index : 0;
+        size_t new_size = mem_size * 2;
+        if (new_size < mem_size + shift || (size_t)index >= new_size) {
+            new_size = mem_size + shift + (index < 0 ? 0 : (size_t)index) + 1;
+        }
+        bigint_t *new_mem = realloc(mem, new_size * sizeof(bigint_t));
+        if (new_mem == NULL) {
+            perror("realloc");
+            abort();
+        }
+        memset(new_mem + mem_size, 0, (new_size - mem_size) * sizeof(bigint_t));
+        if (shift > 0) {
+            memmove(new_mem + shift, new_mem, mem_size * sizeof(bigint_t));
+            memset(new_mem, 0, shift * sizeof(bigint_t));
+            ptr    += shift;
+            index  += shift;
+            origin += shift;
+        }
+        mem = new_mem;
+        mem_size = new_size;
+    }
+#endif
+    return (size_t)index;
+}
+
+BF_FN void big_print(FILE *stream, const bigint_t *a) {
+    if (a->len == 0) {
+        fputc('0', stream);
+        return;
+    }
+
+    // repeatedly divide by 10^9 and collect the remainders
+    size_t len = a->len;
+    uint32_t *mag = malloc(len * sizeof(uint32_t));
+    uint32_t *chunks = malloc((len * 2 + 1) * sizeof(uint32_t));
+    if (mag == NULL || chunks == NULL) {
+        perror("malloc");
+        abort();
+    }
+    memcpy(mag, a->limbs, len * sizeof(uint32_t));
+
+    size_t count = 0;
+    while (len > 0) {
+        uint64_t rem = 0;
+        for (size_t i = len; i > 0; -- i) {
+            uint64_t cur = (rem << 32) | mag[i - 1];
+            mag[i - 1] = (uint32_t)(cur / 1000000000);
+            rem = cur % 1000000000;
+        }
+        while (len > 0 && mag[len - 1] == 0) {
+            -- len;
+        }
+        chunks[count ++] = (uint32_t)rem;
+    }
+
+    if (a->neg) {
+        fputc('-', stream);
+    }
+    fprintf(stream, "%u", chunks[count - 1]);
+    for (size_t i = count - 1; i > 0; -- i) {
+        fprintf(stream, "%09u", chunks[i - 1]);
+    }
+
+    free(chunks);
+    free(mag);
+}
+
+// prints the memory
+BF_FN void dbg(void) {
+    fflush(stdout);
+    fprintf(stderr, "index: %td, mem_size: %zu, *ptr: ", (ptrdiff_t)ptr - (ptrdiff_t)origin, mem_size);
+    big_print(stderr, &mem[ptr]);
+    fprintf(stderr, "\nmem = [");
+    for (size_t i = 0; i < mem_size;) {
+        if (i != 0) {
+            fprintf(stderr, ", ");
+        }
+
+        if (i == ptr) {
+            fprintf(stderr, ">>");
+            big_print(stderr, &mem[i]);
+            fprintf(stderr, "<<");
+            ++ i;
+            continue;
+        }
+
+        size_t count = 1;
+        for (size_t j = i + 1; j < mem_size && j != ptr &&
+                mem[j].neg == mem[i].neg && cmp_mag(&mem[j], &mem[i]) == 0; ++ j) {
+            ++ count;
+        }
+        big_print(stderr, &mem[i]);
+        if (count > 3) {
+            fprintf(stderr, "... x%zu", count);
+            i += count;
+        } else {
+            ++ i;
+        }
+    }
+    fprintf(stderr, "]\n");
+}
+
+BF_FN void bf_init(void) {
+#ifdef TAPE_GROW
+    mem_size = 4096;
+#else
+    mem_size = TAPE_SIZE;
+#endif
+    mem = calloc(mem_size, sizeof(bigint_t));
+    if (mem == NULL) {
+        perror("calloc");
+        exit(EXIT_FAILURE);
+    }
+}
+"##)?;
+
+    Ok(())
+}
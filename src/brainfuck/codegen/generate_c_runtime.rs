@@ -1,6 +1,13 @@
 use std::io::Write;
+use super::super::{TapeOptions, TapeEdge};
+
+pub fn generate_c_runtime(runtime: &mut dyn Write, cell_type: &str, pagesize: usize, tape: &TapeOptions, trap_overflow: bool, debug_dump: bool) -> std::io::Result<()> {
+        let tape_edge = match tape.edge {
+            TapeEdge::Grow  => "TAPE_GROW",
+            TapeEdge::Wrap  => "TAPE_WRAP",
+            TapeEdge::Error => "TAPE_ERROR",
+        };
 
-pub fn generate_c_runtime(runtime: &mut Write, cell_type: &str, pagesize: usize) -> std::io::Result<()> {
         write!(runtime, r##"#define _GNU_SOURCE
 
 #include <stdio.h>
@@ -14,7 +21,17 @@ pub fn generate_c_runtime(runtime: &mut Write, cell_type: &str, pagesize: usize)
 
 #define PAGESIZE {0}
 #define CELL_T {1}
-"##, pagesize, cell_type)?;
+#define TAPE_SIZE {2}
+#define {3}
+"##, pagesize, cell_type, tape.size, tape_edge)?;
+
+        if trap_overflow {
+            runtime.write_all(b"#define TRAP_OVERFLOW\n")?;
+        }
+
+        if debug_dump {
+            runtime.write_all(b"#define DEBUG_DUMP\n")?;
+        }
 
         runtime.write_all(br##"
 #ifndef __linux__
@@ -27,38 +44,80 @@ pub fn generate_c_runtime(runtime: &mut Write, cell_type: &str, pagesize: usize)
 #   error architecture currently not supported
 #endif
 
+#ifdef TAPE_GROW
+#   define GUARD_SIZE PAGESIZE
+#else
+#   define GUARD_SIZE 0
+#endif
+
 volatile CELL_T* mem = NULL;
 volatile size_t mem_size = 0;
 
+#ifdef TAPE_GROW
+// bytes the cells were moved to the right by memory underflows
+volatile size_t mem_shift = 0;
+#endif
+
 struct sigaction segv_action;
 
 void bfmain();
 
-#ifdef DEBUG
-// this function can be called in a debugger to print information about the current state of the program
-void dbg() {
-    ucontext_t ctx;
-    memset(&ctx, 0, sizeof(ctx));
-    CELL_T *ptr;
+#ifdef TAPE_ERROR
+// called by bfmain() when the pointer leaves the tape
+void bf_tape_error(size_t lineno, size_t column, uintptr_t ptr) __attribute__((noreturn));
 
-    if (getcontext(&ctx) != 0) {
-        perror("getcontext");
-        ptr = NULL;
+void bf_tape_error(size_t lineno, size_t column, uintptr_t ptr) {
+    fflush(stdout);
+    intptr_t index = ((intptr_t)ptr - (intptr_t)mem) / (intptr_t)sizeof(CELL_T);
+    fprintf(stderr,
+        "error:%zu:%zu: pointer moved out of tape bounds: cell %zd is not in 0...%zu\n",
+        lineno, column, index, (size_t)TAPE_SIZE - 1);
+    // the exit status of runtime errors in the interpreter
+    exit(7);
+}
+#endif
+
+#ifdef TRAP_OVERFLOW
+// called by bfmain() when a cell over- or underflows
+void bf_overflow_error(size_t lineno, size_t column, uintptr_t ptr) __attribute__((noreturn));
+
+void bf_overflow_error(size_t lineno, size_t column, uintptr_t ptr) {
+    fflush(stdout);
+    intptr_t offset = (intptr_t)ptr - (intptr_t)mem - GUARD_SIZE;
+#ifdef TAPE_GROW
+    offset -= (intptr_t)mem_shift;
+#endif
+    fprintf(stderr,
+        "error:%zu:%zu: arithmetic overflow in cell %zd\n",
+        lineno, column, offset / (intptr_t)sizeof(CELL_T));
+    // the exit status of runtime errors in the interpreter
+    exit(7);
+}
+#endif
+
+#if defined(DEBUG) || defined(DEBUG_DUMP)
+// prints the memory, ptr is the current cell or NULL if it is unknown
+void dbg_at(const volatile CELL_T *ptr) {
+    fflush(stdout);
+
+    // logical index of the cell the program started at
+    intptr_t origin = GUARD_SIZE / sizeof(CELL_T);
+#ifdef TAPE_GROW
+    origin += mem_shift / sizeof(CELL_T);
+#endif
 
+    if (ptr == NULL) {
         fprintf(stderr,
-            "pid: %d, rax: <unknown>, r12: <unknown>, index: <unknown>, mem: 0x%zx, mem_size: %zu, usable_mem_size: %zu, pagesize: %u, *ptr: <unknown>\n",
-            getpid(), (uintptr_t)mem, mem_size, mem_size - PAGESIZE * 2, PAGESIZE);
+            "index: <unknown>, mem: 0x%zx, mem_size: %zu, usable_mem_size: %zu, pagesize: %u, *ptr: <unknown>\n",
+            (uintptr_t)mem, mem_size, mem_size - GUARD_SIZE * 2, PAGESIZE);
     } else {
-        intptr_t rax = ctx.uc_mcontext.gregs[REG_RAX];
-        intptr_t r12 = ctx.uc_mcontext.gregs[REG_R12];
-        ptr = (CELL_T*)r12;
         intptr_t index = ptr - mem;
 
         fprintf(stderr,
-            "pid: %d, rax: %zd, r12: %zd, index: %zd, mem: 0x%zx, mem_size: %zu, usable_mem_size: %zu, pagesize: %u, *ptr: ",
-            getpid(), rax, r12, index - PAGESIZE, (uintptr_t)mem, mem_size, mem_size - PAGESIZE * 2, PAGESIZE);
+            "index: %zd, mem: 0x%zx, mem_size: %zu, usable_mem_size: %zu, pagesize: %u, *ptr: ",
+            index - origin, (uintptr_t)mem, mem_size, mem_size - GUARD_SIZE * 2, PAGESIZE);
 
-        if ((void*)ptr < (void*)mem + PAGESIZE || (void*)ptr >= (void*)mem + mem_size - PAGESIZE) {
+        if ((void*)ptr < (void*)mem + GUARD_SIZE || (void*)ptr >= (void*)mem + mem_size - GUARD_SIZE) {
             fprintf(stderr, "<out of bounds>\n");
         } else {
             fprintf(stderr, "%zd\n", (intptr_t)mem[index]);
@@ -66,8 +125,8 @@ void dbg() {
     }
 
     fprintf(stderr, "mem = [");
-    const size_t start = PAGESIZE / sizeof(CELL_T);
-    const size_t end = (mem_size - PAGESIZE) / sizeof(CELL_T);
+    const size_t start = GUARD_SIZE / sizeof(CELL_T);
+    const size_t end = (mem_size - GUARD_SIZE) / sizeof(CELL_T);
     for (size_t i = start; i < end;) {
         CELL_T val = mem[i];
 
@@ -76,7 +135,7 @@ void dbg() {
         }
 
         if (mem + i == ptr) {
-            fprintf(stderr, ">>%d<<", val);
+            fprintf(stderr, ">>%zd<<", (intptr_t)val);
             ++ i;
             continue;
         }
@@ -95,6 +154,26 @@ void dbg() {
     }
     fprintf(stderr, "]\n");
 }
+#endif
+
+#ifdef DEBUG
+// this function can be called in a debugger to print information about the current state of the program
+void dbg() {
+    ucontext_t ctx;
+    memset(&ctx, 0, sizeof(ctx));
+
+    if (getcontext(&ctx) != 0) {
+        perror("getcontext");
+        fprintf(stderr, "pid: %d, rax: <unknown>, r12: <unknown>, ", getpid());
+        dbg_at(NULL);
+    } else {
+        intptr_t rax = ctx.uc_mcontext.gregs[REG_RAX];
+        intptr_t r12 = ctx.uc_mcontext.gregs[REG_R12];
+
+        fprintf(stderr, "pid: %d, rax: %zd, r12: %zd, ", getpid(), rax, r12);
+        dbg_at((CELL_T*)r12);
+    }
+}
 
 void handle_sigint(int signum) {
     (void)signum;
@@ -141,6 +220,7 @@ void handle_sigint(int signum) {
 }
 #endif
 
+#ifdef TAPE_GROW
 void memmng(int signum, siginfo_t *info, void *vctx) {
     (void)signum;
 
@@ -200,6 +280,7 @@ void memmng(int signum, siginfo_t *info, void *vctx) {
         memmove(new_mem + PAGESIZE * 2, new_mem + PAGESIZE, mem_size - PAGESIZE * 2);
         memset(new_mem + PAGESIZE, 0, PAGESIZE);
         ptr1 += PAGESIZE;
+        mem_shift += PAGESIZE;
     }
 
     ptr1 = (greg_t)new_mem + (ptr1 - (greg_t)(void*)mem);
@@ -209,8 +290,10 @@ void memmng(int signum, siginfo_t *info, void *vctx) {
     mem = new_mem;
     mem_size = new_size;
 }
+#endif
 
 int main() {
+#ifdef TAPE_GROW
     memset(&segv_action, 0, sizeof(struct sigaction));
 
     segv_action.sa_flags = SA_SIGINFO;
@@ -219,11 +302,13 @@ int main() {
         perror("sigaction");
         return EXIT_FAILURE;
     }
+#endif
 
 #ifdef DEBUG
     signal(SIGINT, handle_sigint);
 #endif
 
+#ifdef TAPE_GROW
     mem_size = PAGESIZE * 3;
     mem = mmap(NULL, mem_size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
     if (mem == MAP_FAILED) {
@@ -240,6 +325,14 @@ int main() {
         perror("mprotect guard after");
         return EXIT_FAILURE;
     }
+#else
+    mem_size = TAPE_SIZE * sizeof(CELL_T);
+    mem = calloc(TAPE_SIZE, sizeof(CELL_T));
+    if (mem == NULL) {
+        perror("calloc");
+        return EXIT_FAILURE;
+    }
+#endif
 
     bfmain();
 
@@ -1,8 +1,8 @@
 use std::io::Write;
 use super::super::indent::indent;
 
-pub fn generate_c_write_str(out: &mut Write, data: &[u8], nesting: usize) -> std::io::Result<()> {
-    if data.len() > 0 {
+pub fn generate_c_write_str(out: &mut dyn Write, data: &[u8], nesting: usize) -> std::io::Result<()> {
+    if !data.is_empty() {
         indent(out, nesting)?;
         let multiline = if let Some(pos) = data.iter().position(|b| *b == b'\n') {
             pos < data.len() - 1
@@ -11,7 +11,7 @@ pub fn generate_c_write_str(out: &mut Write, data: &[u8], nesting: usize) -> std
         };
 
         if multiline {
-            write!(out, "fwrite(\n")?;
+            writeln!(out, "fwrite(")?;
             indent(out, nesting + 1)?;
             write!(out, "\"")?;
         } else {
@@ -54,7 +54,7 @@ pub fn generate_c_write_str(out: &mut Write, data: &[u8], nesting: usize) -> std
                     out.write_all(b"\\b")?;
                 },
 
-                c if c >= 32 && c <= 126 => {
+                c if (32..=126).contains(&c) => {
                     out.write_all(&[c])?;
                 },
 
@@ -64,8 +64,8 @@ pub fn generate_c_write_str(out: &mut Write, data: &[u8], nesting: usize) -> std
             }
         }
 
-        write!(out, "\", {}, 1, stdout);\n", data.len())?;
+        writeln!(out, "\", {}, 1, stdout);", data.len())?;
     }
 
-    return Ok(());
+    Ok(())
 }
@@ -1,21 +1,28 @@
 extern crate num_traits;
 
-use num_traits::Signed;
 use std::fs::File;
 use std::io::Write;
 use std::collections::HashMap;
 use std::ffi::OsStr;
-use super::super::{Brainfuck, BrainfuckInteger, Instruct};
+use super::super::{Brainfuck, BrainfuckInteger, Error, Instruct, TapeOptions, TapeEdge};
 use super::generate_c_write_str::generate_c_write_str;
 use super::generate_asm_str::generate_asm_str;
 use super::generate_c_runtime::generate_c_runtime;
+use super::c_bignum;
+
+/// Writes the assembler (or for big cells C) source of the program and the C
+/// runtime next to binary_file. Returns the names of the written files.
+pub fn generate<Int: BrainfuckInteger>(code: &Brainfuck<Int>, binary_file: &str, tape: &TapeOptions, trap_overflow: bool) -> std::io::Result<Vec<String>> {
+    if Int::size().is_none() {
+        return c_bignum::generate(code, binary_file, tape);
+    }
 
-pub fn generate<Int: BrainfuckInteger + Signed>(code: &Brainfuck<Int>, binary_file: &str) -> std::io::Result<Vec<String>> {
     let mut filenames = Vec::new();
     let mut min_move = 0isize;
     let mut max_move = 0isize;
     let mut cur_move = 0isize;
     let mut uses_mem = false;
+    let mut debug_dump = false;
     let mut last_was_move = false;
     let mut nesting = 1usize;
 
@@ -81,7 +88,13 @@ pub fn generate<Int: BrainfuckInteger + Signed>(code: &Brainfuck<Int>, binary_fi
                 last_was_move = false;
             },
 
-            Instruct::WriteStr(_) => {}
+            Instruct::WriteStr(_) => {},
+
+            Instruct::DebugDump => {
+                uses_mem = true;
+                debug_dump = true;
+                last_was_move = false;
+            }
         }
     }
 
@@ -96,11 +109,19 @@ pub fn generate<Int: BrainfuckInteger + Signed>(code: &Brainfuck<Int>, binary_fi
         let mut runtime = File::create(&runtime_src_filename)?;
         filenames.push(runtime_src_filename);
 
-        generate_c_runtime(&mut runtime, Int::c_type(), pagesize)?;
+        generate_c_runtime(&mut runtime, Int::c_type(), pagesize, tape, trap_overflow, debug_dump)?;
 
         let mut str_table = HashMap::new();
         let mut loop_stack = Vec::new();
         let mut loop_count = 0usize;
+        // labels of tape edge checks and the out of bounds stubs: (label, lineno, column, target register)
+        let mut check_count = 0usize;
+        let mut oob_stubs = Vec::new();
+        // overflow checks: (label, lineno, column, address of the cell)
+        let mut overflow_count = 0usize;
+        let mut overflow_stubs = Vec::new();
+        // add and sub set the overflow flag for signed and the carry flag for unsigned cells
+        let jump_overflow = if Int::is_signed() { "jo  " } else { "jc  " };
 
         let bf_src_filename = format!("{}.asm", binary_file);
         let mut asm = File::create(&bf_src_filename)?;
@@ -132,16 +153,46 @@ br##"        bits 64
         extern getchar
         extern fflush
         extern mem
-        global bfmain
+")?;
+
+        if tape.edge == TapeEdge::Error {
+            asm.write_all(b"        extern bf_tape_error\n")?;
+        }
+
+        if trap_overflow {
+            asm.write_all(b"        extern bf_overflow_error\n")?;
+        }
+
+        if debug_dump {
+            asm.write_all(b"        extern dbg_at\n")?;
+        }
+
+        asm.write_all(
+b"        global bfmain
 bfmain:
         push rbp
         mov  rbp, rsp
         push r12
-        mov  qword  r12 , [rel mem]
+")?;
+
+        let int_size = std::mem::size_of::<Int>() as isize;
+        if tape.is_bounded() {
+            // r13 and r14 hold the start and end of the tape
+            write!(asm,
+"        push r13
+        push r14
+        mov  qword  r12 , [rel mem] ; {}* ptr = (void*)mem;
+        mov  qword  r13 , r12
+        mov  qword  r14 , {:8}
+        add  qword  r14 , r12
+", Int::c_type(), tape.size as isize * int_size)?;
+        } else {
+            write!(asm,
+"        mov  qword  r12 , [rel mem]
         add  qword  r12 , {:8} ; {}* ptr = (void*)mem + PAGESIZE;
 ", pagesize, Int::c_type())?;
+        }
 
-        let int_size = std::mem::size_of::<Int>() as isize;
         let prefix = match int_size {
             1 => "byte ",
             2 => "word ",
@@ -158,195 +209,376 @@ bfmain:
         };
         nesting = 0;
         let mut pc = 0;
-        loop {
-            if let Some(instr) = code.get(pc) {
-                match *instr {
-                    Instruct::Move(off) => {
-                        if int_size == 1 && off == 1 {
-                            write!(asm, "        inc  qword  r12            ; {:nesting$}ptr ++;\n", "", nesting = nesting)?;
-                        } else if int_size == 1 && off == -1 {
-                            write!(asm, "        dec  qword  r12            ; {:nesting$}ptr --;\n", "", nesting = nesting)?;
-                        } else if off > 0 {
-                            let val = off * int_size;
-                            write!(asm, "        add  qword  r12 , {:8} ; {:nesting$}ptr  += {};\n", val, "", off, nesting = nesting)?;
-                        } else if off != 0 {
-                            let val = -off * int_size;
-                            write!(asm, "        sub  qword  r12 , {:8} ; {:nesting$}ptr  -= {};\n", val, "", -off, nesting = nesting)?;
-                        }
-                        pc += 1;
-                    },
-
-                    Instruct::Add(val) => {
-                        let v = val.as_i64();
-                        if v == 1 {
-                            write!(asm, "        inc  {} [r12]           ; {:nesting$}*ptr += 1;\n", prefix, "", nesting = nesting)?;
-                        } else if v == -1 {
-                            write!(asm, "        dec  {} [r12]           ; {:nesting$}*ptr -= 1;\n", prefix, "", nesting = nesting)?;
-                        } else if v > 0 {
-                            write!(asm, "        add  {} [r12], {:8} ; {:nesting$}*ptr += {};\n", prefix, v, "", v, nesting = nesting)?;
-                        } else if v != 0 {
-                            write!(asm, "        sub  {} [r12], {:8} ; {:nesting$}*ptr -= {};\n", prefix, -v, "", -v, nesting = nesting)?;
+        while let Some(instr) = code.get(pc) {
+            match *instr {
+                Instruct::Move(off) if tape.is_bounded() => {
+                    let off = if tape.edge == TapeEdge::Wrap {
+                        off.rem_euclid(tape.size as isize)
+                    } else { off };
+                    if off != 0 {
+                        check_count += 1;
+                        generate_move(&mut asm, off * int_size)?;
+                        if tape.edge == TapeEdge::Wrap {
+                            writeln!(asm, "        cmp  qword  r12 , r14      ; {:nesting$}ptr  += {};", "", off, nesting = nesting)?;
+                            writeln!(asm, "        jb   chk{}", check_count)?;
+                            writeln!(asm, "        sub  qword  r12 , r14")?;
+                            writeln!(asm, "        add  qword  r12 , r13")?;
+                            writeln!(asm, "chk{}:", check_count)?;
+                        } else {
+                            let span = code.span(pc);
+                            writeln!(asm, "        cmp  qword  r12 , r13      ; {:nesting$}ptr  += {};", "", off, nesting = nesting)?;
+                            writeln!(asm, "        jb   oob{}", check_count)?;
+                            writeln!(asm, "        cmp  qword  r12 , r14")?;
+                            writeln!(asm, "        jae  oob{}", check_count)?;
+                            oob_stubs.push((check_count, span.start.lineno, span.start.column, "r12"));
                         }
-                        pc += 1;
-                    },
-
-                    Instruct::Set(val) => {
-                        write!(asm, "        mov  {} [r12], {:8} ; {:nesting$}*ptr  = {};\n", prefix, val.as_i64(), "", val.as_i64(), nesting = nesting)?;
-                        pc += 1;
-                    },
-
-                    Instruct::AddTo(_) | Instruct::SubFrom(_) => {
-                        loop_count += 1;
-
-                        if let Some(val) = code.find_set_before(pc) {
-                            if val != Int::zero() {
-                                loop {
-                                    let instr = code.get(pc);
-                                    match instr {
-                                        Some(Instruct::AddTo(off)) | Some(Instruct::SubFrom(off)) => {
-                                            let dest = if *off > 0 {
-                                                format!("[r12+{}]", *off * int_size)
-                                            } else {
-                                                format!("[r12-{}]", -*off * int_size)
-                                            };
-                                            let padding = if dest.len() >= 14 { 0 } else { 14 - dest.len() };
-                                            if let Some(Instruct::AddTo(_)) = instr {
-                                                write!(asm, "        add  {} {}, {:padding$}; {:nesting$}ptr[{}] += *ptr;\n",
-                                                    prefix, dest, val.as_i64(), "", off, nesting = nesting, padding = padding)?;
-                                            } else {
-                                                write!(asm, "        sub  {} {}, {:padding$}; {:nesting$}ptr[{}] -= *ptr;\n",
-                                                    prefix, dest, val.as_i64(), "", off, nesting = nesting, padding = padding)?;
-                                            }
-                                            pc += 1;
-                                        },
-                                        _ => break
+                    }
+                    pc += 1;
+                },
+
+                Instruct::Move(off) => {
+                    if int_size == 1 && off == 1 {
+                        writeln!(asm, "        inc  qword  r12            ; {:nesting$}ptr ++;", "", nesting = nesting)?;
+                    } else if int_size == 1 && off == -1 {
+                        writeln!(asm, "        dec  qword  r12            ; {:nesting$}ptr --;", "", nesting = nesting)?;
+                    } else if off > 0 {
+                        let val = off * int_size;
+                        writeln!(asm, "        add  qword  r12 , {:8} ; {:nesting$}ptr  += {};", val, "", off, nesting = nesting)?;
+                    } else if off != 0 {
+                        let val = -off * int_size;
+                        writeln!(asm, "        sub  qword  r12 , {:8} ; {:nesting$}ptr  -= {};", val, "", -off, nesting = nesting)?;
+                    }
+                    pc += 1;
+                },
+
+                Instruct::Add(val) if trap_overflow => {
+                    // inc and dec don't set the carry flag, so always use add and sub
+                    let (op, sign, delta) = if val < 0 { ("sub", "-", val.unsigned_abs()) } else { ("add", "+", val as usize) };
+                    let bits = int_size as u32 * 8;
+                    if delta != 0 {
+                        let span = code.span(pc);
+                        overflow_count += 1;
+                        overflow_stubs.push((overflow_count, span.start.lineno, span.start.column, "r12".to_string()));
+                        if bits < 64 && delta > (1usize << bits) - 1 {
+                            // no cell value survives this
+                            writeln!(asm, "        jmp  ovf{:<19} ; {:nesting$}*ptr {}= {};", overflow_count, "", sign, delta, nesting = nesting)?;
+                        } else {
+                            // largest immediate that keeps the meaning of the flags
+                            let limit = if int_size == 8 {
+                                i32::MAX as usize
+                            } else if Int::is_signed() {
+                                (1usize << (bits - 1)) - 1
+                            } else {
+                                (1usize << bits) - 1
+                            };
+                            if delta > limit {
+                                if int_size == 8 && Int::is_signed() && delta > i64::MAX as usize {
+                                    // only isize::MIN gets here, as an immediate it is -2^63
+                                    // and the overflow flag would be inverted, but x - 2^63
+                                    // overflows exactly if x < 0
+                                    writeln!(asm, "        cmp  qword [r12], 0")?;
+                                    writeln!(asm, "        jl   ovf{}", overflow_count)?;
+                                    writeln!(asm, "        mov  rax, {}", delta)?;
+                                    writeln!(asm, "        {}  qword [r12], rax    ; {:nesting$}*ptr {}= {};", op, "", sign, delta, nesting = nesting)?;
+                                } else if int_size == 8 {
+                                    writeln!(asm, "        mov  rax, {}", delta)?;
+                                    writeln!(asm, "        {}  qword [r12], rax    ; {:nesting$}*ptr {}= {};", op, "", sign, delta, nesting = nesting)?;
+                                    writeln!(asm, "        {} ovf{}", jump_overflow, overflow_count)?;
+                                } else {
+                                    // split into steps of the same sign, the cell only
+                                    // passes values between the old and the new value
+                                    let mut rest = delta;
+                                    while rest > 0 {
+                                        let step = std::cmp::min(rest, limit);
+                                        writeln!(asm, "        {}  {} [r12], {:8} ; {:nesting$}*ptr {}= {};", op, prefix, step, "", sign, step, nesting = nesting)?;
+                                        writeln!(asm, "        {} ovf{}", jump_overflow, overflow_count)?;
+                                        rest -= step;
                                     }
                                 }
                             } else {
-                                while let Some(Instruct::AddTo(_)) | Some(Instruct::SubFrom(_)) = code.get(pc) {
-                                    pc += 1;
-                                }
+                                writeln!(asm, "        {}  {} [r12], {:8} ; {:nesting$}*ptr {}= {};", op, prefix, delta, "", sign, delta, nesting = nesting)?;
+                                writeln!(asm, "        {} ovf{}", jump_overflow, overflow_count)?;
                             }
+                        }
+                    }
+                    pc += 1;
+                },
+
+                Instruct::Add(val) => {
+                    let v = Int::wrap_delta(val);
+                    if v == 1 {
+                        writeln!(asm, "        inc  {} [r12]           ; {:nesting$}*ptr += 1;", prefix, "", nesting = nesting)?;
+                    } else if v == -1 {
+                        writeln!(asm, "        dec  {} [r12]           ; {:nesting$}*ptr -= 1;", prefix, "", nesting = nesting)?;
+                    } else if v > 0 {
+                        writeln!(asm, "        add  {} [r12], {:8} ; {:nesting$}*ptr += {};", prefix, v, "", v, nesting = nesting)?;
+                    } else if v != 0 {
+                        writeln!(asm, "        sub  {} [r12], {:8} ; {:nesting$}*ptr -= {};", prefix, -v, "", -v, nesting = nesting)?;
+                    }
+                    pc += 1;
+                },
+
+                Instruct::Set(ref val) => {
+                    writeln!(asm, "        mov  {} [r12], {:8} ; {:nesting$}*ptr  = {:?};", prefix, immediate(val), "", val, nesting = nesting)?;
+                    pc += 1;
+                },
+
+                Instruct::AddTo(_) | Instruct::SubFrom(_) if tape.is_bounded() => {
+                    // Go through rdx so the target can be checked against the tape edges.
+                    // Like in the interpreter the target is only touched if *ptr != 0.
+                    loop_count += 1;
+                    let known = code.find_set_before(pc);
+                    if known == Some(Int::zero()) {
+                        while let Some(Instruct::AddTo(_)) | Some(Instruct::SubFrom(_)) = code.get(pc) {
+                            pc += 1;
+                        }
+                        continue;
+                    }
+
+                    let value = if let Some(ref val) = known {
+                        format!("{}", immediate(val))
+                    } else {
+                        writeln!(asm, "        mov         {:3} , [r12]", reg)?;
+                        writeln!(asm, "        cmp         {:3} , 0", reg)?;
+                        writeln!(asm, "        je   addto{}", loop_count)?;
+                        reg.to_string()
+                    };
+
+                    loop {
+                        let instr = code.get(pc);
+                        let off = match instr {
+                            Some(Instruct::AddTo(off)) | Some(Instruct::SubFrom(off)) => *off,
+                            _ => break
+                        };
+                        let target_off = if tape.edge == TapeEdge::Wrap {
+                            off.rem_euclid(tape.size as isize)
+                        } else { off };
+                        check_count += 1;
+                        if target_off < 0 {
+                            writeln!(asm, "        lea  rdx, [r12-{}]", -target_off * int_size)?;
+                        } else {
+                            writeln!(asm, "        lea  rdx, [r12+{}]", target_off * int_size)?;
+                        }
+                        if tape.edge == TapeEdge::Wrap {
+                            writeln!(asm, "        cmp  rdx, r14")?;
+                            writeln!(asm, "        jb   chk{}", check_count)?;
+                            writeln!(asm, "        sub  rdx, r14")?;
+                            writeln!(asm, "        add  rdx, r13")?;
+                            writeln!(asm, "chk{}:", check_count)?;
                         } else {
-                            write!(asm, "        mov         {:3} , [r12]\n", reg)?;
-                            let mut current_off = 0isize;
+                            let span = code.span(pc);
+                            writeln!(asm, "        cmp  rdx, r13")?;
+                            writeln!(asm, "        jb   oob{}", check_count)?;
+                            writeln!(asm, "        cmp  rdx, r14")?;
+                            writeln!(asm, "        jae  oob{}", check_count)?;
+                            oob_stubs.push((check_count, span.start.lineno, span.start.column, "rdx"));
+                        }
+                        let padding = if value.len() >= 14 { 0 } else { 14 - value.len() };
+                        if let Some(Instruct::AddTo(_)) = instr {
+                            writeln!(asm, "        add  {} [rdx], {:padding$}; {:nesting$}ptr[{}] += *ptr;",
+                                prefix, value, "", off, nesting = nesting, padding = padding)?;
+                        } else {
+                            writeln!(asm, "        sub  {} [rdx], {:padding$}; {:nesting$}ptr[{}] -= *ptr;",
+                                prefix, value, "", off, nesting = nesting, padding = padding)?;
+                        }
+                        if trap_overflow {
+                            let span = code.span(pc);
+                            overflow_count += 1;
+                            writeln!(asm, "        {} ovf{}", jump_overflow, overflow_count)?;
+                            overflow_stubs.push((overflow_count, span.start.lineno, span.start.column, "rdx".to_string()));
+                        }
+                        pc += 1;
+                    }
+
+                    if known.is_none() {
+                        writeln!(asm, "addto{}:", loop_count)?;
+                    }
+                },
+
+                Instruct::AddTo(_) | Instruct::SubFrom(_) => {
+                    loop_count += 1;
+
+                    if let Some(val) = code.find_set_before(pc) {
+                        if val != Int::zero() {
                             loop {
                                 let instr = code.get(pc);
                                 match instr {
                                     Some(Instruct::AddTo(off)) | Some(Instruct::SubFrom(off)) => {
-                                        if current_off != *off {
-                                            generate_move(&mut asm, *off - current_off)?;
-                                        }
+                                        let addr = if *off > 0 {
+                                            format!("r12+{}", *off * int_size)
+                                        } else {
+                                            format!("r12-{}", -*off * int_size)
+                                        };
+                                        let dest = format!("[{}]", addr);
+                                        let padding = if dest.len() >= 14 { 0 } else { 14 - dest.len() };
                                         if let Some(Instruct::AddTo(_)) = instr {
-                                            write!(asm, "        add  {} [r12], {:9}; {:nesting$}ptr[{}] += *ptr;\n",
-                                                prefix, reg, "", *off, nesting = nesting)?;
+                                            writeln!(asm, "        add  {} {}, {:padding$}; {:nesting$}ptr[{}] += *ptr;",
+                                                prefix, dest, immediate(&val), "", off, nesting = nesting, padding = padding)?;
                                         } else {
-                                            write!(asm, "        sub  {} [r12], {:9}; {:nesting$}ptr[{}] -= *ptr;\n",
-                                                prefix, reg, "", *off, nesting = nesting)?;
+                                            writeln!(asm, "        sub  {} {}, {:padding$}; {:nesting$}ptr[{}] -= *ptr;",
+                                                prefix, dest, immediate(&val), "", off, nesting = nesting, padding = padding)?;
+                                        }
+                                        if trap_overflow {
+                                            let span = code.span(pc);
+                                            overflow_count += 1;
+                                            writeln!(asm, "        {} ovf{}", jump_overflow, overflow_count)?;
+                                            overflow_stubs.push((overflow_count, span.start.lineno, span.start.column, addr));
                                         }
-                                        current_off = *off;
                                         pc += 1;
                                     },
                                     _ => break
                                 }
                             }
-                            let mut target_off = -current_off;
-
-                            // if there is a move now, we can do this both at once
-                            if let Some(Instruct::Move(off)) = code.get(pc) {
-                                target_off += *off;
+                        } else {
+                            while let Some(Instruct::AddTo(_)) | Some(Instruct::SubFrom(_)) = code.get(pc) {
                                 pc += 1;
-                                // TODO: this eats the comment of this move
                             }
-                            generate_move(&mut asm, target_off)?;
                         }
-                    },
-
-                    Instruct::Read => {
-                        write!(asm, "        mov  rdi, [rel stdout]\n")?;
-                        write!(asm, "        call fflush                ; {:nesting$}fflush(stdout);\n", "", nesting = nesting)?;
-
-                        write!(asm, "        call getchar\n")?;
-                        write!(asm, "        mov  {} [r12], {:7}      ; {:nesting$}*ptr = getchar();\n", prefix, reg, "", nesting = nesting)?;
-                        pc += 1;
-                    },
-
-                    Instruct::Write => {
-                        write!(asm, "        mov  edi,  [r12]\n")?;
-                        write!(asm, "        call putchar               ; {:nesting$}putchar(*ptr)\n", "", nesting = nesting)?;
-                        pc += 1;
-                    },
-
-                    Instruct::LoopStart(pc_loop_end) => {
-                        loop_count += 1;
-
-                        if let Some(val) = code.find_set_before(pc) {
-                            if val == Int::zero() {
-                                pc = pc_loop_end;
-                            } else {
-                                loop_stack.push(loop_count);
-                                write!(asm, "start{}:                           ; {:nesting$}do {{\n", loop_count, "", nesting = nesting)?;
-                                nesting += 4;
-                                pc += 1;
+                    } else {
+                        writeln!(asm, "        mov         {:3} , [r12]", reg)?;
+                        let mut current_off = 0isize;
+                        loop {
+                            let instr = code.get(pc);
+                            match instr {
+                                Some(Instruct::AddTo(off)) | Some(Instruct::SubFrom(off)) => {
+                                    if current_off != *off {
+                                        generate_move(&mut asm, (*off - current_off) * int_size)?;
+                                    }
+                                    if let Some(Instruct::AddTo(_)) = instr {
+                                        writeln!(asm, "        add  {} [r12], {:9}; {:nesting$}ptr[{}] += *ptr;",
+                                            prefix, reg, "", *off, nesting = nesting)?;
+                                    } else {
+                                        writeln!(asm, "        sub  {} [r12], {:9}; {:nesting$}ptr[{}] -= *ptr;",
+                                            prefix, reg, "", *off, nesting = nesting)?;
+                                    }
+                                    if trap_overflow {
+                                        let span = code.span(pc);
+                                        overflow_count += 1;
+                                        writeln!(asm, "        {} ovf{}", jump_overflow, overflow_count)?;
+                                        overflow_stubs.push((overflow_count, span.start.lineno, span.start.column, "r12".to_string()));
+                                    }
+                                    current_off = *off;
+                                    pc += 1;
+                                },
+                                _ => break
                             }
-                        } else {
-                            loop_stack.push(loop_count);
-                            let stmt = if let Some(Instruct::Set(val2)) = code.get(pc_loop_end - 2) {
-                                if *val2 == Int::zero() { "if" } else { "while" }
-                            } else { "while" };
+                        }
+                        let mut target_off = -current_off;
 
-                            write!(asm, "        cmp  {} [r12],        0 ; {:nesting$}{} (*ptr) {{\n", prefix, "", stmt, nesting = nesting)?;
-                            write!(asm, "        je   end{}\n", loop_count)?;
-                            write!(asm, "start{}:\n", loop_count)?;
-                            nesting += 4;
+                        // if there is a move now, we can do this both at once
+                        if let Some(Instruct::Move(off)) = code.get(pc) {
+                            target_off += *off;
                             pc += 1;
+                            // TODO: this eats the comment of this move
                         }
-                    },
-
-                    Instruct::LoopEnd(pc_start) => {
-                        nesting -= 4;
-                        let loop_id = loop_stack.pop().unwrap();
-                        let stmt = if code.find_set_before(pc_start).is_some() {
-                            "} while (*ptr);"
-                        } else { "}" };
-
-                        if let Some(val) = code.find_set_before(pc) {
-                            if val == Int::zero() {
-                                write!(asm, "                                   ; {:nesting$}{}\n", "", stmt, nesting = nesting)?;
-                            } else {
-                                // This would be an infinite loop, right?
-                                write!(asm, "        jmp  {:7} ; {:nesting$}{}\n", format!("start{}", loop_id), "", stmt, nesting = nesting)?;
-                            }
+                        generate_move(&mut asm, target_off * int_size)?;
+                    }
+                },
+
+                Instruct::Read => {
+                    writeln!(asm, "        mov  rdi, [rel stdout]")?;
+                    writeln!(asm, "        call fflush                ; {:nesting$}fflush(stdout);", "", nesting = nesting)?;
+
+                    writeln!(asm, "        call getchar")?;
+                    if int_size == 8 {
+                        // EOF (-1) has to become -1 in all 64 bits
+                        writeln!(asm, "        movsxd rax, eax")?;
+                    }
+                    writeln!(asm, "        mov  {} [r12], {:7}      ; {:nesting$}*ptr = getchar();", prefix, reg, "", nesting = nesting)?;
+                    pc += 1;
+                },
+
+                Instruct::Write => {
+                    writeln!(asm, "        mov  edi,  [r12]")?;
+                    writeln!(asm, "        call putchar               ; {:nesting$}putchar(*ptr)", "", nesting = nesting)?;
+                    pc += 1;
+                },
+
+                Instruct::LoopStart(pc_loop_end) => {
+                    loop_count += 1;
+
+                    if let Some(val) = code.find_set_before(pc) {
+                        if val == Int::zero() {
+                            pc = pc_loop_end;
                         } else {
-                            write!(asm, "        cmp  {} [r12],        0 ; {:nesting$}{}\n", prefix, "", stmt, nesting = nesting)?;
-                            write!(asm, "        jne  start{}\n", loop_id)?;
+                            loop_stack.push(loop_count);
+                            writeln!(asm, "start{}:                           ; {:nesting$}do {{", loop_count, "", nesting = nesting)?;
+                            nesting += 4;
+                            pc += 1;
                         }
-
-                        write!(asm, "end{}:\n", loop_id)?;
+                    } else {
+                        loop_stack.push(loop_count);
+                        let stmt = if let Some(Instruct::Set(val2)) = code.get(pc_loop_end - 2) {
+                            if *val2 == Int::zero() { "if" } else { "while" }
+                        } else { "while" };
+
+                        writeln!(asm, "        cmp  {} [r12],        0 ; {:nesting$}{} (*ptr) {{", prefix, "", stmt, nesting = nesting)?;
+                        writeln!(asm, "        je   end{}", loop_count)?;
+                        writeln!(asm, "start{}:", loop_count)?;
+                        nesting += 4;
                         pc += 1;
-                    },
-
-                    Instruct::WriteStr(ref data) => {
-                        if data.len() == 1 {
-                            write!(asm, "        mov  edi, {}\n", data[0])?;
-                            write!(asm, "        call putchar               ; {:nesting$}putchar({})\n", "", data[0], nesting = nesting)?;
-                        } else if data.len() > 0 {
-                            let msg_id = str_table.get(data).unwrap();
-
-                            write!(asm, "        mov  rcx, [rel stdout]\n")?;
-                            write!(asm, "        mov  edx, 1\n")?;
-                            write!(asm, "        mov  esi, {}\n", data.len())?;
-                            write!(asm, "        mov  edi, msg{}\n", msg_id)?;
-                            write!(asm, "        call fwrite                ; {:nesting$}fwrite(msg{}, {}, 1, stdout);\n", "", msg_id, data.len(), nesting = nesting)?;
+                    }
+                },
+
+                Instruct::LoopEnd(pc_start) => {
+                    nesting -= 4;
+                    let loop_id = loop_stack.pop().unwrap();
+                    let stmt = if code.find_set_before(pc_start).is_some() {
+                        "} while (*ptr);"
+                    } else { "}" };
+
+                    if let Some(val) = code.find_set_before(pc) {
+                        if val == Int::zero() {
+                            writeln!(asm, "                                   ; {:nesting$}{}", "", stmt, nesting = nesting)?;
+                        } else {
+                            // This would be an infinite loop, right?
+                            writeln!(asm, "        jmp  {:7} ; {:nesting$}{}", format!("start{}", loop_id), "", stmt, nesting = nesting)?;
                         }
-                        pc += 1;
-                    },
-                }
-            } else {
-                break;
+                    } else {
+                        writeln!(asm, "        cmp  {} [r12],        0 ; {:nesting$}{}", prefix, "", stmt, nesting = nesting)?;
+                        writeln!(asm, "        jne  start{}", loop_id)?;
+                    }
+
+                    writeln!(asm, "end{}:", loop_id)?;
+                    pc += 1;
+                },
+
+                Instruct::WriteStr(ref data) => {
+                    if data.len() == 1 {
+                        writeln!(asm, "        mov  edi, {}", data[0])?;
+                        writeln!(asm, "        call putchar               ; {:nesting$}putchar({})", "", data[0], nesting = nesting)?;
+                    } else if !data.is_empty() {
+                        let msg_id = str_table.get(data).unwrap();
+
+                        writeln!(asm, "        mov  rcx, [rel stdout]")?;
+                        writeln!(asm, "        mov  edx, 1")?;
+                        writeln!(asm, "        mov  esi, {}", data.len())?;
+                        writeln!(asm, "        mov  edi, msg{}", msg_id)?;
+                        writeln!(asm, "        call fwrite                ; {:nesting$}fwrite(msg{}, {}, 1, stdout);", "", msg_id, data.len(), nesting = nesting)?;
+                    }
+                    pc += 1;
+                },
+
+                Instruct::DebugDump => {
+                    writeln!(asm, "        mov  rdi, r12")?;
+                    writeln!(asm, "        push rbp")?;
+                    writeln!(asm, "        mov  rbp, rsp")?;
+                    writeln!(asm, "        and  rsp, -16")?;
+                    writeln!(asm, "        call dbg_at                ; {:nesting$}dbg_at(ptr);", "", nesting = nesting)?;
+                    writeln!(asm, "        mov  rsp, rbp")?;
+                    writeln!(asm, "        pop  rbp")?;
+                    pc += 1;
+                },
             }
         }
 
+        if tape.is_bounded() {
+            asm.write_all(
+b"        pop  r14
+        pop  r13
+")?;
+        }
+
         asm.write_all(
 b"        pop  r12
         mov  rsp, rbp
@@ -354,6 +586,38 @@ b"        pop  r12
         ret
 ")?;
 
+        if !oob_stubs.is_empty() {
+            for (label, lineno, column, target) in oob_stubs {
+                writeln!(asm, "oob{}:", label)?;
+                writeln!(asm, "        mov  rdi, {}", lineno)?;
+                writeln!(asm, "        mov  rsi, {}", column)?;
+                if target != "rdx" {
+                    writeln!(asm, "        mov  rdx, {}", target)?;
+                }
+                asm.write_all(b"        jmp  tape_error\n")?;
+            }
+            asm.write_all(
+b"tape_error:
+        and  rsp, -16
+        call bf_tape_error              ; does not return
+")?;
+        }
+
+        if !overflow_stubs.is_empty() {
+            for (label, lineno, column, addr) in overflow_stubs {
+                writeln!(asm, "ovf{}:", label)?;
+                writeln!(asm, "        mov  rdi, {}", lineno)?;
+                writeln!(asm, "        mov  rsi, {}", column)?;
+                writeln!(asm, "        lea  rdx, [{}]", addr)?;
+                asm.write_all(b"        jmp  overflow_error\n")?;
+            }
+            asm.write_all(
+b"overflow_error:
+        and  rsp, -16
+        call bf_overflow_error          ; does not return
+")?;
+        }
+
     } else {
         let c_filename = format!("{}.c", binary_file);
         let mut out = File::create(&c_filename)?;
@@ -365,7 +629,7 @@ int main() {{
 
         for instr in code.iter() {
             if let Instruct::WriteStr(data) = instr {
-                if data.len() > 0 {
+                if !data.is_empty() {
                     generate_c_write_str(&mut out, data, nesting)?;
                     need_flush = data[data.len() - 1] != b'\n';
                 }
@@ -381,24 +645,29 @@ int main() {{
         filenames.push(c_filename);
     }
 
-    return Ok(filenames);
+    Ok(filenames)
 }
 
-fn generate_move(asm: &mut Write, off: isize) -> std::io::Result<()> {
+// value of a fixed width cell as an assembler immediate
+fn immediate<Int: BrainfuckInteger>(val: &Int) -> isize {
+    val.to_delta().expect("cell value doesn't fit into an immediate")
+}
+
+fn generate_move(asm: &mut dyn Write, off: isize) -> std::io::Result<()> {
     if off == 1 {
-        write!(asm, "        inc  qword r12\n")
+        writeln!(asm, "        inc  qword r12")
     } else if off == -1 {
-        write!(asm, "        dec  qword r12\n")
+        writeln!(asm, "        dec  qword r12")
     } else if off < 0 {
-        write!(asm, "        sub  qword r12, {}\n", -off)
+        writeln!(asm, "        sub  qword r12, {}", -off)
     } else if off > 0 {
-        write!(asm, "        add  qword r12, {}\n", off)
+        writeln!(asm, "        add  qword r12, {}", off)
     } else {
         Ok(())
     }
 }
 
-pub fn compile_c(source_file: &str, object_file: &str, debug: bool, optlevel: u32) -> std::io::Result<()> {
+pub fn compile_c(source_file: &str, object_file: &str, debug: bool, optlevel: u32) -> std::result::Result<(), Error> {
     let cc = if let Ok(cc) = std::env::var("CC") {
         cc
     } else {
@@ -426,20 +695,16 @@ pub fn compile_c(source_file: &str, object_file: &str, debug: bool, optlevel: u3
         .status()?;
 
     if !status.success() {
-        let message = if let Some(code) = status.code() {
-            format!("{} exited with status {}", cc, code)
-        } else {
-            format!("{} terminated by signal", cc)
-        };
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            message));
+        return Err(Error::ToolFailed {
+            tool:   cc,
+            status: status.code(),
+        });
     }
 
-    return Ok(());
+    Ok(())
 }
 
-pub fn assemble(source_file: &str, object_file: &str, debug: bool, optlevel: u32) -> std::io::Result<()> {
+pub fn assemble(source_file: &str, object_file: &str, debug: bool, optlevel: u32) -> std::result::Result<(), Error> {
     let asm = if let Ok(asm) = std::env::var("ASM") {
         asm
     } else {
@@ -466,20 +731,16 @@ pub fn assemble(source_file: &str, object_file: &str, debug: bool, optlevel: u32
         .status()?;
 
     if !status.success() {
-        let message = if let Some(code) = status.code() {
-            format!("{} exited with status {}", asm, code)
-        } else {
-            format!("{} terminated by signal", asm)
-        };
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            message));
+        return Err(Error::ToolFailed {
+            tool:   asm,
+            status: status.code(),
+        });
     }
 
-    return Ok(());
+    Ok(())
 }
 
-pub fn link(obj_files: impl IntoIterator<Item=impl AsRef<OsStr>>, binary_file: &str, debug: bool, optlevel: u32) -> std::io::Result<()> {
+pub fn link(obj_files: impl IntoIterator<Item=impl AsRef<OsStr>>, binary_file: &str, debug: bool, optlevel: u32) -> std::result::Result<(), Error> {
     let ld = if let Ok(ld) = std::env::var("LD") {
         ld
     } else if let Ok(cc) = std::env::var("CC") {
@@ -504,21 +765,18 @@ pub fn link(obj_files: impl IntoIterator<Item=impl AsRef<OsStr>>, binary_file: &
         .status()?;
 
     if !status.success() {
-        let message = if let Some(code) = status.code() {
-            format!("{} exited with status {}", ld, code)
-        } else {
-            format!("{} terminated by signal", ld)
-        };
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            message));
+        return Err(Error::ToolFailed {
+            tool:   ld,
+            status: status.code(),
+        });
     }
 
-    return Ok(());
+    Ok(())
 }
 
-pub fn compile<Int: BrainfuckInteger + Signed>(code: &Brainfuck<Int>, binary_file: &str, debug: bool, optlevel: u32, keep_source: bool) -> std::io::Result<()> {
-    let filenames = generate(code, &binary_file)?;
+/// Compiles the program to the executable binary_file using gcc and nasm.
+pub fn compile<Int: BrainfuckInteger>(code: &Brainfuck<Int>, binary_file: &str, tape: &TapeOptions, trap_overflow: bool, debug: bool, optlevel: u32, keep_source: bool) -> std::result::Result<(), Error> {
+    let filenames = generate(code, binary_file, tape, trap_overflow)?;
     let mut obj_files = Vec::new();
 
     for filename in &filenames {
@@ -527,21 +785,21 @@ pub fn compile<Int: BrainfuckInteger + Signed>(code: &Brainfuck<Int>, binary_fil
             obj_file.truncate(obj_file.len()-2);
             obj_file.push_str(".o");
 
-            compile_c(&filename, &obj_file, debug, optlevel)?;
+            compile_c(filename, &obj_file, debug, optlevel)?;
             obj_files.push(obj_file);
         } else if filename.ends_with(".asm") {
             let mut obj_file = filename.to_owned();
             obj_file.truncate(obj_file.len()-4);
             obj_file.push_str(".o");
 
-            assemble(&filename, &obj_file, debug, optlevel)?;
+            assemble(filename, &obj_file, debug, optlevel)?;
             obj_files.push(obj_file);
         } else {
             panic!("unhandeled file extension: {}", filename);
         }
     }
 
-    link(&obj_files, &binary_file, debug, optlevel)?;
+    link(&obj_files, binary_file, debug, optlevel)?;
     
     if !keep_source {
         for filename in &filenames {
@@ -552,5 +810,5 @@ pub fn compile<Int: BrainfuckInteger + Signed>(code: &Brainfuck<Int>, binary_fil
     for filename in &obj_files {
         std::fs::remove_file(filename)?;
     }
-    return Ok(());
+    Ok(())
 }
\ No newline at end of file
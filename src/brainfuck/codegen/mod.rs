@@ -1,4 +1,9 @@
+//! Compilation of programs to native code.
+
 mod generate_asm_str;
 mod generate_c_write_str;
 mod generate_c_runtime;
+mod generate_c_bignum_runtime;
+mod c_bignum;
+pub(crate) mod generate_bf_const;
 pub mod linux_x86_64;
\ No newline at end of file
@@ -0,0 +1,634 @@
+//! The commands of the `bfc` tool: loading a source or bytecode file,
+//! optimizing it with the requested checks and compiling, running, debugging,
+//! linting or formatting it. The command line tool only turns its arguments
+//! into [`Settings`] and a [`Command`] and calls [`run`].
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use super::{Brainfuck, BrainfuckInteger, BigInt, Cst, Error, ParseOptions, InputFormat, ExecOptions, TapeOptions, TapeEdge};
+use super::tape::{DEFAULT_TAPE_SIZE, MAX_TAPE_SIZE};
+use super::optimize::Options;
+use super::trace::{Tracer, TraceOptions};
+use super::profile::Profile;
+use super::coverage::{Coverage, FileCoverage};
+use super::verify::{self, VerifyOptions};
+use super::{bytecode, codegen, debugger, dot, fmt, json, lint, ook};
+
+pub use super::trace::TraceFormat;
+pub use super::lint::Lint;
+
+const TAPE_SIZE_EXPECTED: &str = "a positive integer of at most 1152921504606846975 (2^60 - 1)";
+
+/// Type of the memory cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellSize {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    Big,
+}
+
+impl std::str::FromStr for CellSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "8"   => Ok(CellSize::I8),
+            "16"  => Ok(CellSize::I16),
+            "32"  => Ok(CellSize::I32),
+            "64"  => Ok(CellSize::I64),
+            "8u"  => Ok(CellSize::U8),
+            "16u" => Ok(CellSize::U16),
+            "32u" => Ok(CellSize::U32),
+            "64u" => Ok(CellSize::U64),
+            "big" => Ok(CellSize::Big),
+            _     => Err(format!("illegal cell size: {}", s)),
+        }
+    }
+}
+
+/// In the syntax of `--cell-size`, e.g. `8u` or `big`.
+impl std::fmt::Display for CellSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            CellSize::I8  => "8",
+            CellSize::I16 => "16",
+            CellSize::I32 => "32",
+            CellSize::I64 => "64",
+            CellSize::U8  => "8u",
+            CellSize::U16 => "16u",
+            CellSize::U32 => "32u",
+            CellSize::U64 => "64u",
+            CellSize::Big => "big",
+        })
+    }
+}
+
+// calls a function that is generic over the cell type with the type of a
+// CellSize
+macro_rules! with_cell_type {
+    ($cell_size:expr, $f:ident($($arg:expr),*)) => {
+        match $cell_size {
+            CellSize::I8  => $f::< i8>($($arg),*),
+            CellSize::I16 => $f::<i16>($($arg),*),
+            CellSize::I32 => $f::<i32>($($arg),*),
+            CellSize::I64 => $f::<i64>($($arg),*),
+            CellSize::U8  => $f::< u8>($($arg),*),
+            CellSize::U16 => $f::<u16>($($arg),*),
+            CellSize::U32 => $f::<u32>($($arg),*),
+            CellSize::U64 => $f::<u64>($($arg),*),
+            CellSize::Big => $f::<BigInt>($($arg),*),
+        }
+    };
+}
+
+/// What `compile` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// assembler and C runtime
+    Source,
+    Binary,
+    Brainfuck,
+    /// the intermediate representation as text
+    Debug,
+    Ook,
+    Bytecode,
+    Json,
+    /// Graphviz control-flow graph
+    Dot,
+}
+
+impl OutputFormat {
+    /// The file written when no output is given.
+    pub fn default_output(self) -> &'static str {
+        match self {
+            OutputFormat::Source    => "a.out",
+            OutputFormat::Binary    => "a.out",
+            OutputFormat::Brainfuck => "out.bf",
+            OutputFormat::Debug     => "out.txt",
+            OutputFormat::Ook       => "out.ook",
+            OutputFormat::Bytecode  => "out.bfb",
+            OutputFormat::Json      => "out.json",
+            OutputFormat::Dot       => "out.dot",
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "source"    => Ok(OutputFormat::Source),
+            "binary"    => Ok(OutputFormat::Binary),
+            "brainfuck" => Ok(OutputFormat::Brainfuck),
+            "debug"     => Ok(OutputFormat::Debug),
+            "ook"       => Ok(OutputFormat::Ook),
+            "bytecode"  => Ok(OutputFormat::Bytecode),
+            "json"      => Ok(OutputFormat::Json),
+            "dot"       => Ok(OutputFormat::Dot),
+            _           => Err(format!("unsupported format: {}", s)),
+        }
+    }
+}
+
+/// The options shared by all commands.
+#[derive(Clone, Default)]
+pub struct Settings {
+    /// source or bytecode file
+    pub input: String,
+    /// `None` is the cell size of a bytecode file or 32 bit
+    pub cell_size: Option<CellSize>,
+    /// `None` is the tape of a bytecode file or the default size
+    pub tape_size: Option<usize>,
+    /// `None` is the tape of a bytecode file or a growing tape
+    pub tape_edge: Option<TapeEdge>,
+    /// `None` is the setting of a bytecode file or off
+    pub trap_overflow: Option<bool>,
+    /// the tape and `trap_overflow` are taken from the fields above
+    pub optimizations: Options,
+    pub debug_char: Option<char>,
+    /// `None` is Ook! for `.ook` files and brainfuck otherwise
+    pub input_format: Option<InputFormat>,
+    /// check the structure of the program after every pass, debug builds
+    /// always do
+    pub verify_ir: bool,
+    /// run the program before and after every pass and compare
+    pub verify_passes: bool,
+    /// files whose content is used as input by `verify_passes`, besides a few
+    /// generated inputs
+    pub verify_inputs: Vec<String>,
+    /// instruction limit of each `verify_passes` run
+    pub verify_steps: Option<u64>,
+}
+
+/// A subcommand of the tool.
+pub enum Command {
+    Compile(Compile),
+    Exec(Exec),
+    Debug,
+    /// with `check` only report if the file isn't formatted, with `write`
+    /// rewrite it, otherwise print the formatted program
+    Fmt { check: bool, write: bool },
+    Lint { json: bool, allow: Vec<Lint> },
+}
+
+/// Compiles the program to a binary or writes it in another format.
+pub struct Compile {
+    pub format: OutputFormat,
+    /// `None` is [`OutputFormat::default_output`]
+    pub output: Option<String>,
+    /// keep the generated assembler and C files
+    pub keep_source: bool,
+    /// compile with debug information
+    pub debug: bool,
+    pub c_opt_level: u32,
+    /// draw the program before and after the first run of this pass instead
+    /// (`OutputFormat::Dot` only)
+    pub dot_pass: Option<String>,
+}
+
+/// Runs the program in the interpreter.
+pub struct Exec {
+    pub max_steps: Option<u64>,
+    pub max_tape: Option<usize>,
+    pub timeout: Option<Duration>,
+    /// file to write the trace to
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+    /// source range like `3:5-7:1` the trace is limited to
+    pub trace_range: Option<String>,
+    /// print the profile to stderr
+    pub profile: bool,
+    /// file to write the profile to as folded stacks
+    pub profile_folded: Option<String>,
+    /// lcov tracefile the coverage of the run is added to, turns off the
+    /// `constexpr`, `deadcode` and `skip` passes
+    pub coverage: Option<String>,
+}
+
+fn illegal_tape_size(value: String) -> Error {
+    Error::IllegalArgument { name: "tape-size", value, expected: TAPE_SIZE_EXPECTED }
+}
+
+/// Parses the value of `--tape-size`.
+pub fn parse_tape_size(value: &str) -> std::result::Result<usize, Error> {
+    match value.parse() {
+        Ok(size) if size > 0 && size <= MAX_TAPE_SIZE => Ok(size),
+        _ => Err(illegal_tape_size(value.to_string())),
+    }
+}
+
+/// Prints an error like the command line tool does, showing the source line
+/// of errors at a source position.
+pub fn print_error(err: &Error, input: &str, out: &mut dyn Write) -> std::io::Result<()> {
+    // only read for the snippet of errors with a source position
+    let source = if err.span().is_some() { std::fs::read_to_string(input).ok() } else { None };
+    let source = source.filter(|source| !bytecode::is_bytecode(source.as_bytes()));
+    err.print(out, input, source.as_deref())
+}
+
+/// Runs a command. The output goes to the files given in the command, stdout
+/// and stderr.
+pub fn run(settings: &Settings, command: &Command) -> std::result::Result<(), Error> {
+    let input = settings.input.as_str();
+    let mut options = settings.optimizations.clone();
+
+    if let Some(tape_size) = settings.tape_size {
+        if tape_size == 0 || tape_size > MAX_TAPE_SIZE {
+            return Err(illegal_tape_size(tape_size.to_string()));
+        }
+    }
+    let tape_size = settings.tape_size.unwrap_or(DEFAULT_TAPE_SIZE);
+    let tape_edge = settings.tape_edge.unwrap_or(TapeEdge::Grow);
+
+    let mut tape = TapeOptions::new(tape_size, tape_edge);
+    let mut cell_size = settings.cell_size.unwrap_or(CellSize::I32);
+    let mut trap_overflow = settings.trap_overflow.unwrap_or(false);
+
+    // a bytecode file was compiled for a cell size, tape and overflow
+    // checks, which are used unless they are given and contradict it
+    let header = read_bytecode_header(input)?;
+    if let Some(ref header) = header {
+        match command {
+            Command::Exec(_) | Command::Compile(_) => {},
+            _ => return Err(Error::IllegalArgument {
+                name:     "INPUT",
+                value:    input.to_string(),
+                expected: "a source file, only exec and compile take bytecode files",
+            }),
+        }
+        let bytecode_tape = header.options.tape;
+        if let Some(given) = settings.cell_size {
+            if given.to_string() != header.cell_size() {
+                return Err(Error::BytecodeMismatch { name: "cell-size", bytecode: header.cell_size(), given: given.to_string() });
+            }
+        }
+        if settings.tape_edge.is_some() && tape_edge != bytecode_tape.edge {
+            return Err(Error::BytecodeMismatch {
+                name:     "tape-edge",
+                bytecode: format!("{:?}", bytecode_tape.edge).to_lowercase(),
+                given:    format!("{:?}", tape_edge).to_lowercase(),
+            });
+        }
+        if settings.tape_size.is_some() && bytecode_tape.is_bounded() && tape_size != bytecode_tape.size {
+            return Err(Error::BytecodeMismatch { name: "tape-size", bytecode: bytecode_tape.size.to_string(), given: tape_size.to_string() });
+        }
+        if settings.trap_overflow.is_some() && trap_overflow != header.options.trap_overflow {
+            return Err(Error::BytecodeMismatch {
+                name:     "trap-overflow",
+                bytecode: if header.options.trap_overflow { "on" } else { "off" }.to_string(),
+                given:    if trap_overflow { "on" } else { "off" }.to_string(),
+            });
+        }
+        cell_size = header.cell_size().parse().map_err(|_| Error::IllegalCellSize(header.cell_size()))?;
+        tape = bytecode_tape;
+        trap_overflow = header.options.trap_overflow;
+    }
+    options.tape = tape;
+    options.trap_overflow = trap_overflow;
+
+    let format = settings.input_format.unwrap_or(
+        if input.to_lowercase().ends_with(".ook") { InputFormat::Ook } else { InputFormat::Brainfuck });
+    let parse_options = ParseOptions { debug_char: settings.debug_char, format };
+
+    let verify = if settings.verify_passes {
+        let mut inputs = Vec::new();
+        for file in &settings.verify_inputs {
+            inputs.push(std::fs::read(file)?);
+        }
+        let max_steps = settings.verify_steps.unwrap_or(verify::DEFAULT_MAX_STEPS);
+        let exec_options = ExecOptions { tape, trap_overflow, max_steps: Some(max_steps), ..ExecOptions::default() };
+        Some(VerifyOptions::new(inputs, exec_options))
+    } else {
+        None
+    };
+    let checks = Checks { ir: settings.verify_ir, passes: verify };
+
+    match command {
+        Command::Compile(compile_command) => {
+            with_cell_type!(cell_size, compile(input, &parse_options, options, compile_command, &checks))
+        },
+        Command::Exec(exec_command) => {
+            if exec_command.coverage.is_some() {
+                // these passes drop code that ran, which would then be
+                // reported as never executed
+                options.constexpr = false;
+                options.deadcode  = false;
+                options.skip      = false;
+            }
+            let exec_options = ExecOptions {
+                tape,
+                trap_overflow,
+                max_steps: exec_command.max_steps,
+                max_tape:  exec_command.max_tape,
+                timeout:   exec_command.timeout,
+            };
+            let instrument = if let Some(ref file) = exec_command.trace {
+                let range = match exec_command.trace_range {
+                    Some(ref range) => Some(super::trace::parse_range(range).map_err(|_| Error::IllegalArgument {
+                        name:     "trace-range",
+                        value:    range.to_string(),
+                        expected: "a source range like 3:5-7:1",
+                    })?),
+                    None => None,
+                };
+                Instrument::Trace(file, TraceOptions { format: exec_command.trace_format, range })
+            } else if exec_command.profile || exec_command.profile_folded.is_some() || exec_command.coverage.is_some() {
+                if exec_command.coverage.is_some() && header.is_some() {
+                    return Err(Error::IllegalArgument {
+                        name:     "INPUT",
+                        value:    input.to_string(),
+                        expected: "a source file, coverage is reported for the source",
+                    });
+                }
+                if exec_command.coverage.is_some() && format != InputFormat::Brainfuck {
+                    return Err(Error::IllegalArgument {
+                        name:     "input-format",
+                        value:    format!("{:?}", format).to_lowercase(),
+                        expected: "brainfuck, coverage is reported for brainfuck source",
+                    });
+                }
+                Instrument::Count {
+                    report:   exec_command.profile || exec_command.profile_folded.is_some(),
+                    folded:   exec_command.profile_folded.as_deref(),
+                    coverage: exec_command.coverage.as_deref(),
+                }
+            } else {
+                Instrument::None
+            };
+            with_cell_type!(cell_size, exec(input, &parse_options, options, &exec_options, instrument, &checks))
+        },
+        Command::Debug => {
+            let exec_options = ExecOptions { tape, trap_overflow, ..ExecOptions::default() };
+            with_cell_type!(cell_size, debug(input, &parse_options, options, &exec_options, &checks))
+        },
+        Command::Fmt { check, write } => {
+            if format != InputFormat::Brainfuck {
+                return Err(Error::IllegalArgument {
+                    name:     "input-format",
+                    value:    format!("{:?}", format).to_lowercase(),
+                    expected: "brainfuck, fmt only formats brainfuck source",
+                });
+            }
+            format_source(input, &parse_options, *check, *write)
+        },
+        Command::Lint { json, allow } => {
+            with_cell_type!(cell_size, lint_source(input, &parse_options, &tape, allow, *json))
+        },
+    }
+}
+
+// the header if input is a bytecode file
+fn read_bytecode_header(input: &str) -> std::result::Result<Option<bytecode::Header>, Error> {
+    let mut data = Vec::new();
+    std::fs::File::open(input)?.take(64).read_to_end(&mut data)?;
+    if !bytecode::is_bytecode(&data) {
+        return Ok(None);
+    }
+    bytecode::read_header(&data).map(Some)
+}
+
+// the program, its source (bytecode files have none) and the source hash
+fn load<Int: BrainfuckInteger>(input: &str, parse_options: &ParseOptions)
+        -> std::result::Result<(Brainfuck<Int>, Option<String>, u64), Error> {
+    let data = std::fs::read(input)?;
+    if bytecode::is_bytecode(&data) {
+        let (header, code) = bytecode::read::<Int>(&data)?;
+        return Ok((code, None, header.source_hash));
+    }
+    let hash = bytecode::source_hash(&data);
+    let source = String::from_utf8(data)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let code = Brainfuck::<Int>::from_str(&source, parse_options)?;
+    Ok((code, Some(source), hash))
+}
+
+// what is checked after every optimization pass
+struct Checks {
+    // --verify-ir, debug builds always check the structure
+    ir: bool,
+    // --verify-passes
+    passes: Option<VerifyOptions>,
+}
+
+// called with the name of a pass and the program before and after it
+type Inspect<'a, Int> = dyn FnMut(&str, &Brainfuck<Int>, &Brainfuck<Int>) + 'a;
+
+// Optimizes the program, calling inspect for every pass. The first pass that
+// fails the checks is reported.
+fn optimize<Int: BrainfuckInteger>(
+        code: &Brainfuck<Int>, options: Options, checks: &Checks,
+        inspect: &mut Inspect<Int>) -> std::result::Result<Brainfuck<Int>, Error> {
+    let mut broken = None;
+    let mut failure = None;
+    let code = code.optimize_inspect(options, &mut std::io::stdout(), &mut |name, before, after| {
+        inspect(name, before, after);
+        // later passes work on the broken program anyway
+        if broken.is_some() || failure.is_some() {
+            return;
+        }
+        // running a program with broken loops could go anywhere
+        if checks.ir || checks.passes.is_some() {
+            if let Err(Error::MalformedProgram { index, message, .. }) = after.verify() {
+                broken = Some(Error::MalformedProgram { pass: Some(name.to_string()), index, message });
+                return;
+            }
+        }
+        if let Some(ref verify) = checks.passes {
+            failure = verify::verify_pass(name, before, after, verify);
+        }
+    })?;
+    if let Some(err) = broken {
+        return Err(err);
+    }
+    if let Some(failure) = failure {
+        failure.write_report(&mut std::io::stderr())?;
+        return Err(Error::PassChangedBehavior { pass: failure.pass });
+    }
+    Ok(code)
+}
+
+fn compile<Int: BrainfuckInteger>(
+        input: &str, parse_options: &ParseOptions, options: Options, command: &Compile,
+        checks: &Checks) -> std::result::Result<(), Error> {
+    let (code, _, source_hash) = load::<Int>(input, parse_options)?;
+    let tape = options.tape;
+    let trap_overflow = options.trap_overflow;
+    let output = command.output.as_deref().unwrap_or(command.format.default_output());
+    let dot_pass = command.dot_pass.as_deref();
+    let mut header_options = options.clone();
+    if let Some(header) = read_bytecode_header(input)? {
+        // optimized again
+        let old = header.options;
+        header_options.fold      |= old.fold;
+        header_options.set       |= old.set;
+        header_options.add_to    |= old.add_to;
+        header_options.write     |= old.write;
+        header_options.deadcode  |= old.deadcode;
+        header_options.constexpr |= old.constexpr;
+        header_options.skip      |= old.skip;
+    }
+    let mut pass_code = None;
+    let code = optimize(&code, options, checks, &mut |name, before, after| {
+        if Some(name) == dot_pass && pass_code.is_none() {
+            pass_code = Some((before.clone(), after.clone()));
+        }
+    })?;
+
+    match command.format {
+        OutputFormat::Source    => {
+            codegen::linux_x86_64::generate(&code, output, &tape, trap_overflow)?;
+        },
+        OutputFormat::Binary    => codegen::linux_x86_64::compile(
+            &code, output, &tape, trap_overflow, command.debug, command.c_opt_level, command.keep_source)?,
+        OutputFormat::Brainfuck => {
+            let mut out = std::fs::File::create(output)?;
+            code.write_bf(&mut out)?;
+        },
+        OutputFormat::Debug     => {
+            let mut out = std::fs::File::create(output)?;
+            code.write_debug(&mut out)?;
+        },
+        OutputFormat::Ook       => {
+            let mut out = std::fs::File::create(output)?;
+            ook::write_ook(&code, &mut out)?;
+        },
+        OutputFormat::Bytecode  => {
+            let mut out = std::fs::File::create(output)?;
+            bytecode::write(&code, &header_options, source_hash, &mut out)?;
+        },
+        OutputFormat::Json      => {
+            let mut out = std::fs::File::create(output)?;
+            json::write_json(&code, &mut out)?;
+        },
+        OutputFormat::Dot       => {
+            match (dot_pass, pass_code) {
+                (Some(pass), Some((before, after))) => {
+                    let mut out = std::fs::File::create(output)?;
+                    dot::write_dot_pass(&before, &after, pass, &mut out)?;
+                },
+                (Some(pass), None) => return Err(Error::IllegalArgument {
+                    name:     "dot-pass",
+                    value:    pass.to_string(),
+                    expected: "an optimization enabled with --opt",
+                }),
+                (None, _) => {
+                    let mut out = std::fs::File::create(output)?;
+                    dot::write_dot(&code, &mut out)?;
+                },
+            }
+        },
+    }
+
+    Ok(())
+}
+
+// what to record while executing a program
+enum Instrument<'a> {
+    None,
+    Trace(&'a str, TraceOptions),
+    // execution counts for the profile and coverage outputs
+    Count { report: bool, folded: Option<&'a str>, coverage: Option<&'a str> },
+}
+
+fn exec<Int: BrainfuckInteger>(
+        input: &str, parse_options: &ParseOptions, options: Options, exec_options: &ExecOptions, instrument: Instrument,
+        checks: &Checks) -> std::result::Result<(), Error> {
+    let (code, source, _) = load::<Int>(input, parse_options)?;
+    let code = optimize(&code, options, checks, &mut |_, _, _| {})?;
+    match instrument {
+        Instrument::None => {
+            code.exec(exec_options)?;
+        },
+        Instrument::Trace(file, trace_options) => {
+            let out = std::io::BufWriter::new(std::fs::File::create(file)?);
+            let mut tracer = Tracer::<_, Int>::new(out, trace_options)?;
+            let res = code.exec_trace(exec_options, &mut tracer);
+            // keep the trace up to the point of failure
+            tracer.finish()?;
+            res?;
+        },
+        Instrument::Count { report, folded, coverage } => {
+            let mut profile = Profile::new(&code);
+            let res = code.exec_profile(exec_options, &mut profile);
+            if report {
+                profile.write_report(&code, &mut std::io::stderr(), 20)?;
+            }
+            if let Some(file) = folded {
+                let mut out = std::io::BufWriter::new(std::fs::File::create(file)?);
+                profile.write_folded(&code, &mut out)?;
+                out.flush()?;
+            }
+            if let (Some(file), Some(source)) = (coverage, source) {
+                let source_name = std::fs::canonicalize(input)?;
+                let run = FileCoverage::from_counts(
+                    &source_name.to_string_lossy(), &source, parse_options, &code, profile.counts());
+                let mut merged = Coverage::read(file)?;
+                merged.merge(run);
+                let mut out = std::io::BufWriter::new(std::fs::File::create(file)?);
+                merged.write(&mut out)?;
+                out.flush()?;
+            }
+            res?;
+        },
+    }
+    Ok(())
+}
+
+fn debug<Int: BrainfuckInteger>(input: &str, parse_options: &ParseOptions, options: Options, exec_options: &ExecOptions, checks: &Checks) -> std::result::Result<(), Error> {
+    let source = std::fs::read_to_string(input)?;
+    let code = Brainfuck::<Int>::from_str(&source, parse_options)?;
+    let code = optimize(&code, options, checks, &mut |_, _, _| {})?;
+    debugger::debug(&code, &source, input, exec_options)?;
+    Ok(())
+}
+
+fn lint_source<Int: BrainfuckInteger>(input: &str, parse_options: &ParseOptions, tape: &TapeOptions, allow: &[Lint], json: bool) -> std::result::Result<(), Error> {
+    let source = std::fs::read_to_string(input)?;
+    let code = Brainfuck::<Int>::from_str(&source, parse_options)?;
+    let mut warnings = lint::lint(&code, tape);
+    warnings.retain(|warning| !allow.contains(&warning.lint));
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    if json {
+        lint::write_json(&warnings, &mut out, input)?;
+    } else {
+        lint::write_text(&warnings, &mut out, input, Some(&source))?;
+    }
+    out.flush()?;
+
+    if !warnings.is_empty() {
+        return Err(Error::LintWarnings { count: warnings.len() });
+    }
+    Ok(())
+}
+
+fn format_source(input: &str, parse_options: &ParseOptions, check: bool, write: bool) -> std::result::Result<(), Error> {
+    let source = std::fs::read_to_string(input)?;
+    let cst = Cst::parse(&source, parse_options)?;
+    let formatted = fmt::format(&cst);
+
+    if check {
+        if formatted != source {
+            return Err(Error::NotFormatted);
+        }
+    } else if write {
+        if formatted != source {
+            std::fs::write(input, formatted)?;
+        }
+    } else {
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(formatted.as_bytes())?;
+        out.flush()?;
+    }
+    Ok(())
+}
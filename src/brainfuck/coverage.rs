@@ -0,0 +1,228 @@
+//! Source coverage in the lcov tracefile format.
+//!
+//! lcov has no notion of columns, so every command character of a line is
+//! reported as a branch of that line (BRDA with the column as branch number).
+//! Line counts are those of the most executed command on the line and every
+//! source loop is reported as a function named after the position of its '['.
+//! Executed optimized instructions count for all source characters they were
+//! generated from.
+
+use std::collections::BTreeMap;
+use std::io::{Write, BufRead};
+use super::{Brainfuck, BrainfuckInteger, Error, ParseOptions};
+use super::span::Pos;
+
+#[derive(Default)]
+pub struct FileCoverage {
+    pub source: String,
+    lines: BTreeMap<usize, u64>,
+    // (line, column) of command characters
+    chars: BTreeMap<(usize, usize), u64>,
+    // line and name of loops
+    loops: BTreeMap<String, (usize, u64)>,
+}
+
+#[derive(Default)]
+pub struct Coverage {
+    files: Vec<FileCoverage>,
+}
+
+impl FileCoverage {
+    pub fn new(source: &str) -> Self {
+        FileCoverage {
+            source: source.to_string(),
+            ..FileCoverage::default()
+        }
+    }
+
+    /// counts are the execution counts of the instructions of code
+    pub fn from_counts<Int: BrainfuckInteger>(
+            source_name: &str, source: &str, options: &ParseOptions, code: &Brainfuck<Int>, counts: &[u64]) -> Self {
+        let mut positions = Vec::new();
+        let mut lineno = 1;
+        let mut column = 1;
+        for c in source.chars() {
+            if c == '\n' {
+                lineno += 1;
+                column = 1;
+                continue;
+            }
+            if "<>+-[].,".contains(c) || Some(c) == options.debug_char {
+                positions.push((Pos::new(lineno, column), c));
+            }
+            column += 1;
+        }
+
+        let mut char_counts = vec![0u64; positions.len()];
+        for (pc, count) in counts.iter().enumerate() {
+            let span = code.span(pc);
+            if *count == 0 || span.is_unknown() {
+                continue;
+            }
+            let first = positions.partition_point(|(pos, _)| *pos < span.start);
+            for index in first..positions.len() {
+                if positions[index].0 > span.end {
+                    break;
+                }
+                char_counts[index] = std::cmp::max(char_counts[index], *count);
+            }
+        }
+
+        let mut coverage = FileCoverage::new(source_name);
+        for ((pos, c), count) in positions.iter().zip(char_counts.iter()) {
+            coverage.chars.insert((pos.lineno, pos.column), *count);
+            let line = coverage.lines.entry(pos.lineno).or_insert(0);
+            *line = std::cmp::max(*line, *count);
+            if *c == '[' {
+                coverage.loops.insert(format!("loop@{}:{}", pos.lineno, pos.column), (pos.lineno, *count));
+            }
+        }
+
+        coverage
+    }
+
+    pub fn merge(&mut self, other: &FileCoverage) {
+        for (line, count) in &other.lines {
+            *self.lines.entry(*line).or_insert(0) += count;
+        }
+        for (pos, count) in &other.chars {
+            *self.chars.entry(*pos).or_insert(0) += count;
+        }
+        for (name, (line, count)) in &other.loops {
+            self.loops.entry(name.clone()).or_insert((*line, 0)).1 += count;
+        }
+    }
+
+    // loops in source order, by line and the column in their name
+    fn sorted_loops(&self) -> Vec<(&String, &(usize, u64))> {
+        let mut loops: Vec<_> = self.loops.iter().collect();
+        loops.sort_by_key(|(name, (line, _))| {
+            let column = name.rsplit(':').next().and_then(|column| column.parse().ok()).unwrap_or(0usize);
+            (*line, column, *name)
+        });
+        loops
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        write!(out, "TN:\nSF:{}\n", self.source)?;
+
+        let loops = self.sorted_loops();
+        for (name, (line, _)) in &loops {
+            writeln!(out, "FN:{},{}", line, name)?;
+        }
+        for (name, (_, count)) in &loops {
+            writeln!(out, "FNDA:{},{}", count, name)?;
+        }
+        writeln!(out, "FNF:{}", self.loops.len())?;
+        writeln!(out, "FNH:{}", self.loops.values().filter(|(_, count)| *count > 0).count())?;
+
+        for ((line, column), count) in &self.chars {
+            writeln!(out, "BRDA:{},0,{},{}", line, column, count)?;
+        }
+        writeln!(out, "BRF:{}", self.chars.len())?;
+        writeln!(out, "BRH:{}", self.chars.values().filter(|count| **count > 0).count())?;
+
+        for (line, count) in &self.lines {
+            writeln!(out, "DA:{},{}", line, count)?;
+        }
+        writeln!(out, "LF:{}", self.lines.len())?;
+        writeln!(out, "LH:{}", self.lines.values().filter(|count| **count > 0).count())?;
+
+        out.write_all(b"end_of_record\n")?;
+        Ok(())
+    }
+}
+
+impl Coverage {
+    /// Reads an existing tracefile. A missing file is an empty coverage.
+    pub fn read(path: &str) -> std::result::Result<Self, Error> {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Coverage::default());
+            },
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut coverage = Coverage::default();
+        let mut current: Option<FileCoverage> = None;
+        for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            let invalid = || Error::IllegalCoverageRecord {
+                filename: path.to_string(),
+                lineno:   index + 1,
+                record:   line.to_string(),
+            };
+
+            if line == "end_of_record" {
+                if let Some(file) = current.take() {
+                    coverage.files.push(file);
+                }
+                continue;
+            }
+
+            let (key, value) = match line.find(':') {
+                Some(colon) => (&line[..colon], &line[colon + 1..]),
+                None        => continue,
+            };
+
+            if key == "SF" {
+                current = Some(FileCoverage::new(value));
+                continue;
+            }
+
+            let file = match current.as_mut() {
+                Some(file) => file,
+                None       => continue,
+            };
+            let fields: Vec<&str> = value.split(',').collect();
+            match (key, fields.len()) {
+                ("DA", 2) | ("DA", 3) => {
+                    let line = fields[0].parse().map_err(|_| invalid())?;
+                    let count: u64 = fields[1].parse().map_err(|_| invalid())?;
+                    *file.lines.entry(line).or_insert(0) += count;
+                },
+                ("BRDA", 4) => {
+                    let line = fields[0].parse().map_err(|_| invalid())?;
+                    let column = fields[2].parse().map_err(|_| invalid())?;
+                    let count: u64 = if fields[3] == "-" { 0 } else { fields[3].parse().map_err(|_| invalid())? };
+                    *file.chars.entry((line, column)).or_insert(0) += count;
+                },
+                ("FN", 2) => {
+                    let line = fields[0].parse().map_err(|_| invalid())?;
+                    file.loops.entry(fields[1].to_string()).or_insert((line, 0)).0 = line;
+                },
+                ("FNDA", 2) => {
+                    let count: u64 = fields[0].parse().map_err(|_| invalid())?;
+                    file.loops.entry(fields[1].to_string()).or_insert((0, 0)).1 += count;
+                },
+                ("DA", _) | ("BRDA", _) | ("FN", _) | ("FNDA", _) => {
+                    return Err(invalid());
+                },
+                // summaries are recomputed when writing
+                _ => {}
+            }
+        }
+
+        if let Some(file) = current {
+            coverage.files.push(file);
+        }
+
+        Ok(coverage)
+    }
+
+    pub fn merge(&mut self, file: FileCoverage) {
+        match self.files.iter_mut().find(|other| other.source == file.source) {
+            Some(other) => other.merge(&file),
+            None => self.files.push(file),
+        }
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for file in &self.files {
+            file.write(out)?;
+        }
+        Ok(())
+    }
+}
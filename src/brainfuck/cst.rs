@@ -0,0 +1,118 @@
+//! Lossless syntax tree of brainfuck source. Unlike [`Brainfuck`](super::Brainfuck)
+//! it keeps everything that isn't a command, so printing it gives back the
+//! exact source. [`Brainfuck::parse`](super::Brainfuck::parse) is built on it
+//! and the formatter works on it.
+
+use super::{Error, ParseOptions, Span};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// one of `<>+-.,` or the debug char
+    Command(char, Span),
+    /// anything else up to the end of the line, including whitespace
+    Text(String, Span),
+    Newline,
+    Loop(Loop),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub start: Span,
+    pub body: Vec<Node>,
+    pub end: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Cst {
+    pub nodes: Vec<Node>,
+}
+
+impl Cst {
+    /// Parses brainfuck source. Fails on unmatched brackets.
+    pub fn parse(input: &str, options: &ParseOptions) -> std::result::Result<Self, Error> {
+        // the bodies of the currently open loops, the top level at the bottom
+        let mut stack: Vec<(Span, Vec<Node>)> = vec![(Span::unknown(), Vec::new())];
+        let mut text = String::new();
+        let mut text_start = Span::unknown();
+        let mut lineno: usize = 1;
+        let mut column: usize = 1;
+
+        for c in input.chars() {
+            let is_command = "<>+-.,[]".contains(c) || Some(c) == options.debug_char;
+            if (is_command || c == '\n') && !text.is_empty() {
+                let span = Span { start: text_start.start, end: Span::at(lineno, column - 1).start };
+                push(&mut stack, Node::Text(std::mem::take(&mut text), span));
+            }
+
+            match c {
+                '\n' => {
+                    push(&mut stack, Node::Newline);
+                    lineno += 1;
+                    column = 1;
+                    continue;
+                },
+                '[' => {
+                    stack.push((Span::at(lineno, column), Vec::new()));
+                },
+                ']' => {
+                    if stack.len() == 1 {
+                        return Err(Error::UnmatchedLoopEnd { lineno, column });
+                    }
+                    let (start, body) = stack.pop().unwrap_or_default();
+                    push(&mut stack, Node::Loop(Loop { start, body, end: Span::at(lineno, column) }));
+                },
+                c if is_command => {
+                    push(&mut stack, Node::Command(c, Span::at(lineno, column)));
+                },
+                c => {
+                    if text.is_empty() {
+                        text_start = Span::at(lineno, column);
+                    }
+                    text.push(c);
+                }
+            }
+            column += 1;
+        }
+
+        if !text.is_empty() {
+            let span = Span { start: text_start.start, end: Span::at(lineno, column - 1).start };
+            push(&mut stack, Node::Text(text, span));
+        }
+
+        if stack.len() > 1 {
+            let (start, _) = stack.pop().unwrap_or_default();
+            return Err(Error::UnmatchedLoopStart { lineno: start.start.lineno, column: start.start.column });
+        }
+
+        Ok(Cst { nodes: stack.pop().map(|(_, nodes)| nodes).unwrap_or_default() })
+    }
+}
+
+fn push(stack: &mut [(Span, Vec<Node>)], node: Node) {
+    if let Some((_, nodes)) = stack.last_mut() {
+        nodes.push(node);
+    }
+}
+
+fn write_nodes(f: &mut std::fmt::Formatter, nodes: &[Node]) -> std::fmt::Result {
+    for node in nodes {
+        match *node {
+            Node::Command(c, _)     => write!(f, "{}", c)?,
+            Node::Text(ref text, _) => f.write_str(text)?,
+            Node::Newline           => f.write_str("\n")?,
+            Node::Loop(ref body)    => {
+                f.write_str("[")?;
+                write_nodes(f, &body.body)?;
+                f.write_str("]")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// gives back the parsed source
+impl std::fmt::Display for Cst {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write_nodes(f, &self.nodes)
+    }
+}
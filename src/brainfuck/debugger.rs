@@ -0,0 +1,385 @@
+//! Interactive source level debugger on top of the interpreter.
+//! Commands are read from stdin, so a program that reads input gets the
+//! lines following the command that made it run.
+
+use std::io::{BufRead, Write};
+use super::{Brainfuck, BrainfuckInteger, ExecOptions, ExecState, Instruct};
+use super::span::Pos;
+
+const HELP: &str = "\
+commands:
+ step [N] ........... s  execute N instructions (default: 1)
+ next ............... n  execute one instruction, run loops to completion
+ continue ........... c  run until a breakpoint, watchpoint or the end
+ finish ............. f  run until the current loop is left
+ break LINE:COLUMN .. b  stop at the instructions generated from this source position
+ break #INSTRUCTION . b  stop at the instruction with this index
+ watch [CELL] ....... w  stop when the value of the cell (default: current cell) changes
+ delete [ID] ........ d  delete a breakpoint or watchpoint (default: all)
+ info ............... i  show executed steps, breakpoints and watchpoints
+ list ............... l  show the current instruction and its source
+ tape [RADIUS] ...... t  show the cells around the pointer (default radius: 5)
+ print [CELL] ....... p  show the value of a cell (default: current cell)
+ restart ............ r  restart the program
+ help ............... h  show this help
+ quit ............... q  quit the debugger
+An empty line repeats the last command.
+";
+
+enum Point<Int: BrainfuckInteger> {
+    Source(Pos),
+    Instruct(usize),
+    Watch { index: isize, value: Int },
+}
+
+pub struct Debugger<'a, Int: BrainfuckInteger> {
+    code:     &'a Brainfuck<Int>,
+    source:   &'a str,
+    lines:    Vec<&'a str>,
+    filename: &'a str,
+    options:  &'a ExecOptions,
+    state:    ExecState<Int>,
+    // breakpoints and watchpoints share their ids
+    points:   Vec<(usize, Point<Int>)>,
+    next_id:  usize,
+    running:  bool,
+}
+
+impl<'a, Int: BrainfuckInteger> Debugger<'a, Int> {
+    pub fn new(code: &'a Brainfuck<Int>, source: &'a str, filename: &'a str, options: &'a ExecOptions) -> Self {
+        Debugger {
+            code,
+            source,
+            lines: source.lines().collect(),
+            filename,
+            options,
+            state: ExecState::new(options),
+            points: Vec::new(),
+            next_id: 1,
+            running: true,
+        }
+    }
+
+    pub fn run(&mut self) -> std::io::Result<()> {
+        let stdin = std::io::stdin();
+        let mut out = std::io::stdout();
+        let mut last_command = String::new();
+
+        writeln!(out, "bfc debugger, type 'help' for a list of commands.")?;
+        self.show_current(&mut out)?;
+
+        loop {
+            write!(out, "(bfdb) ")?;
+            out.flush()?;
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                writeln!(out)?;
+                break;
+            }
+
+            let line = line.trim();
+            let command = if line.is_empty() {
+                last_command.clone()
+            } else {
+                last_command = line.to_string();
+                line.to_string()
+            };
+
+            let mut words = command.split_whitespace();
+            let cmd = match words.next() {
+                Some(cmd) => cmd,
+                None => continue
+            };
+            let arg = words.next();
+
+            match cmd {
+                "s" | "step" => {
+                    let mut count = match arg.map(|arg| arg.parse::<usize>()) {
+                        None => 1,
+                        Some(Ok(count)) if count > 0 => count,
+                        _ => {
+                            writeln!(out, "illegal step count: {}", arg.unwrap_or(""))?;
+                            continue;
+                        }
+                    };
+                    self.resume(&mut out, |_| {
+                        count -= 1;
+                        count == 0
+                    })?;
+                },
+
+                "n" | "next" => {
+                    if let Some(Instruct::LoopStart(pc_false)) = self.code.get(self.state.pc) {
+                        let pc_false = *pc_false;
+                        self.resume(&mut out, |state| state.pc == pc_false)?;
+                    } else {
+                        self.resume(&mut out, |_| true)?;
+                    }
+                },
+
+                "c" | "continue" => {
+                    self.resume(&mut out, |_| false)?;
+                },
+
+                "f" | "finish" => {
+                    match self.loop_end() {
+                        Some(pc_false) => self.resume(&mut out, |state| state.pc == pc_false)?,
+                        None => writeln!(out, "not inside of a loop")?,
+                    }
+                },
+
+                "b" | "break" => {
+                    match arg {
+                        Some(arg) => self.add_breakpoint(&mut out, arg)?,
+                        None => writeln!(out, "expected LINE:COLUMN or #INSTRUCTION")?,
+                    }
+                },
+
+                "w" | "watch" => {
+                    let index = match arg.map(|arg| arg.parse::<isize>()) {
+                        None => self.state.tape.pos(),
+                        Some(Ok(index)) => index,
+                        Some(Err(_)) => {
+                            writeln!(out, "illegal cell index: {}", arg.unwrap_or(""))?;
+                            continue;
+                        }
+                    };
+                    let value = self.cell(index);
+                    writeln!(out, "Watchpoint {}: cell {} = {:?}", self.next_id, index, value)?;
+                    self.points.push((self.next_id, Point::Watch { index, value }));
+                    self.next_id += 1;
+                },
+
+                "d" | "delete" => {
+                    match arg.map(|arg| arg.parse::<usize>()) {
+                        None => self.points.clear(),
+                        Some(Ok(id)) if self.points.iter().any(|(other, _)| *other == id) => {
+                            self.points.retain(|(other, _)| *other != id);
+                        },
+                        _ => writeln!(out, "no breakpoint or watchpoint: {}", arg.unwrap_or(""))?,
+                    }
+                },
+
+                "i" | "info" => {
+                    writeln!(out, "{} instructions executed", self.state.steps())?;
+                    if self.points.is_empty() {
+                        writeln!(out, "no breakpoints or watchpoints")?;
+                    }
+                    for (id, point) in &self.points {
+                        match *point {
+                            Point::Source(pos) =>
+                                writeln!(out, "{:3} breakpoint at {}:{}", id, pos.lineno, pos.column)?,
+                            Point::Instruct(pc) =>
+                                writeln!(out, "{:3} breakpoint at #{} ({})", id, pc, self.code.get(pc).unwrap())?,
+                            Point::Watch { index, ref value } =>
+                                writeln!(out, "{:3} watchpoint on cell {} = {:?}", id, index, value)?,
+                        }
+                    }
+                },
+
+                "l" | "list" => {
+                    self.show_current(&mut out)?;
+                },
+
+                "t" | "tape" => {
+                    match arg.map(|arg| arg.parse::<usize>()) {
+                        None => self.state.tape.write_window(&mut out, 5)?,
+                        Some(Ok(radius)) => self.state.tape.write_window(&mut out, radius)?,
+                        Some(Err(_)) => writeln!(out, "illegal radius: {}", arg.unwrap_or(""))?,
+                    }
+                },
+
+                "p" | "print" => {
+                    match arg.map(|arg| arg.parse::<isize>()) {
+                        None => {
+                            let index = self.state.tape.pos();
+                            writeln!(out, "cell {} = {:?}", index, self.cell(index))?;
+                        },
+                        Some(Ok(index)) => writeln!(out, "cell {} = {:?}", index, self.cell(index))?,
+                        Some(Err(_)) => writeln!(out, "illegal cell index: {}", arg.unwrap_or(""))?,
+                    }
+                },
+
+                "r" | "restart" => {
+                    self.state = ExecState::new(self.options);
+                    self.running = true;
+                    for (_, point) in &mut self.points {
+                        if let Point::Watch { ref mut value, .. } = *point {
+                            *value = Int::zero();
+                        }
+                    }
+                    self.show_current(&mut out)?;
+                },
+
+                "h" | "help" => {
+                    out.write_all(HELP.as_bytes())?;
+                },
+
+                "q" | "quit" => break,
+
+                _ => {
+                    writeln!(out, "unknown command: {}, type 'help' for a list of commands", cmd)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Executes at least one instruction and then until `stop` returns true,
+    // a breakpoint is hit, a watched cell changes or the program ends.
+    fn resume(&mut self, out: &mut dyn Write, mut stop: impl FnMut(&ExecState<Int>) -> bool) -> std::io::Result<()> {
+        if !self.running {
+            writeln!(out, "the program is not running, use 'restart' to run it again")?;
+            return Ok(());
+        }
+
+        loop {
+            if let Err(err) = self.code.step(&mut self.state, self.options) {
+                self.running = false;
+                self.end_line(out)?;
+                err.print(out, self.filename, Some(self.source))?;
+                return Ok(());
+            }
+
+            if self.state.pc >= self.code.len() {
+                self.running = false;
+                self.end_line(out)?;
+                // flushes the program output
+                if let Err(err) = self.code.step(&mut self.state, self.options) {
+                    err.print(out, self.filename, Some(self.source))?;
+                }
+                writeln!(out, "program exited")?;
+                return Ok(());
+            }
+
+            let mut hit = false;
+            for point_index in 0..self.points.len() {
+                let new_value = match self.points[point_index] {
+                    (id, Point::Watch { index, ref value }) => {
+                        let new_value = self.cell(index);
+                        if *value == new_value {
+                            continue;
+                        }
+                        writeln!(out, "Watchpoint {}: cell {}: {:?} -> {:?}", id, index, value, new_value)?;
+                        new_value
+                    },
+                    _ => continue
+                };
+                if let Point::Watch { ref mut value, .. } = self.points[point_index].1 {
+                    *value = new_value;
+                }
+                hit = true;
+            }
+
+            let pc = self.state.pc;
+            let span = self.code.span(pc);
+            for (id, point) in &self.points {
+                let matches = match *point {
+                    Point::Source(pos) => !span.is_unknown() && span.start <= pos && pos <= span.end,
+                    Point::Instruct(other) => other == pc,
+                    Point::Watch { .. } => false,
+                };
+                if matches {
+                    writeln!(out, "Breakpoint {}", id)?;
+                    hit = true;
+                }
+            }
+
+            if hit || stop(&self.state) {
+                return self.show_current(out);
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, out: &mut dyn Write, arg: &str) -> std::io::Result<()> {
+        let point = if let Some(index) = arg.strip_prefix('#') {
+            match index.parse::<usize>() {
+                Ok(pc) if pc < self.code.len() => Point::Instruct(pc),
+                _ => {
+                    return writeln!(out, "no such instruction: {}", arg);
+                }
+            }
+        } else {
+            let mut parts = arg.splitn(2, ':');
+            let lineno = parts.next().and_then(|lineno| lineno.parse::<usize>().ok());
+            let column = parts.next().and_then(|column| column.parse::<usize>().ok());
+            match (lineno, column) {
+                (Some(lineno), Some(column)) if lineno > 0 && column > 0 => {
+                    let pos = Pos::new(lineno, column);
+                    let found = (0..self.code.len()).any(|pc| {
+                        let span = self.code.span(pc);
+                        !span.is_unknown() && span.start <= pos && pos <= span.end
+                    });
+                    if !found {
+                        writeln!(out, "warning: no instruction was generated from {}:{}", lineno, column)?;
+                    }
+                    Point::Source(pos)
+                },
+                _ => {
+                    return writeln!(out, "expected LINE:COLUMN or #INSTRUCTION: {}", arg);
+                }
+            }
+        };
+
+        writeln!(out, "Breakpoint {} at {}", self.next_id, arg)?;
+        self.points.push((self.next_id, point));
+        self.next_id += 1;
+
+        Ok(())
+    }
+
+    // so messages don't continue the last line of program output
+    fn end_line(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        if self.state.pending_line() {
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    // index after the end of the innermost loop around the current instruction
+    fn loop_end(&self) -> Option<usize> {
+        let pc = self.state.pc;
+        for index in (0..pc).rev() {
+            if let Some(Instruct::LoopStart(pc_false)) = self.code.get(index) {
+                if *pc_false > pc {
+                    return Some(*pc_false);
+                }
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn cell(&self, index: isize) -> Int {
+        self.state.tape.get(index).clone()
+    }
+
+    fn show_current(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        let pc = self.state.pc;
+        let instr = match self.code.get(pc) {
+            Some(instr) => instr,
+            None => {
+                return writeln!(out, "#{} end of program", pc);
+            }
+        };
+
+        let span = self.code.span(pc);
+        if span.is_unknown() {
+            return writeln!(out, "#{} {}", pc, instr);
+        }
+
+        writeln!(out, "#{} {}: {}", pc, span, instr)?;
+        if let Some(line) = self.lines.get(span.start.lineno - 1) {
+            span.write_snippet(out, line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the debugger on stdin and stdout until the user quits.
+pub fn debug<Int: BrainfuckInteger>(code: &Brainfuck<Int>, source: &str, filename: &str, options: &ExecOptions) -> std::io::Result<()> {
+    Debugger::new(code, source, filename, options).run()
+}
\ No newline at end of file
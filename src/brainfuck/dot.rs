@@ -0,0 +1,143 @@
+//! Control-flow graph of a program in the Graphviz dot language. Straight
+//! runs of instructions are basic blocks (boxes), every loop is a condition
+//! (diamond) with an edge into the body and one past the loop. The end of
+//! the body jumps back to the condition (dashed back edge).
+//!
+//! ```text
+//! bfc -O all prog.bf compile --format dot -o prog.dot && dot -Tsvg prog.dot > prog.svg
+//! ```
+
+use std::io::Write;
+use super::{Brainfuck, BrainfuckInteger, Instruct};
+
+enum Node {
+    Start,
+    End,
+    // instructions start..end
+    Block(usize, usize),
+    // index of the LoopStart
+    Cond(usize),
+}
+
+struct Edge {
+    from: usize,
+    to: usize,
+    label: &'static str,
+    back: bool,
+}
+
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn connect(&mut self, preds: &[(usize, &'static str)], to: usize, back: bool) {
+        for (from, label) in preds {
+            self.edges.push(Edge { from: *from, to, label, back });
+        }
+    }
+
+    // Adds the instructions start..end, entered from preds. Returns the nodes
+    // that leave the range with the labels of their edges.
+    fn build<Int: BrainfuckInteger>(&mut self, code: &Brainfuck<Int>, start: usize, end: usize, mut preds: Vec<(usize, &'static str)>) -> Vec<(usize, &'static str)> {
+        let mut index = start;
+        let mut block_start = start;
+        while index < end {
+            if let Some(Instruct::LoopStart(after_end)) = code.get(index) {
+                let after_end = *after_end;
+                if block_start < index {
+                    let block = self.add(Node::Block(block_start, index));
+                    self.connect(&preds, block, false);
+                    preds = vec![(block, "")];
+                }
+                let cond = self.add(Node::Cond(index));
+                self.connect(&preds, cond, false);
+                let body = self.build(code, index + 1, after_end - 1, vec![(cond, "!= 0")]);
+                self.connect(&body, cond, true);
+                preds = vec![(cond, "== 0")];
+                index = after_end;
+                block_start = index;
+            } else {
+                index += 1;
+            }
+        }
+        if block_start < end {
+            let block = self.add(Node::Block(block_start, end));
+            self.connect(&preds, block, false);
+            preds = vec![(block, "")];
+        }
+        preds
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn write_graph<Int: BrainfuckInteger>(code: &Brainfuck<Int>, out: &mut dyn Write, prefix: &str) -> std::io::Result<()> {
+    let mut graph = Graph { nodes: Vec::new(), edges: Vec::new() };
+    let start = graph.add(Node::Start);
+    let exits = graph.build(code, 0, code.len(), vec![(start, "")]);
+    let end = graph.add(Node::End);
+    graph.connect(&exits, end, false);
+
+    for (id, node) in graph.nodes.iter().enumerate() {
+        match *node {
+            Node::Start => writeln!(out, "    {}{} [label=\"start\", shape=oval];", prefix, id)?,
+            Node::End   => writeln!(out, "    {}{} [label=\"end\", shape=oval];", prefix, id)?,
+            Node::Block(start, end) => {
+                // one left aligned line per instruction
+                let mut label = String::new();
+                for index in start..end {
+                    if let Some(instr) = code.get(index) {
+                        label.push_str(&escape(&format!("{:4}: {}", index, instr)));
+                        label.push_str("\\l");
+                    }
+                }
+                writeln!(out, "    {}{} [label=\"{}\", shape=box, fontname=monospace];", prefix, id, label)?;
+            },
+            Node::Cond(index) => {
+                let span = code.span(index);
+                let label = if span.is_unknown() { format!("{}: loop", index) } else { format!("{}: loop at {}", index, span) };
+                writeln!(out, "    {}{} [label=\"{}\", shape=diamond];", prefix, id, escape(&label))?;
+            },
+        }
+    }
+
+    for edge in &graph.edges {
+        write!(out, "    {}{} -> {}{}", prefix, edge.from, prefix, edge.to)?;
+        match (edge.label, edge.back) {
+            ("", false)    => {},
+            ("", true)     => out.write_all(b" [style=dashed]")?,
+            (label, false) => write!(out, " [label=\"{}\"]", label)?,
+            (label, true)  => write!(out, " [label=\"{}\", style=dashed]", label)?,
+        }
+        out.write_all(b";\n")?;
+    }
+
+    Ok(())
+}
+
+/// Writes the control-flow graph of the program.
+pub fn write_dot<Int: BrainfuckInteger>(code: &Brainfuck<Int>, out: &mut dyn Write) -> std::io::Result<()> {
+    out.write_all(b"digraph program {\n")?;
+    write_graph(code, out, "n")?;
+    out.write_all(b"}\n")
+}
+
+/// Writes the control-flow graphs of the program before and after an
+/// optimization pass next to each other.
+pub fn write_dot_pass<Int: BrainfuckInteger>(before: &Brainfuck<Int>, after: &Brainfuck<Int>, pass: &str, out: &mut dyn Write) -> std::io::Result<()> {
+    out.write_all(b"digraph program {\n")?;
+    write!(out, "    subgraph cluster_before {{\n    label=\"before {}\";\n", escape(pass))?;
+    write_graph(before, out, "b")?;
+    write!(out, "    }}\n    subgraph cluster_after {{\n    label=\"after {}\";\n", escape(pass))?;
+    write_graph(after, out, "a")?;
+    out.write_all(b"    }\n}\n")
+}
@@ -1,10 +1,47 @@
-// TODO: error messages (custom Debug impl)
+use super::Span;
 
+/// Everything that can go wrong when parsing, optimizing, running or
+/// compiling a program, including bad command line arguments.
 #[derive(Debug)]
 pub enum Error {
     IO(std::io::Error),
+    IllegalCoverageRecord { filename: String, lineno: usize, record: String },
+    IllegalBytecode(String),
+
+    // command line
+    IllegalOptimization(String),
+    IllegalCellSize(String),
+    UnsupportedFormat(String),
+    IllegalArgument { name: &'static str, value: String, expected: &'static str },
+    MissingSubcommand,
+    IllegalSubcommand(String),
+    BytecodeMismatch { name: &'static str, bytecode: String, given: String },
+
+    // source
     UnmatchedLoopStart { lineno: usize, column: usize },
     UnmatchedLoopEnd { lineno: usize, column: usize },
+    IrSyntax { lineno: usize, column: usize, message: String },
+    OokSyntax { lineno: usize, column: usize, message: String },
+
+    // execution
+    TapeOutOfBounds { lineno: usize, column: usize, index: isize, size: usize },
+    CellOverflow { lineno: usize, column: usize, index: isize },
+    StepLimitExceeded { lineno: usize, column: usize, steps: u64, cells: usize },
+    TapeLimitExceeded { lineno: usize, column: usize, steps: u64, cells: usize, limit: usize },
+    Timeout { lineno: usize, column: usize, steps: u64, cells: usize, millis: u64 },
+
+    // output
+    ConstantTooLarge { lineno: usize, column: usize, value: String },
+    NotRepresentable { lineno: usize, column: usize },
+    ToolFailed { tool: String, status: Option<i32> },
+
+    // lint and fmt --check
+    LintWarnings { count: usize },
+    NotFormatted,
+
+    // --verify-passes and --verify-ir
+    PassChangedBehavior { pass: String },
+    MalformedProgram { pass: Option<String>, index: usize, message: String },
 }
 
 impl std::convert::From<std::io::Error> for Error {
@@ -13,16 +50,202 @@ impl std::convert::From<std::io::Error> for Error {
     }
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(span) = self.span() {
+            write!(f, "{}: ", span)?;
+        }
+
+        match *self {
+            Error::IO(ref err) => write!(f, "{}", err),
+
+            Error::IllegalOptimization(ref opt) =>
+                write!(f, "illegal optimization: {}", opt),
+
+            Error::IllegalCellSize(ref size) =>
+                write!(f, "illegal cell size: {}", size),
+
+            Error::UnsupportedFormat(ref format) =>
+                write!(f, "unsupported format: {}", format),
+
+            Error::IllegalArgument { name, ref value, expected } =>
+                write!(f, "illegal {}: {:?} is not {}", name, value, expected),
+
+            Error::MissingSubcommand =>
+                f.write_str("a sub-command is required"),
+
+            Error::IllegalSubcommand(ref cmd) =>
+                write!(f, "illegal sub-command: {}", cmd),
+
+            Error::BytecodeMismatch { name, ref bytecode, ref given } =>
+                write!(f, "the bytecode was compiled with {} {}, not {}", name, bytecode, given),
+
+            Error::UnmatchedLoopStart { .. } =>
+                f.write_str("unmatched '['"),
+
+            Error::UnmatchedLoopEnd { .. } =>
+                f.write_str("unmatched ']'"),
+
+            Error::IrSyntax { ref message, .. } |
+            Error::OokSyntax { ref message, .. } =>
+                f.write_str(message),
+
+            Error::TapeOutOfBounds { index, size, .. } =>
+                write!(f, "pointer moved out of tape bounds: cell {} is not in 0...{}", index, size as isize - 1),
+
+            Error::CellOverflow { index, .. } =>
+                write!(f, "arithmetic overflow in cell {}", index),
+
+            Error::StepLimitExceeded { steps, cells, .. } =>
+                write!(f, "step limit exceeded after {} steps using {} cells", steps, cells),
+
+            Error::TapeLimitExceeded { steps, cells, limit, .. } =>
+                write!(f, "tape limit exceeded after {} steps: {} cells needed, {} allowed", steps, cells, limit),
+
+            Error::Timeout { steps, cells, millis, .. } =>
+                write!(f, "timeout after {}.{:03} seconds, {} steps using {} cells",
+                    millis / 1000, millis % 1000, steps, cells),
+
+            Error::ConstantTooLarge { ref value, .. } =>
+                write!(f, "constant too large for brainfuck output: {}", value),
+
+            Error::NotRepresentable { .. } =>
+                f.write_str("this optimized brainfuck program cannot (easily) be converted back to brainfuck anymore"),
+
+            Error::ToolFailed { ref tool, status: Some(status) } =>
+                write!(f, "{} exited with status {}", tool, status),
+
+            Error::ToolFailed { ref tool, status: None } =>
+                write!(f, "{} terminated by signal", tool),
+
+            Error::LintWarnings { count } =>
+                write!(f, "{} lint warning(s)", count),
+
+            Error::NotFormatted =>
+                f.write_str("source is not formatted"),
+
+            Error::PassChangedBehavior { ref pass } =>
+                write!(f, "optimization pass {} changed the behavior of the program", pass),
+
+            Error::MalformedProgram { pass: Some(ref pass), index, ref message } =>
+                write!(f, "optimization pass {} broke the program at instruction {}: {}", pass, index, message),
+
+            Error::MalformedProgram { pass: None, index, ref message } =>
+                write!(f, "malformed program at instruction {}: {}", index, message),
+
+            Error::IllegalCoverageRecord { ref filename, lineno, ref record } =>
+                write!(f, "{}:{}: illegal lcov record: {}", filename, lineno, record),
+
+            Error::IllegalBytecode(ref message) =>
+                write!(f, "illegal bytecode file: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::IO(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 impl Error {
-    pub fn print(&self, out: &mut std::io::Write, input: &str) -> std::io::Result<()> {
+    /// Source position the error refers to, if any.
+    pub fn span(&self) -> Option<Span> {
         match *self {
-            Error::IO(ref err) => write!(out, "error:{}: {}\n", input, err),
+            Error::UnmatchedLoopStart { lineno, column } |
+            Error::UnmatchedLoopEnd   { lineno, column } |
+            Error::IrSyntax           { lineno, column, .. } |
+            Error::OokSyntax          { lineno, column, .. } |
+            Error::TapeOutOfBounds    { lineno, column, .. } |
+            Error::CellOverflow       { lineno, column, .. } |
+            Error::StepLimitExceeded  { lineno, column, .. } |
+            Error::TapeLimitExceeded  { lineno, column, .. } |
+            Error::Timeout            { lineno, column, .. } |
+            Error::ConstantTooLarge   { lineno, column, .. } |
+            Error::NotRepresentable   { lineno, column } if lineno > 0 => Some(Span::at(lineno, column)),
+            _ => None,
+        }
+    }
 
-            Error::UnmatchedLoopStart { lineno, column } =>
-                write!(out, "error:{}:{}:{}: unmatched '['\n", input, lineno, column),
+    /// Prints the error message for the program read from the file input.
+    /// If the source is given, errors at a source position also show the
+    /// offending line.
+    pub fn print(&self, out: &mut dyn std::io::Write, input: &str, source: Option<&str>) -> std::io::Result<()> {
+        if self.is_usage() {
+            writeln!(out, "error: {}", self)?;
+        } else if self.span().is_some() {
+            writeln!(out, "error:{}:{}", input, self)?;
+        } else {
+            writeln!(out, "error:{}: {}", input, self)?;
+        }
 
-            Error::UnmatchedLoopEnd { lineno, column } =>
-                write!(out, "error:{}:{}:{}: unmatched ']'\n", input, lineno, column),
+        if let (Some(span), Some(source)) = (self.span(), source) {
+            if let Some(line) = source.lines().nth(span.start.lineno - 1) {
+                span.write_snippet(out, line)?;
+            }
         }
+
+        Ok(())
+    }
+
+    /// True for errors in the command line arguments.
+    pub fn is_usage(&self) -> bool {
+        self.exit_code() == 2
     }
-}
\ No newline at end of file
+
+    /// Process exit status by kind of error:
+    ///
+    /// * 1 ... I/O errors, including malformed input files
+    /// * 2 ... illegal command line arguments
+    /// * 3 ... step limit exceeded
+    /// * 4 ... tape limit exceeded
+    /// * 5 ... timeout
+    /// * 6 ... malformed source
+    /// * 7 ... runtime errors of the program
+    /// * 8 ... the program can't be written in the requested format or the
+    ///   compiler tool chain failed
+    /// * 9 ... `lint` found something or `fmt --check` found unformatted
+    ///   source
+    /// * 10 .. `--verify-passes` found a pass that changed the behavior of
+    ///   the program or `--verify-ir` one that broke its structure
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::IO(_) |
+            Error::IllegalCoverageRecord { .. } |
+            Error::IllegalBytecode(_) => 1,
+
+            Error::IllegalOptimization(_) |
+            Error::IllegalCellSize(_) |
+            Error::UnsupportedFormat(_) |
+            Error::IllegalArgument { .. } |
+            Error::MissingSubcommand |
+            Error::IllegalSubcommand(_) |
+            Error::BytecodeMismatch { .. } => 2,
+
+            Error::StepLimitExceeded { .. } => 3,
+            Error::TapeLimitExceeded { .. } => 4,
+            Error::Timeout { .. }           => 5,
+
+            Error::UnmatchedLoopStart { .. } |
+            Error::UnmatchedLoopEnd { .. } |
+            Error::IrSyntax { .. } |
+            Error::OokSyntax { .. } => 6,
+
+            Error::TapeOutOfBounds { .. } |
+            Error::CellOverflow { .. } => 7,
+
+            Error::ConstantTooLarge { .. } |
+            Error::NotRepresentable { .. } |
+            Error::ToolFailed { .. } => 8,
+
+            Error::LintWarnings { .. } |
+            Error::NotFormatted => 9,
+
+            Error::PassChangedBehavior { .. } |
+            Error::MalformedProgram { .. } => 10,
+        }
+    }
+}
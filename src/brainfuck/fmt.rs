@@ -0,0 +1,211 @@
+//! Source formatter. The line structure of the source is kept, so comments
+//! stay on the line of the code they describe. Only:
+//!
+//! * lines are indented by loop depth (4 spaces per level)
+//! * whitespace is normalized: a space separates comments from code and
+//!   each run of `<>` that follows other commands starts a new group
+//! * loops are split into a `[` line, the body and a `]` line, unless they
+//!   are short and already on one line (like `[-]` or `[<]`)
+//! * consecutive blank lines are collapsed
+//!
+//! Formatting formatted source doesn't change it anymore.
+
+use super::cst::{Cst, Node};
+
+// longest loop body (in commands) that is kept on one line
+const INLINE_LOOP_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Command(char),
+    // loop index
+    Start(usize),
+    End(usize),
+    Comment(String),
+}
+
+struct Flattener {
+    lines: Vec<Vec<Token>>,
+    // per loop: kept on one line
+    inline: Vec<bool>,
+}
+
+impl Flattener {
+    fn push(&mut self, token: Token) {
+        if let Some(line) = self.lines.last_mut() {
+            line.push(token);
+        }
+    }
+
+    // returns the number of commands and if there were nested loops
+    fn flatten(&mut self, nodes: &[Node]) -> (usize, bool) {
+        let mut commands = 0;
+        let mut loops = false;
+        for node in nodes {
+            match *node {
+                Node::Command(c, _) => {
+                    self.push(Token::Command(c));
+                    commands += 1;
+                },
+                Node::Text(ref text, _) => {
+                    let text = text.trim();
+                    if !text.is_empty() {
+                        self.push(Token::Comment(text.to_string()));
+                    }
+                },
+                Node::Newline => {
+                    self.lines.push(Vec::new());
+                },
+                Node::Loop(ref body) => {
+                    let index = self.inline.len();
+                    let line = self.lines.len();
+                    self.inline.push(false);
+                    self.push(Token::Start(index));
+                    let (body_commands, nested) = self.flatten(&body.body);
+                    self.push(Token::End(index));
+                    self.inline[index] = line == self.lines.len() && !nested && body_commands <= INLINE_LOOP_LEN;
+                    commands += body_commands;
+                    loops = true;
+                }
+            }
+        }
+        (commands, loops)
+    }
+}
+
+struct Printer<'a> {
+    inline: &'a [bool],
+    out: String,
+    line: String,
+    // nesting at the start of the current line
+    line_depth: usize,
+    depth: usize,
+    // nesting of inline loops
+    inline_depth: usize,
+    last: Option<Token>,
+    // after a split '[' or ']' only comments stay on the line
+    pending_break: bool,
+    blank: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            for _ in 0..self.line_depth {
+                self.out.push_str("    ");
+            }
+            self.out.push_str(&self.line);
+            self.out.push('\n');
+            self.line.clear();
+            self.blank = false;
+        }
+        self.last = None;
+        self.pending_break = false;
+    }
+
+    fn blank_line(&mut self) {
+        if !self.blank && !self.out.is_empty() {
+            self.out.push('\n');
+            self.blank = true;
+        }
+    }
+
+    fn is_move(token: Option<&Token>) -> bool {
+        match token {
+            Some(Token::Command(c)) => *c == '<' || *c == '>',
+            _ => false,
+        }
+    }
+
+    fn append(&mut self, token: Token, text: &str) {
+        if self.line.is_empty() {
+            self.line_depth = self.depth;
+        } else {
+            let space = match (&self.last, &token) {
+                (Some(Token::Comment(_)), _) | (_, Token::Comment(_)) => true,
+                (Some(Token::Start(index)), _) if self.inline[*index] => false,
+                (_, Token::End(index)) if self.inline[*index] => false,
+                _ => self.inline_depth == 0 && Self::is_move(Some(&token)) && !Self::is_move(self.last.as_ref()),
+            };
+            if space {
+                self.line.push(' ');
+            }
+        }
+        self.line.push_str(text);
+        self.last = Some(token);
+    }
+
+    fn print(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            if self.pending_break {
+                match *token {
+                    Token::Comment(_) => {},
+                    _ => self.end_line(),
+                }
+            }
+
+            match *token {
+                Token::Command(c) => {
+                    let mut buf = [0; 4];
+                    self.append(token.clone(), c.encode_utf8(&mut buf));
+                },
+                Token::Comment(ref text) => {
+                    self.append(token.clone(), text);
+                },
+                Token::Start(index) => {
+                    self.append(token.clone(), "[");
+                    if self.inline[index] {
+                        self.inline_depth += 1;
+                    } else {
+                        self.depth += 1;
+                        self.pending_break = true;
+                    }
+                },
+                Token::End(index) => {
+                    if self.inline[index] {
+                        self.inline_depth -= 1;
+                        self.append(token.clone(), "]");
+                    } else {
+                        self.end_line();
+                        self.depth -= 1;
+                        self.append(token.clone(), "]");
+                        self.pending_break = true;
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Formats the source the syntax tree was parsed from.
+pub fn format(cst: &Cst) -> String {
+    let mut flattener = Flattener { lines: vec![Vec::new()], inline: Vec::new() };
+    flattener.flatten(&cst.nodes);
+
+    let mut printer = Printer {
+        inline: &flattener.inline,
+        out: String::new(),
+        line: String::new(),
+        line_depth: 0,
+        depth: 0,
+        inline_depth: 0,
+        last: None,
+        pending_break: false,
+        blank: false,
+    };
+
+    for tokens in &flattener.lines {
+        if tokens.is_empty() {
+            printer.blank_line();
+        } else {
+            printer.print(tokens);
+            printer.end_line();
+        }
+    }
+
+    if printer.blank {
+        printer.out.pop();
+    }
+
+    printer.out
+}
@@ -0,0 +1,390 @@
+//! Random program generator and differential tester for the optimizer.
+//!
+//! [`generate`] writes random well-formed brainfuck programs whose loops
+//! almost always terminate: a loop either counts its cell down (and nothing
+//! else in the body touches that cell), clears it, or scans for a zero cell.
+//! Pointer movement stays within a few cells of the start, apart from scans.
+//!
+//! [`check`] runs a program with every optimization configuration of
+//! [`configurations`] and compares output and final tape with the
+//! unoptimized program. [`shrink`] reduces a failing program to a small
+//! program that still fails.
+
+use super::{Brainfuck, BrainfuckInteger, Error, ExecOptions, ParseOptions};
+use super::optimize::Options;
+
+/// Small deterministic random number generator (xorshift64*), so failing
+/// cases can be reproduced from their seed.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // the state must not be 0
+        Rng { state: seed.wrapping_mul(0x9E3779B97F4A7C15) | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    /// Random number in 0..n.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// True with a probability of percent / 100.
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    /// Random bytes, e.g. the input of a program.
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+}
+
+/// Shape of the generated programs.
+pub struct GenOptions {
+    /// approximate number of instructions
+    pub length: usize,
+    pub max_depth: usize,
+    /// the pointer stays within -max_offset..=max_offset, apart from scans
+    pub max_offset: isize,
+    /// generate `,`
+    pub read: bool,
+    /// generate scan loops like `[>]`
+    pub scan: bool,
+}
+
+impl std::default::Default for GenOptions {
+    fn default() -> Self {
+        GenOptions {
+            length:     40,
+            max_depth:  3,
+            max_offset: 4,
+            read:       true,
+            scan:       true,
+        }
+    }
+}
+
+struct Generator<'a> {
+    rng: &'a mut Rng,
+    options: &'a GenOptions,
+    out: String,
+    budget: usize,
+    ptr: isize,
+    // counter cells of the enclosing loops, relative to the start
+    counters: Vec<isize>,
+}
+
+impl<'a> Generator<'a> {
+    fn move_to(&mut self, target: isize) {
+        let ch = if target < self.ptr { '<' } else { '>' };
+        for _ in 0..(target - self.ptr).abs() {
+            self.out.push(ch);
+        }
+        self.ptr = target;
+    }
+
+    // moves to a random cell that isn't a loop counter
+    fn move_free(&mut self) -> bool {
+        for _ in 0..8 {
+            let max = self.options.max_offset;
+            let target = self.rng.below((max * 2 + 1) as usize) as isize - max;
+            if !self.counters.contains(&target) {
+                self.move_to(target);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn repeat(&mut self, ch: char, max: usize) {
+        for _ in 0..self.rng.below(max) + 1 {
+            self.out.push(ch);
+        }
+    }
+
+    fn block(&mut self, depth: usize) {
+        while self.budget > 0 {
+            self.budget -= 1;
+            match self.rng.below(100) {
+                0..=29  => if self.move_free() { self.repeat('+', 12) },
+                30..=39 => if self.move_free() { self.repeat('-', 6) },
+                40..=54 => {
+                    self.move_free();
+                    self.out.push('.');
+                },
+                55..=61 => if self.options.read && self.move_free() { self.out.push(',') },
+                62..=79 if depth < self.options.max_depth => self.counter_loop(depth),
+                80..=87 => if self.move_free() {
+                    self.out.push_str(if self.rng.chance(80) { "[-]" } else { "[+]" });
+                },
+                88..=91 if depth < self.options.max_depth => self.if_loop(depth),
+                92..=94 if self.options.scan && self.counters.is_empty() => self.scan(),
+                _ => if !self.counters.is_empty() && self.rng.chance(30) { return },
+            }
+        }
+    }
+
+    // [- body ] where the body never touches the counter and ends where it started
+    fn counter_loop(&mut self, depth: usize) {
+        if !self.move_free() {
+            return;
+        }
+        let counter = self.ptr;
+        if self.rng.chance(70) {
+            self.repeat('+', 6);
+        }
+        self.out.push('[');
+        let first = self.rng.chance(50);
+        if first {
+            self.out.push('-');
+        }
+        self.counters.push(counter);
+        self.block(depth + 1);
+        self.counters.pop();
+        self.move_to(counter);
+        if !first {
+            self.out.push('-');
+        }
+        self.out.push(']');
+    }
+
+    // [ body [-] ], runs at most once
+    fn if_loop(&mut self, depth: usize) {
+        if !self.move_free() {
+            return;
+        }
+        let cell = self.ptr;
+        self.out.push('[');
+        self.counters.push(cell);
+        self.block(depth + 1);
+        self.counters.pop();
+        self.move_to(cell);
+        self.out.push_str("[-]]");
+    }
+
+    fn scan(&mut self) {
+        self.move_free();
+        let step = self.rng.below(2) + 1;
+        let ch = if self.rng.chance(60) { '>' } else { '<' };
+        self.out.push('[');
+        for _ in 0..step {
+            self.out.push(ch);
+        }
+        self.out.push(']');
+        // the pointer is somewhere else now, pretend it is back at the start
+        // so the following code stays within the range again
+        self.ptr = 0;
+    }
+}
+
+/// Generates a random well-formed brainfuck program.
+pub fn generate(rng: &mut Rng, options: &GenOptions) -> String {
+    let mut gen = Generator {
+        rng,
+        options,
+        out: String::new(),
+        budget: options.length,
+        ptr: 0,
+        counters: Vec::new(),
+    };
+    gen.block(0);
+    gen.out
+}
+
+/// The optimization configurations a program is checked with: every single
+/// optimization, all of them and all but constexpr.
+pub fn configurations() -> Vec<(&'static str, Options)> {
+    let single = |name: &'static str, set: &dyn Fn(&mut Options)| {
+        let mut options = Options::none();
+        set(&mut options);
+        (name, options)
+    };
+    vec![
+        single("fold",      &|options| options.fold      = true),
+        single("set",       &|options| options.set       = true),
+        single("add_to",    &|options| options.add_to    = true),
+        single("write",     &|options| options.write     = true),
+        single("deadcode",  &|options| options.deadcode  = true),
+        single("constexpr", &|options| options.constexpr = true),
+        single("skip",      &|options| options.skip      = true),
+        ("all", Options::all()),
+        ("all -constexpr", Options { constexpr: false, ..Options::all() }),
+    ]
+}
+
+/// What a run of a program did, as far as it can be observed.
+#[derive(Debug, PartialEq)]
+pub struct Outcome<Int: BrainfuckInteger> {
+    pub output: Vec<u8>,
+    /// logical index of the cell the pointer ends at
+    pub ptr: isize,
+    /// the non-zero cells at the end by logical index
+    pub cells: Vec<(isize, Int)>,
+}
+
+impl<Int: BrainfuckInteger> Outcome<Int> {
+    // Compares the output and, if tape is set, pointer and cells.
+    pub(crate) fn same(&self, other: &Self, tape: bool) -> bool {
+        self.output == other.output && (!tape || (self.ptr == other.ptr && self.cells == other.cells))
+    }
+}
+
+/// Runs the program with the input. Set `options.max_steps` for programs
+/// that might not terminate. Debug dumps aren't part of the outcome and are
+/// dropped.
+pub fn run<Int: BrainfuckInteger>(code: &Brainfuck<Int>, input: &[u8], options: &ExecOptions) -> std::result::Result<Outcome<Int>, Error> {
+    let mut output = Vec::new();
+    let state = code.exec_with(&mut &input[..], &mut output, &mut std::io::sink(), options.clone())?;
+    let start = state.tape.start();
+    let cells = state.tape.cells().iter().enumerate()
+        .filter(|(_, val)| !val.is_zero())
+        .map(|(index, val)| (start + index as isize, val.clone()))
+        .collect();
+    Ok(Outcome { output, ptr: state.tape.pos(), cells })
+}
+
+/// A configuration that behaves differently than the unoptimized program.
+pub struct Mismatch<Int: BrainfuckInteger> {
+    pub config: &'static str,
+    pub expected: Outcome<Int>,
+    pub actual: std::result::Result<Outcome<Int>, Error>,
+}
+
+impl<Int: BrainfuckInteger> std::fmt::Display for Mismatch<Int> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "-O {} differs from -O none", self.config)?;
+        writeln!(f, "expected: {:?}", self.expected)?;
+        match self.actual {
+            Ok(ref actual) => write!(f, "actual:   {:?}", actual),
+            Err(ref err)   => write!(f, "actual:   error: {}", err),
+        }
+    }
+}
+
+/// Runs the source unoptimized and with every configuration. Returns the
+/// outcome of the unoptimized run, or None if it didn't finish within
+/// max_steps, so there is nothing to compare.
+pub fn check<Int: BrainfuckInteger>(source: &str, input: &[u8], max_steps: u64) -> std::result::Result<Option<Outcome<Int>>, Box<Mismatch<Int>>> {
+    let code = match Brainfuck::<Int>::from_str(source, &ParseOptions::default()) {
+        Ok(code) => code,
+        Err(_) => return Ok(None),
+    };
+    let exec_options = ExecOptions {
+        max_steps: Some(max_steps),
+        ..ExecOptions::default()
+    };
+    let expected = match run(&code, input, &exec_options) {
+        Ok(outcome) => outcome,
+        Err(_) => return Ok(None),
+    };
+
+    for (config, options) in configurations() {
+        let constexpr = options.constexpr;
+        let actual = code.optimize_with(options, &mut std::io::sink())
+            .map_err(Error::IO)
+            .and_then(|opt_code| run(&opt_code, input, &exec_options));
+        // constexpr drops the tape of a program it evaluates completely,
+        // only the output is left. "all -constexpr" checks the tape after
+        // the other passes together.
+        let same = match actual {
+            Ok(ref actual) => actual.same(&expected, !constexpr),
+            Err(_) => false,
+        };
+        if !same {
+            return Err(Box::new(Mismatch { config, expected, actual }));
+        }
+    }
+
+    Ok(Some(expected))
+}
+
+/// Shrinks a program for which fails returns true to a smaller program that
+/// still fails. Removes whole loops, unwraps loop bodies and removes runs,
+/// pairs and single instructions until nothing can be removed anymore, so
+/// the result is always well-formed if the source is.
+pub fn shrink(source: &str, fails: &mut dyn FnMut(&str) -> bool) -> String {
+    let mut current: Vec<u8> = source.bytes().filter(|byte| b"+-<>,.[]".contains(byte)).collect();
+    let mut shrunk = true;
+    while shrunk {
+        shrunk = false;
+        let mut index = 0;
+        while index < current.len() {
+            let candidates: Vec<Vec<u8>> = match current[index] {
+                b'[' => {
+                    let end = match matching_end(&current, index) {
+                        Some(end) => end,
+                        None => break,
+                    };
+                    let mut without_loop = current[..index].to_vec();
+                    without_loop.extend_from_slice(&current[end + 1..]);
+                    let mut unwrapped = current[..index].to_vec();
+                    unwrapped.extend_from_slice(&current[index + 1..end]);
+                    unwrapped.extend_from_slice(&current[end + 1..]);
+                    vec![without_loop, unwrapped]
+                },
+                b']' => Vec::new(),
+                _ => {
+                    // a run of the same instruction at once, then a single one
+                    let mut end = index + 1;
+                    while end < current.len() && current[end] == current[index] {
+                        end += 1;
+                    }
+                    let mut without_run = current[..index].to_vec();
+                    without_run.extend_from_slice(&current[end..]);
+                    let mut without_one = current[..index].to_vec();
+                    without_one.extend_from_slice(&current[index + 1..]);
+                    let mut candidates = if end - index > 1 { vec![without_run] } else { Vec::new() };
+                    // e.g. `<>`, which only changes the program together
+                    if index + 1 < current.len() && !b"[]".contains(&current[index + 1]) {
+                        let mut without_pair = current[..index].to_vec();
+                        without_pair.extend_from_slice(&current[index + 2..]);
+                        candidates.push(without_pair);
+                    }
+                    candidates.push(without_one);
+                    candidates
+                },
+            };
+
+            let mut replaced = false;
+            for candidate in candidates {
+                let text = String::from_utf8(candidate).expect("brainfuck is ASCII");
+                if fails(&text) {
+                    current = text.into_bytes();
+                    replaced = true;
+                    shrunk = true;
+                    break;
+                }
+            }
+            if !replaced {
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(current).expect("brainfuck is ASCII")
+}
+
+fn matching_end(code: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, byte) in code.iter().enumerate().skip(start) {
+        match byte {
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            },
+            _ => {}
+        }
+    }
+    None
+}
@@ -1,12 +1,11 @@
 extern crate num_traits;
 
-use num_traits::Signed;
 use super::integer::BrainfuckInteger;
 
 #[derive(Debug)]
-pub enum Instruct<Int: BrainfuckInteger + Signed> {
+pub enum Instruct<Int: BrainfuckInteger> {
     Move(isize),
-    Add(Int),
+    Add(isize),
     Set(Int),
     AddTo(isize),
     SubFrom(isize),
@@ -14,15 +13,17 @@ pub enum Instruct<Int: BrainfuckInteger + Signed> {
     Write,
     LoopStart(usize),
     LoopEnd(usize),
-    WriteStr(Vec<u8>)
+    WriteStr(Vec<u8>),
+    /// print the state of the tape to stderr
+    DebugDump,
 }
 
-impl<Int: BrainfuckInteger + Signed> Clone for Instruct<Int> {
+impl<Int: BrainfuckInteger> Clone for Instruct<Int> {
     fn clone(&self) -> Self {
         match *self {
             Instruct::Move(off)         => Instruct::Move(off),
             Instruct::Add(val)          => Instruct::Add(val),
-            Instruct::Set(val)          => Instruct::Set(val),
+            Instruct::Set(ref val)      => Instruct::Set(val.clone()),
             Instruct::AddTo(off)        => Instruct::AddTo(off),
             Instruct::SubFrom(off)      => Instruct::SubFrom(off),
             Instruct::Read              => Instruct::Read,
@@ -30,6 +31,26 @@ impl<Int: BrainfuckInteger + Signed> Clone for Instruct<Int> {
             Instruct::LoopStart(val)    => Instruct::LoopStart(val),
             Instruct::LoopEnd(val)      => Instruct::LoopEnd(val),
             Instruct::WriteStr(ref val) => Instruct::WriteStr(val.to_vec()),
+            Instruct::DebugDump         => Instruct::DebugDump,
+        }
+    }
+}
+
+// the format used by write_debug()
+impl<Int: BrainfuckInteger> std::fmt::Display for Instruct<Int> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Instruct::Move(off)         => write!(f, "move {}", off),
+            Instruct::Add(val)          => write!(f, "add {}", val),
+            Instruct::Set(ref val)      => write!(f, "set {:?}", val),
+            Instruct::AddTo(off)        => write!(f, "add_to {}", off),
+            Instruct::SubFrom(off)      => write!(f, "sub_from {}", off),
+            Instruct::Read              => f.write_str("read"),
+            Instruct::Write             => f.write_str("write"),
+            Instruct::LoopStart(_)      => f.write_str("loop {"),
+            Instruct::LoopEnd(_)        => f.write_str("}"),
+            Instruct::WriteStr(ref val) => write!(f, "write {:?}", val),
+            Instruct::DebugDump         => f.write_str("debug_dump"),
         }
     }
 }
\ No newline at end of file
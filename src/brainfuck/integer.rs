@@ -1,252 +1,202 @@
+use super::bigint::BigInt;
 
-extern crate num_traits;
-
-use num_traits::{PrimInt, WrappingShl, WrappingAdd};
-
-pub trait BrainfuckInteger: PrimInt + WrappingShl + WrappingAdd + std::fmt::Debug {
+/// Cell type of the brainfuck machine. Cells are either fixed width integers
+/// with wrapping arithmetic or unbounded BigInts.
+pub trait BrainfuckInteger: Clone + PartialEq + std::fmt::Debug + std::str::FromStr {
     fn c_type() -> &'static str;
-    fn get_least_byte(self) -> u8;
+    /// size of a cell in bytes, None for cells without fixed width
+    fn size() -> Option<usize>;
+    fn is_signed() -> bool;
+    fn zero() -> Self;
+    fn is_zero(&self) -> bool;
+    fn get_least_byte(&self) -> u8;
     fn from_byte(value: u8) -> Self;
-    fn wrapping_usize(self) -> usize;
-    fn as_i64(self) -> i64;
-}
+    /// wraps a signed delta (of Add) to the cell width
+    fn from_delta(delta: isize) -> Self;
+    /// the cell value reinterpreted as a signed number of the same width,
+    /// None if it doesn't fit
+    fn to_delta(&self) -> Option<isize>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_sub(&self, other: &Self) -> Self;
+    /// None if the result doesn't fit into the cell
+    fn checked_add_delta(&self, delta: isize) -> Option<Self>;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_sub(&self, other: &Self) -> Option<Self>;
 
-impl BrainfuckInteger for u8 {
     #[inline]
-    fn get_least_byte(self) -> u8 {
-        self
+    fn add_delta(&self, delta: isize) -> Self {
+        self.wrapping_add(&Self::from_delta(delta))
     }
 
+    /// delta wrapped to the cell width, so e.g. 256 on a byte cell becomes 0
     #[inline]
-    fn from_byte(value: u8) -> Self {
-        value
-    }
-
-    fn c_type() -> &'static str {
-        "uint8_t"
+    fn wrap_delta(delta: isize) -> isize {
+        Self::from_delta(delta).to_delta().unwrap_or(delta)
     }
 
-    fn wrapping_usize(self) -> usize {
-        self as usize
-    }
-
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
-}
-
-impl BrainfuckInteger for i8 {
+    /// value a cell gets when reading past the end of input
     #[inline]
-    fn get_least_byte(self) -> u8 {
-        self as u8
-    }
-
-    #[inline]
-    fn from_byte(value: u8) -> Self {
-        value as i8
-    }
-
-    fn c_type() -> &'static str {
-        "int8_t"
-    }
-
-    fn wrapping_usize(self) -> usize {
-        self as usize
-    }
-
-    fn as_i64(self) -> i64 {
-        self as i64
+    fn eof() -> Self {
+        Self::from_delta(-1)
     }
 }
 
-impl BrainfuckInteger for u16 {
-    #[inline]
-    fn get_least_byte(self) -> u8 {
-        (self & 0xFF) as u8
-    }
-
-    #[inline]
-    fn from_byte(value: u8) -> Self {
-        value as u16
-    }
-
-    fn c_type() -> &'static str {
-        "uint16_t"
-    }
-
-    fn wrapping_usize(self) -> usize {
-        self as usize
-    }
-
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
+macro_rules! impl_brainfuck_integer {
+    ($type:ty, $signed:ty, $is_signed:expr, $c_type:expr) => {
+        impl BrainfuckInteger for $type {
+            #[inline]
+            fn c_type() -> &'static str {
+                $c_type
+            }
+
+            #[inline]
+            fn size() -> Option<usize> {
+                Some(std::mem::size_of::<$type>())
+            }
+
+            #[inline]
+            fn is_signed() -> bool {
+                $is_signed
+            }
+
+            #[inline]
+            fn zero() -> Self {
+                0
+            }
+
+            #[inline]
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+
+            #[inline]
+            fn get_least_byte(&self) -> u8 {
+                *self as u8
+            }
+
+            #[inline]
+            fn from_byte(value: u8) -> Self {
+                value as $type
+            }
+
+            #[inline]
+            fn from_delta(delta: isize) -> Self {
+                delta as $type
+            }
+
+            #[inline]
+            fn to_delta(&self) -> Option<isize> {
+                Some(*self as $signed as isize)
+            }
+
+            #[inline]
+            fn wrapping_add(&self, other: &Self) -> Self {
+                <$type>::wrapping_add(*self, *other)
+            }
+
+            #[inline]
+            fn wrapping_sub(&self, other: &Self) -> Self {
+                <$type>::wrapping_sub(*self, *other)
+            }
+
+            #[inline]
+            fn checked_add_delta(&self, delta: isize) -> Option<Self> {
+                <$type as std::convert::TryFrom<i128>>::try_from(*self as i128 + delta as i128).ok()
+            }
+
+            #[inline]
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_add(*self, *other)
+            }
+
+            #[inline]
+            fn checked_sub(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_sub(*self, *other)
+            }
+        }
+    };
 }
 
-impl BrainfuckInteger for i16 {
-    #[inline]
-    fn get_least_byte(self) -> u8 {
-        (self & 0xFF) as u8
-    }
-
-    #[inline]
-    fn from_byte(value: u8) -> Self {
-        value as i16
-    }
-
+impl_brainfuck_integer!(u8,    i8,    false, "uint8_t");
+impl_brainfuck_integer!(i8,    i8,    true,  "int8_t");
+impl_brainfuck_integer!(u16,   i16,   false, "uint16_t");
+impl_brainfuck_integer!(i16,   i16,   true,  "int16_t");
+impl_brainfuck_integer!(u32,   i32,   false, "uint32_t");
+impl_brainfuck_integer!(i32,   i32,   true,  "int32_t");
+impl_brainfuck_integer!(u64,   i64,   false, "uint64_t");
+impl_brainfuck_integer!(i64,   i64,   true,  "int64_t");
+impl_brainfuck_integer!(usize, isize, false, "size_t");
+impl_brainfuck_integer!(isize, isize, true,  "ssize_t");
+
+impl BrainfuckInteger for BigInt {
     fn c_type() -> &'static str {
-        "int16_t"
+        "bigint_t"
     }
 
-    fn wrapping_usize(self) -> usize {
-        self as usize
-    }
-
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
-}
-
-impl BrainfuckInteger for u32 {
     #[inline]
-    fn get_least_byte(self) -> u8 {
-        (self & 0xFF) as u8
+    fn size() -> Option<usize> {
+        None
     }
 
     #[inline]
-    fn from_byte(value: u8) -> Self {
-        value as u32
+    fn is_signed() -> bool {
+        true
     }
 
-    fn c_type() -> &'static str {
-        "uint32_t"
-    }
-
-    fn wrapping_usize(self) -> usize {
-        self as usize
-    }
-
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
-}
-
-impl BrainfuckInteger for i32 {
     #[inline]
-    fn get_least_byte(self) -> u8 {
-        (self & 0xFF) as u8
+    fn zero() -> Self {
+        BigInt::zero()
     }
 
     #[inline]
-    fn from_byte(value: u8) -> Self {
-        value as i32
-    }
-
-    fn c_type() -> &'static str {
-        "int32_t"
+    fn is_zero(&self) -> bool {
+        BigInt::is_zero(self)
     }
 
-    fn wrapping_usize(self) -> usize {
-        self as usize
-    }
-
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
-}
-
-impl BrainfuckInteger for u64 {
     #[inline]
-    fn get_least_byte(self) -> u8 {
-        (self & 0xFF) as u8
+    fn get_least_byte(&self) -> u8 {
+        self.least_byte()
     }
 
     #[inline]
     fn from_byte(value: u8) -> Self {
-        value as u64
-    }
-
-    fn c_type() -> &'static str {
-        "uint64_t"
-    }
-
-    fn wrapping_usize(self) -> usize {
-        self as usize
+        BigInt::from(value as isize)
     }
 
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
-}
-
-impl BrainfuckInteger for i64 {
     #[inline]
-    fn get_least_byte(self) -> u8 {
-        (self & 0xFF) as u8
+    fn from_delta(delta: isize) -> Self {
+        BigInt::from(delta)
     }
 
     #[inline]
-    fn from_byte(value: u8) -> Self {
-        value as i64
-    }
-
-    fn c_type() -> &'static str {
-        "int64_t"
-    }
-
-    fn wrapping_usize(self) -> usize {
-        self as usize
+    fn to_delta(&self) -> Option<isize> {
+        self.to_isize()
     }
 
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
-}
-
-impl BrainfuckInteger for isize {
     #[inline]
-    fn get_least_byte(self) -> u8 {
-        (self & 0xFF) as u8
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self.add(other)
     }
 
     #[inline]
-    fn from_byte(value: u8) -> Self {
-        value as isize
-    }
-
-    fn c_type() -> &'static str {
-        "ssize_t"
-    }
-
-    fn wrapping_usize(self) -> usize {
-        self as usize
+    fn wrapping_sub(&self, other: &Self) -> Self {
+        self.sub(other)
     }
 
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
-}
+    // BigInts never overflow
 
-impl BrainfuckInteger for usize {
     #[inline]
-    fn get_least_byte(self) -> u8 {
-        (self & 0xFF) as u8
+    fn checked_add_delta(&self, delta: isize) -> Option<Self> {
+        Some(self.add(&BigInt::from(delta)))
     }
 
     #[inline]
-    fn from_byte(value: u8) -> Self {
-        value as usize
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self.add(other))
     }
 
-    fn c_type() -> &'static str {
-        "size_t"
-    }
-
-    fn as_i64(self) -> i64 {
-        self as i64
-    }
-
-    fn wrapping_usize(self) -> usize {
-        self as usize
+    #[inline]
+    fn checked_sub(&self, other: &Self) -> Option<Self> {
+        Some(self.sub(other))
     }
 }
@@ -0,0 +1,192 @@
+//! Parser of the text format written by
+//! [`Brainfuck::write_debug`](super::Brainfuck::write_debug):
+//!
+//! ```text
+//! read
+//! loop {
+//!     add_to 1
+//!     set 0
+//! }
+//! move 1
+//! write [72, 105, 10]
+//! ```
+//!
+//! One instruction per line, indentation doesn't matter and `#` starts a
+//! comment. The span of each instruction is its position in the IR text.
+
+use super::{Brainfuck, BrainfuckInteger, Error, Span};
+
+struct Token<'a> {
+    text: &'a str,
+    lineno: usize,
+    column: usize,
+}
+
+fn tokenize(input: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(end) => &line[..end],
+            None => line,
+        };
+        // byte offset and column (counted in chars, like the brainfuck
+        // parser does) of the current word
+        let mut start: Option<(usize, usize)> = None;
+        for (column, (pos, c)) in line.char_indices().enumerate() {
+            let single = "{}[],".contains(c);
+            if c.is_whitespace() || single {
+                if let Some((word, word_column)) = start.take() {
+                    tokens.push(Token { text: &line[word..pos], lineno: index + 1, column: word_column + 1 });
+                }
+                if single {
+                    tokens.push(Token { text: &line[pos..pos + 1], lineno: index + 1, column: column + 1 });
+                }
+            } else if start.is_none() {
+                start = Some((pos, column));
+            }
+        }
+        if let Some((word, word_column)) = start {
+            tokens.push(Token { text: &line[word..], lineno: index + 1, column: word_column + 1 });
+        }
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    index: usize,
+    // line and column after the last token, for errors at the end
+    end: (usize, usize),
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, token: Option<&Token>, message: String) -> Error {
+        let (lineno, column) = match token {
+            Some(token) => (token.lineno, token.column),
+            None        => self.end,
+        };
+        Error::IrSyntax { lineno, column, message }
+    }
+
+    fn next(&mut self) -> Option<&Token<'a>> {
+        let token = self.tokens.get(self.index);
+        if token.is_some() {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.index).map(|token| token.text)
+    }
+
+    fn expect(&mut self, text: &str) -> std::result::Result<(), Error> {
+        let index = self.index;
+        match self.next() {
+            Some(token) if token.text == text => Ok(()),
+            _ => Err(self.error(self.tokens.get(index), format!("expected '{}'", text))),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, expected: &str) -> std::result::Result<T, Error> {
+        let index = self.index;
+        let value = self.next().and_then(|token| token.text.parse().ok());
+        match value {
+            Some(value) => Ok(value),
+            None => {
+                let token = self.tokens.get(index);
+                let message = match token {
+                    Some(token) => format!("expected {}, found '{}'", expected, token.text),
+                    None        => format!("expected {}", expected),
+                };
+                Err(self.error(token, message))
+            }
+        }
+    }
+
+    // move 0 and add 0 would be no-ops, which programs never contain
+    fn non_zero(&mut self, expected: &str) -> std::result::Result<isize, Error> {
+        let index = self.index;
+        let value = self.number(expected)?;
+        if value == 0 {
+            return Err(self.error(self.tokens.get(index), format!("expected {}, found '0'", expected)));
+        }
+        Ok(value)
+    }
+
+    // the bytes of `[72, 105]`, the '[' is already consumed
+    fn bytes(&mut self) -> std::result::Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        if self.peek() == Some("]") {
+            return Err(self.error(self.tokens.get(self.index), "expected a byte".to_string()));
+        }
+        loop {
+            data.push(self.number("a byte")?);
+            match self.peek() {
+                Some(",") => self.index += 1,
+                _ => {
+                    self.expect("]")?;
+                    return Ok(data);
+                }
+            }
+        }
+    }
+}
+
+/// Appends the instructions of the IR text to code.
+pub fn parse<Int: BrainfuckInteger>(code: &mut Brainfuck<Int>, input: &str) -> std::result::Result<(), Error> {
+    let lines = input.lines().count();
+    let last_len = input.lines().last().map(|line| line.chars().count()).unwrap_or(0);
+    let mut parser = Parser { tokens: tokenize(input), index: 0, end: (lines.max(1), last_len + 1) };
+    // spans of the open loops
+    let mut loops: Vec<Span> = Vec::new();
+
+    loop {
+        let index = parser.index;
+        let (name, span) = match parser.next() {
+            Some(token) => (token.text, Span::at(token.lineno, token.column)),
+            None => break,
+        };
+        match name {
+            "move"       => code.push_move(parser.non_zero("a non-zero offset")?, span),
+            "add"        => code.push_add(parser.non_zero("a non-zero number")?, span),
+            "set"        => code.push_set(parser.number("a cell value")?, span),
+            "add_to"     => code.push_add_to(parser.number("an offset")?, span),
+            "sub_from"   => code.push_sub_from(parser.number("an offset")?, span),
+            "read"       => code.push_read(span),
+            "debug_dump" => code.push_debug_dump(span),
+            "write"      => {
+                if parser.peek() == Some("[") {
+                    parser.index += 1;
+                    code.push_write_str(parser.bytes()?, span);
+                } else {
+                    code.push_write(span);
+                }
+            },
+            "loop" => {
+                parser.expect("{")?;
+                loops.push(span);
+                code.push_loop_start(span);
+            },
+            "}" => {
+                if loops.pop().is_none() {
+                    return Err(parser.error(parser.tokens.get(index), "'}' without loop".to_string()));
+                }
+                code.push_loop_end(span);
+            },
+            _ => {
+                return Err(parser.error(parser.tokens.get(index), format!("unknown instruction '{}'", name)));
+            }
+        }
+    }
+
+    if let Some(span) = loops.pop() {
+        return Err(Error::IrSyntax {
+            lineno:  span.start.lineno,
+            column:  span.start.column,
+            message: "loop without '}'".to_string(),
+        });
+    }
+
+    Ok(())
+}
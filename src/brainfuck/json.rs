@@ -0,0 +1,146 @@
+//! JSON export of a program for external tools. Version 1 of the schema:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "cell": {"bits": 8, "signed": true},   bits is null for big cells
+//!   "instructions": [INSTRUCTION...],
+//!   "loops": [LOOP...]
+//! }
+//!
+//! INSTRUCTION:
+//! {
+//!   "index": 0,
+//!   "op": "move" | "add" | "set" | "add_to" | "sub_from" | "read" | "write" |
+//!         "write_str" | "loop_start" | "loop_end" | "debug_dump",
+//!   "offset": 1,          move, add_to and sub_from
+//!   "value": 3,           add (the delta) and set
+//!   "bytes": [72, 105],   write_str
+//!   "end": 7,             loop_start: index of its loop_end
+//!   "start": 2,           loop_end: index of its loop_start
+//!   "span": {"start": {"line": 1, "column": 1}, "end": {...}},
+//!                         only if the instruction comes from the source
+//!   "known": 0            value of the current cell before the instruction
+//!                         as far as the optimizer knows it, otherwise null
+//! }
+//!
+//! LOOP, in order of their loop_start:
+//! {
+//!   "start": 2,           index of the loop_start
+//!   "end": 7,             index of the loop_end
+//!   "depth": 0,           number of enclosing loops
+//!   "parent": null,       index of the loop_start of the enclosing loop
+//!   "if": true,           the body leaves the cell at 0, so it runs at most once
+//!   "entered": null       true or false if it is known whether the body runs
+//! }
+//! ```
+//!
+//! Cell values are JSON numbers, big cells can exceed what a double holds.
+//! Later versions only add keys, a tool should ignore keys it doesn't know.
+
+use std::io::Write;
+use super::{Brainfuck, BrainfuckInteger, Instruct, Span};
+
+pub const JSON_VERSION: u32 = 1;
+
+/// Writes the program as JSON.
+pub fn write_json<Int: BrainfuckInteger>(code: &Brainfuck<Int>, out: &mut dyn Write) -> std::io::Result<()> {
+    write!(out, "{{\"version\":{},\"cell\":{{\"bits\":", JSON_VERSION)?;
+    match Int::size() {
+        Some(size) => write!(out, "{}", size * 8)?,
+        None       => out.write_all(b"null")?,
+    }
+    write!(out, ",\"signed\":{}}},\"instructions\":[", Int::is_signed())?;
+
+    for (index, instr) in code.iter().enumerate() {
+        if index > 0 {
+            out.write_all(b",")?;
+        }
+        write!(out, "\n{{\"index\":{},", index)?;
+        match *instr {
+            Instruct::Move(off)         => write!(out, "\"op\":\"move\",\"offset\":{}", off)?,
+            Instruct::Add(val)          => write!(out, "\"op\":\"add\",\"value\":{}", val)?,
+            Instruct::Set(ref val)      => write!(out, "\"op\":\"set\",\"value\":{:?}", val)?,
+            Instruct::AddTo(off)        => write!(out, "\"op\":\"add_to\",\"offset\":{}", off)?,
+            Instruct::SubFrom(off)      => write!(out, "\"op\":\"sub_from\",\"offset\":{}", off)?,
+            Instruct::Read              => out.write_all(b"\"op\":\"read\"")?,
+            Instruct::Write             => out.write_all(b"\"op\":\"write\"")?,
+            Instruct::WriteStr(ref val) => write!(out, "\"op\":\"write_str\",\"bytes\":{:?}", val)?,
+            // LoopStart points after its LoopEnd
+            Instruct::LoopStart(end)    => write!(out, "\"op\":\"loop_start\",\"end\":{}", end - 1)?,
+            Instruct::LoopEnd(start)    => write!(out, "\"op\":\"loop_end\",\"start\":{}", start)?,
+            Instruct::DebugDump         => out.write_all(b"\"op\":\"debug_dump\"")?,
+        }
+        let span = code.span(index);
+        if !span.is_unknown() {
+            out.write_all(b",\"span\":")?;
+            write_span(out, span)?;
+        }
+        out.write_all(b",\"known\":")?;
+        write_value(out, code.find_set_before(index))?;
+        out.write_all(b"}")?;
+    }
+
+    out.write_all(b"],\"loops\":[")?;
+    let mut stack: Vec<usize> = Vec::new();
+    let mut first = true;
+    for (index, instr) in code.iter().enumerate() {
+        match *instr {
+            Instruct::LoopStart(end) => {
+                if !first {
+                    out.write_all(b",")?;
+                }
+                first = false;
+                let end = end - 1;
+                let is_if = code.find_set_before(end).map(|val| val.is_zero()).unwrap_or(false);
+                let entered = code.find_set_before(index).map(|val| !val.is_zero());
+                write!(out, "\n{{\"start\":{},\"end\":{},\"depth\":{},\"parent\":", index, end, stack.len())?;
+                match stack.last() {
+                    Some(parent) => write!(out, "{}", parent)?,
+                    None         => out.write_all(b"null")?,
+                }
+                write!(out, ",\"if\":{},\"entered\":", is_if)?;
+                match entered {
+                    Some(entered) => write!(out, "{}", entered)?,
+                    None          => out.write_all(b"null")?,
+                }
+                out.write_all(b"}")?;
+                stack.push(index);
+            },
+            Instruct::LoopEnd(_) => {
+                stack.pop();
+            },
+            _ => {}
+        }
+    }
+    out.write_all(b"]}\n")?;
+
+    Ok(())
+}
+
+fn write_value<Int: BrainfuckInteger>(out: &mut dyn Write, value: Option<Int>) -> std::io::Result<()> {
+    match value {
+        Some(value) => write!(out, "{:?}", value),
+        None        => out.write_all(b"null"),
+    }
+}
+
+pub(crate) fn write_span(out: &mut dyn Write, span: Span) -> std::io::Result<()> {
+    write!(out, "{{\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}}}}",
+        span.start.lineno, span.start.column, span.end.lineno, span.end.column)
+}
+
+/// Writes s as a JSON string.
+pub(crate) fn write_str(out: &mut dyn Write, s: &str) -> std::io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"'  => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
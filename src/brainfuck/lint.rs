@@ -0,0 +1,448 @@
+//! Static checks for likely bugs.
+//!
+//! Most checks look at the program after the `fold` pass, so runs like
+//! `+++` are a single instruction whose span covers all of them. Only the
+//! check for commands that cancel out needs the unfolded program. A loop
+//! that is the very first instruction is the usual way to write a comment
+//! header and therefore never reported as dead.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use super::{Brainfuck, BrainfuckInteger, Instruct, Span, TapeOptions, TapeEdge};
+use super::{optimize, json};
+
+pub const LINT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// loop whose cell is always 0 when it is reached
+    DeadLoop,
+    /// loop that never terminates once it is entered
+    InfiniteLoop,
+    /// loop that only terminates for some cell values
+    WrapLoop,
+    /// code after a loop that never terminates
+    Unreachable,
+    /// pointer moves left of the first cell
+    TapeUnderflow,
+    /// commands that cancel each other out, like `+-` or `<>`
+    Noise,
+    /// cell changes that are overwritten before anything reads them
+    DeadStore,
+}
+
+pub const LINTS: [Lint; 7] = [
+    Lint::DeadLoop,
+    Lint::InfiniteLoop,
+    Lint::WrapLoop,
+    Lint::Unreachable,
+    Lint::TapeUnderflow,
+    Lint::Noise,
+    Lint::DeadStore,
+];
+
+impl Lint {
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::DeadLoop      => "dead-loop",
+            Lint::InfiniteLoop  => "infinite-loop",
+            Lint::WrapLoop      => "wrap-loop",
+            Lint::Unreachable   => "unreachable",
+            Lint::TapeUnderflow => "tape-underflow",
+            Lint::Noise         => "noise",
+            Lint::DeadStore     => "dead-store",
+        }
+    }
+}
+
+impl std::str::FromStr for Lint {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match LINTS.iter().find(|lint| lint.name() == s) {
+            Some(lint) => Ok(*lint),
+            None       => Err(format!("unknown lint: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub lint: Lint,
+    pub span: Span,
+    pub message: String,
+}
+
+/// Checks the parsed (not optimized) program. The warnings are sorted by
+/// source position.
+pub fn lint<Int: BrainfuckInteger>(code: &Brainfuck<Int>, tape: &TapeOptions) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    noise(code, &mut warnings);
+
+    let folded = optimize::fold(code, false);
+    let decided = forward(&folded, tape, &mut warnings);
+    loops(&folded, &decided, &mut warnings);
+    dead_stores(&clear_loops(&folded), &mut warnings);
+
+    warnings.sort_by_key(|warning| (warning.span.start, warning.span.end));
+    let mut seen = HashSet::new();
+    warnings.retain(|warning| seen.insert((warning.lint, warning.span)));
+    warnings
+}
+
+// what one iteration of a loop body does, relative to the loop's cell
+struct Body {
+    // sum of the Adds to the loop's cell
+    delta: isize,
+    // the loop's cell is changed by something else than Add
+    clobbered: bool,
+    // offsets of all cells the body might change
+    writes: Vec<isize>,
+    // leftmost cell the body moves to and the instruction doing so
+    min: (isize, usize),
+}
+
+// None if the pointer doesn't end up where it started
+fn loop_body<Int: BrainfuckInteger>(code: &Brainfuck<Int>, start: usize) -> Option<Body> {
+    let mut body = Body { delta: 0, clobbered: false, writes: Vec::new(), min: (0, start) };
+    let mut off = 0isize;
+    let mut index = start + 1;
+
+    loop {
+        match *code.get(index)? {
+            Instruct::Move(val) => {
+                off += val;
+                if off < body.min.0 {
+                    body.min = (off, index);
+                }
+            },
+            Instruct::Add(val) => {
+                if off == 0 {
+                    body.delta = body.delta.wrapping_add(val);
+                }
+                body.writes.push(off);
+            },
+            Instruct::Set(_) | Instruct::Read => {
+                body.clobbered |= off == 0;
+                body.writes.push(off);
+            },
+            Instruct::AddTo(val) | Instruct::SubFrom(val) => {
+                body.clobbered |= off + val == 0;
+                body.writes.push(off + val);
+            },
+            Instruct::LoopStart(end) => {
+                let inner = loop_body(code, index)?;
+                // a nested loop leaves its own cell at 0
+                body.clobbered |= off == 0 || inner.writes.contains(&-off);
+                body.writes.push(off);
+                body.writes.extend(inner.writes.iter().map(|write| write + off));
+                if inner.min.0 + off < body.min.0 {
+                    body.min = (inner.min.0 + off, inner.min.1);
+                }
+                index = end;
+                continue;
+            },
+            Instruct::LoopEnd(_) => {
+                return if off == 0 { Some(body) } else { None };
+            },
+            Instruct::Write | Instruct::WriteStr(_) | Instruct::DebugDump => {},
+        }
+        index += 1;
+    }
+}
+
+// Some(false) if a loop changing its cell by delta per iteration never
+// terminates when entered with value, None if that can't be decided.
+fn terminates<Int: BrainfuckInteger>(value: &Int, delta: isize) -> Option<bool> {
+    let value = value.to_delta()? as i128;
+    let delta = Int::wrap_delta(delta) as i128;
+    if delta == 0 {
+        return Some(value == 0);
+    }
+    if Int::size().is_some() {
+        // with wrapping arithmetic only the common power of two matters
+        let step = 1i128 << delta.trailing_zeros();
+        return Some(value.rem_euclid(step) == 0);
+    }
+    Some(value % delta == 0 && (value == 0 || (value > 0) != (delta > 0)))
+}
+
+// Runs the top level of the program as far as the pointer position is known
+// and reports what depends on the known cell values. Returns the loops that
+// were fully decided that way.
+fn forward<Int: BrainfuckInteger>(code: &Brainfuck<Int>, tape: &TapeOptions, warnings: &mut Vec<Warning>) -> HashSet<usize> {
+    let mut decided = HashSet::new();
+    // cells not in here are still 0, None is an unknown value
+    let mut cells: HashMap<isize, Option<Int>> = HashMap::new();
+    let mut ptr = 0isize;
+    let mut underflow = tape.edge == TapeEdge::Wrap;
+    let mut index = 0;
+
+    while let Some(instr) = code.get(index) {
+        let value = cells.get(&ptr).cloned().unwrap_or_else(|| Some(Int::zero()));
+        match *instr {
+            Instruct::Move(val) => {
+                ptr += val;
+                if ptr < 0 && !underflow {
+                    underflow = true;
+                    warnings.push(Warning {
+                        lint:    Lint::TapeUnderflow,
+                        span:    code.span(index),
+                        message: format!("moves {} cell(s) left of the first cell", -ptr),
+                    });
+                }
+            },
+            Instruct::Add(val) => {
+                cells.insert(ptr, value.map(|value| value.add_delta(val)));
+            },
+            Instruct::Set(ref val) => {
+                cells.insert(ptr, Some(val.clone()));
+            },
+            Instruct::Read => {
+                cells.insert(ptr, None);
+            },
+            Instruct::AddTo(val) | Instruct::SubFrom(val) => {
+                cells.insert(ptr + val, None);
+            },
+            Instruct::LoopStart(end) => {
+                let value = match value {
+                    Some(ref value) if value.is_zero() => {
+                        decided.insert(index);
+                        if index > 0 {
+                            warnings.push(Warning {
+                                lint:    Lint::DeadLoop,
+                                span:    code.span_range(index, end),
+                                message: "loop never runs, the cell is always 0 here".to_string(),
+                            });
+                        }
+                        index = end;
+                        continue;
+                    },
+                    value => value,
+                };
+
+                let body = match loop_body(code, index) {
+                    Some(body) => body,
+                    // nothing is known after this
+                    None => break,
+                };
+
+                if ptr + body.min.0 < 0 && !underflow {
+                    underflow = true;
+                    warnings.push(Warning {
+                        lint:    Lint::TapeUnderflow,
+                        span:    code.span(body.min.1),
+                        message: format!("moves {} cell(s) left of the first cell", -(ptr + body.min.0)),
+                    });
+                }
+
+                if let (Some(value), false) = (value, body.clobbered) {
+                    decided.insert(index);
+                    if terminates(&value, body.delta) == Some(false) {
+                        warnings.push(Warning {
+                            lint:    Lint::InfiniteLoop,
+                            span:    code.span_range(index, end),
+                            message: format!("loop never terminates, the cell is {:?} when it is entered and changes by {} per iteration",
+                                value, Int::wrap_delta(body.delta)),
+                        });
+                        if end < code.len() {
+                            warnings.push(Warning {
+                                lint:    Lint::Unreachable,
+                                span:    code.span_range(end, code.len()),
+                                message: "code is never reached because of the loop before".to_string(),
+                            });
+                        }
+                        break;
+                    }
+                }
+
+                for write in &body.writes {
+                    cells.insert(ptr + write, None);
+                }
+                cells.insert(ptr, Some(Int::zero()));
+                index = end;
+                continue;
+            },
+            Instruct::LoopEnd(_) | Instruct::Write | Instruct::WriteStr(_) | Instruct::DebugDump => {},
+        }
+        index += 1;
+    }
+
+    decided
+}
+
+// checks of every loop that don't need to know the cell values
+fn loops<Int: BrainfuckInteger>(code: &Brainfuck<Int>, decided: &HashSet<usize>, warnings: &mut Vec<Warning>) {
+    for (index, instr) in code.iter().enumerate() {
+        let end = match *instr {
+            Instruct::LoopStart(end) => end,
+            _ => continue,
+        };
+        if decided.contains(&index) {
+            continue;
+        }
+        let span = code.span_range(index, end);
+
+        if code.find_set_before(index).map(|value| value.is_zero()).unwrap_or(false) {
+            warnings.push(Warning {
+                lint:    Lint::DeadLoop,
+                span,
+                message: "loop never runs, the cell is always 0 here".to_string(),
+            });
+            continue;
+        }
+
+        let body = match loop_body(code, index) {
+            Some(body) if !body.clobbered => body,
+            _ => continue,
+        };
+        let delta = Int::wrap_delta(body.delta);
+        if delta == 0 {
+            warnings.push(Warning {
+                lint:    Lint::InfiniteLoop,
+                span,
+                message: "loop never terminates once it is entered, nothing changes its cell".to_string(),
+            });
+        } else if Int::size().is_some() {
+            let step = 1u128 << delta.trailing_zeros();
+            if step > 1 {
+                warnings.push(Warning {
+                    lint:    Lint::WrapLoop,
+                    span,
+                    message: format!("loop only terminates if the cell is a multiple of {} when it is entered, it changes by {} per iteration",
+                        step, delta),
+                });
+            }
+        } else if delta.abs() > 1 {
+            warnings.push(Warning {
+                lint:    Lint::WrapLoop,
+                span,
+                message: format!("loop only terminates if the cell is a {} multiple of {} when it is entered, it changes by {} per iteration",
+                    if delta > 0 { "non-positive" } else { "non-negative" }, delta.abs(), delta),
+            });
+        }
+    }
+}
+
+// Like the set pass, but only for loops like [-] that certainly terminate.
+// The set pass turns e.g. [--] into a set too, which would make the change
+// before a loop that never terminates look like a dead store.
+fn clear_loops<Int: BrainfuckInteger>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
+    let mut cleared = Brainfuck::new();
+    let mut index = 0;
+    while let Some(instr) = code.get(index) {
+        if let (Instruct::LoopStart(_), Some(Instruct::Add(val)), Some(Instruct::LoopEnd(_))) =
+                (instr, code.get(index + 1), code.get(index + 2)) {
+            if Int::size().is_some() && Int::wrap_delta(*val) % 2 != 0 {
+                cleared.push_set(Int::zero(), code.span_range(index, index + 3));
+                index += 3;
+                continue;
+            }
+        }
+        cleared.push(instr, code.span(index));
+        index += 1;
+    }
+    cleared
+}
+
+fn dead_stores<Int: BrainfuckInteger>(code: &Brainfuck<Int>, warnings: &mut Vec<Warning>) {
+    let mut run: Option<Span> = None;
+    for index in 0..=code.len() {
+        let dead = match code.get(index) {
+            None => false,
+            Some(instr) => match *instr {
+                Instruct::Add(_) | Instruct::Set(_) => optimize::has_set_after(code, 0, index + 1),
+                Instruct::AddTo(off) | Instruct::SubFrom(off) => optimize::has_set_after(code, off, index + 1),
+                _ => false,
+            },
+        };
+        if dead {
+            run = Some(run.map(|span| span.join(code.span(index))).unwrap_or_else(|| code.span(index)));
+        } else if let Some(span) = run.take() {
+            warnings.push(Warning {
+                lint:    Lint::DeadStore,
+                span,
+                message: "the cell is overwritten before this change is used".to_string(),
+            });
+        }
+    }
+}
+
+// runs of +- or <> that partially cancel out
+fn noise<Int: BrainfuckInteger>(code: &Brainfuck<Int>, warnings: &mut Vec<Warning>) {
+    let mut index = 0;
+    while let Some(instr) = code.get(index) {
+        let start = index;
+        let delta = |instr: &Instruct<Int>| match *instr {
+            Instruct::Add(val)  => Some((val, "+-")),
+            Instruct::Move(val) => Some((val, "<>")),
+            _ => None,
+        };
+        let kind = match delta(instr) {
+            Some((_, kind)) => kind,
+            None => {
+                index += 1;
+                continue;
+            }
+        };
+
+        let mut sum = 0isize;
+        let mut count = 0isize;
+        let mut signs = (false, false);
+        while let Some((val, other)) = code.get(index).and_then(delta) {
+            if other != kind {
+                break;
+            }
+            sum += val;
+            count += val.abs();
+            signs = (signs.0 || val > 0, signs.1 || val < 0);
+            index += 1;
+        }
+
+        if signs.0 && signs.1 {
+            warnings.push(Warning {
+                lint:    Lint::Noise,
+                span:    code.span_range(start, index),
+                message: format!("{} of these {} '{}' commands cancel each other out", count - sum.abs(), count, kind),
+            });
+        }
+    }
+}
+
+/// Prints the warnings like compiler messages, with the source line if the
+/// source is given.
+pub fn write_text(warnings: &[Warning], out: &mut dyn Write, input: &str, source: Option<&str>) -> std::io::Result<()> {
+    for warning in warnings {
+        writeln!(out, "warning:{}:{}: {} [{}]", input, warning.span, warning.message, warning.lint.name())?;
+        if let Some(line) = source.and_then(|source| source.lines().nth(warning.span.start.lineno - 1)) {
+            warning.span.write_snippet(out, line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the warnings as a JSON object, with spans like in the JSON export
+/// of programs:
+///
+/// ```text
+/// {"version":2,"file":"prog.bf","warnings":[{"lint":"dead-loop",
+///  "span":{"start":{"line":1,"column":5},"end":{"line":1,"column":9}},
+///  "message":"..."}]}
+/// ```
+pub fn write_json(warnings: &[Warning], out: &mut dyn Write, input: &str) -> std::io::Result<()> {
+    write!(out, "{{\"version\":{},\"file\":", LINT_VERSION)?;
+    json::write_str(out, input)?;
+    out.write_all(b",\"warnings\":[")?;
+    for (index, warning) in warnings.iter().enumerate() {
+        if index > 0 {
+            out.write_all(b",")?;
+        }
+        write!(out, "\n{{\"lint\":\"{}\",\"span\":", warning.lint.name())?;
+        json::write_span(out, warning.span)?;
+        out.write_all(b",\"message\":")?;
+        json::write_str(out, &warning.message)?;
+        out.write_all(b"}")?;
+    }
+    out.write_all(b"]}\n")?;
+    Ok(())
+}
@@ -1,198 +1,387 @@
 pub mod codegen;
 pub mod optimize;
-pub mod error;
-pub mod instruct;
-pub mod integer;
-pub mod indent;
+mod error;
+mod instruct;
+mod integer;
+mod bigint;
+mod indent;
+mod span;
+mod tape;
+mod debugger;
+mod trace;
+mod profile;
+mod coverage;
+mod lint;
+mod cst;
+mod fmt;
+mod ir;
+mod ook;
+mod bytecode;
+mod json;
+mod dot;
+pub mod fuzz;
+mod verify;
+pub mod command;
 
 extern crate num_traits;
 
 use std::io::{Read, Write};
-use num_traits::Signed;
 pub use integer::BrainfuckInteger;
+pub use bigint::BigInt;
 pub use error::Error;
 pub use instruct::Instruct;
+pub use span::Span;
+pub use cst::Cst;
+pub use tape::{Tape, TapeOptions, TapeEdge, DEFAULT_TAPE_SIZE, MAX_TAPE_SIZE};
 use indent::indent;
+use codegen::generate_bf_const::{generate_bf_const, bf_const_len, write_add, write_move, ConstOptions};
 
-pub struct Brainfuck<Int: BrainfuckInteger + Signed> {
+/// Options of the parser.
+pub struct ParseOptions {
+    /// Character that is parsed as [`Instruct::DebugDump`], e.g. `'#'`.
+    pub debug_char: Option<char>,
+    pub format: InputFormat,
+}
+
+impl std::default::Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            debug_char: None,
+            format:     InputFormat::Brainfuck,
+        }
+    }
+}
+
+/// Syntax of the parsed source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Brainfuck,
+    /// the text written by [`Brainfuck::write_debug`]
+    Ir,
+    /// the Ook! dialect, a pair of the words `Ook.`, `Ook?` and `Ook!` per
+    /// command
+    Ook,
+}
+
+impl std::str::FromStr for InputFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "brainfuck" => Ok(InputFormat::Brainfuck),
+            "ir"        => Ok(InputFormat::Ir),
+            "ook"       => Ok(InputFormat::Ook),
+            _           => Err(()),
+        }
+    }
+}
+
+/// Options of the interpreter.
+#[derive(Clone, Default)]
+pub struct ExecOptions {
+    pub tape: TapeOptions,
+    /// Stop with [`Error::CellOverflow`] when a cell over- or underflows.
+    pub trap_overflow: bool,
+    /// Stop with [`Error::StepLimitExceeded`] after this many instructions.
+    pub max_steps: Option<u64>,
+    /// Stop with [`Error::TapeLimitExceeded`] before a growing tape would use
+    /// more cells than this.
+    pub max_tape: Option<usize>,
+    /// Stop with [`Error::Timeout`] after running this long.
+    pub timeout: Option<std::time::Duration>,
+}
+
+/// A parsed (and possibly optimized) brainfuck program.
+///
+/// Each instruction knows the source span it was generated from, so errors,
+/// traces and profiles of optimized programs still point at the source.
+pub struct Brainfuck<Int: BrainfuckInteger> {
     code: Vec<Instruct<Int>>,
+    spans: Vec<Span>,
     loop_stack: Vec<usize>,
     phantom: std::marker::PhantomData<Int>
 }
 
-impl<Int: BrainfuckInteger + Signed> Clone for Brainfuck<Int> {
+/// Interpreter state between two steps.
+pub struct ExecState<Int: BrainfuckInteger> {
+    pub tape: Tape<Int>,
+    /// Index of the next instruction to execute.
+    pub pc: usize,
+    need_flush: bool,
+    steps: u64,
+    started: std::time::Instant,
+}
+
+impl<Int: BrainfuckInteger> ExecState<Int> {
+    pub fn new(options: &ExecOptions) -> Self {
+        ExecState {
+            tape: Tape::new(options.tape, Int::zero()),
+            pc: 0,
+            need_flush: false,
+            steps: 0,
+            started: std::time::Instant::now(),
+        }
+    }
+
+    /// Number of executed instructions.
+    #[inline]
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// True if the last output of the program didn't end in a newline.
+    #[inline]
+    pub fn pending_line(&self) -> bool {
+        self.need_flush
+    }
+}
+
+impl<Int: BrainfuckInteger> Clone for Brainfuck<Int> {
     fn clone(&self) -> Self {
         Brainfuck {
             code: self.code.to_vec(),
+            spans: self.spans.to_vec(),
             loop_stack: self.loop_stack.to_vec(),
             phantom: std::marker::PhantomData,
         }
     }
 }
 
-impl<Int: BrainfuckInteger + Signed> Brainfuck<Int> {
+impl<Int: BrainfuckInteger> std::default::Default for Brainfuck<Int> {
+    fn default() -> Self {
+        Brainfuck::new()
+    }
+}
+
+impl<Int: BrainfuckInteger> Brainfuck<Int> {
+    /// An empty program.
     pub fn new() -> Brainfuck<Int> {
         Brainfuck {
             code: vec![],
+            spans: vec![],
             loop_stack: Vec::<usize>::new(),
             phantom: std::marker::PhantomData
         }
     }
 
-    pub fn from_file(filename: &str) -> std::result::Result<Self, Error> {
+    /// Reads and parses a source file.
+    pub fn from_file(filename: &str, options: &ParseOptions) -> std::result::Result<Self, Error> {
         let code = std::fs::read_to_string(filename)?;
-        Brainfuck::<Int>::from_str(&code)
+        Brainfuck::<Int>::from_str(&code, options)
     }
 
-    pub fn from_str(input: &str) -> std::result::Result<Self, Error> {
+    /// Parses source in the format of the options. Fails on unmatched
+    /// brackets, malformed IR or unknown Ook! pairs.
+    pub fn from_str(input: &str, options: &ParseOptions) -> std::result::Result<Self, Error> {
         let mut code = Self::new();
-        code.parse(input)?;
+        code.parse(input, options)?;
         Ok(code)
     }
 
-    pub fn iter(&self) -> std::slice::Iter<Instruct<Int>> {
+    /// The instructions of the program.
+    pub fn iter(&self) -> std::slice::Iter<'_, Instruct<Int>> {
         self.code.iter()
     }
 
+    /// Number of instructions.
     #[inline]
     pub fn len(&self) -> usize {
         self.code.len()
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
     #[inline]
     pub fn get(&self, index: usize) -> Option<&Instruct<Int>> {
         self.code.get(index)
     }
 
-    pub fn parse(&mut self, input: &str) -> std::result::Result<(), Error> {
-        let mut it = input.chars();
-        let mut lineno: usize = 1;
-        let mut column: usize = 1;
-        let mut begin_lineno: usize = 0;
-        let mut begin_column: usize = 0;
+    /// Source span of the instruction at index.
+    #[inline]
+    pub fn span(&self, index: usize) -> Span {
+        self.spans.get(index).cloned().unwrap_or_default()
+    }
 
-        loop {
-            if let Some(c) = it.next() {
-                match c {
-                    '<' => {
-                        self.push_move(-1);
-                        column += 1;
-                    },
-                    '>' => {
-                        self.push_move(1);
-                        column += 1;
-                    },
-                    '-' => {
-                        self.push_add(-Int::one());
-                        column += 1;
-                    },
-                    '+' => {
-                        self.push_add(Int::one());
-                        column += 1;
-                    },
-                    '[' => {
-                        self.push_loop_start();
-                        begin_lineno = lineno;
-                        begin_column = column;
-                        column += 1;
-                    },
-                    ']' => {
-                        if self.loop_stack.len() == 0 {
-                            return Err(Error::UnmatchedLoopEnd { lineno, column });
-                        }
-                        self.push_loop_end();
-                        column += 1;
-                    },
-                    '.' => {
-                        self.push_write();
-                        column += 1;
-                    },
-                    ',' => {
-                        self.push_read();
-                        column += 1;
-                    },
-                    '\n' => {
-                        column = 1;
-                        lineno += 1;
-                    }
-                    _ => {
-                        column += 1;
-                    }
-                }
-            } else {
-                break;
+    /// Joined source span of the instructions in `start..end`.
+    pub fn span_range(&self, start: usize, end: usize) -> Span {
+        let end = std::cmp::min(end, self.spans.len());
+        let mut span = Span::unknown();
+        if start < end {
+            for other in &self.spans[start..end] {
+                span = span.join(*other);
             }
         }
+        span
+    }
 
-        if self.loop_stack.len() > 0 {
-            return Err(Error::UnmatchedLoopStart { lineno: begin_lineno, column: begin_column });
+    /// Appends the parsed source to the program.
+    pub fn parse(&mut self, input: &str, options: &ParseOptions) -> std::result::Result<(), Error> {
+        match options.format {
+            InputFormat::Ir  => return ir::parse(self, input),
+            InputFormat::Ook => return ook::parse(self, input),
+            InputFormat::Brainfuck => {},
         }
-
+        let cst = Cst::parse(input, options)?;
+        self.push_nodes(&cst.nodes);
         Ok(())
     }
 
-    pub fn push_move(&mut self, val: isize) {
+    fn push_nodes(&mut self, nodes: &[cst::Node]) {
+        for node in nodes {
+            match *node {
+                cst::Node::Command('<', span) => self.push_move(-1, span),
+                cst::Node::Command('>', span) => self.push_move(1, span),
+                cst::Node::Command('-', span) => self.push_add(-1, span),
+                cst::Node::Command('+', span) => self.push_add(1, span),
+                cst::Node::Command('.', span) => self.push_write(span),
+                cst::Node::Command(',', span) => self.push_read(span),
+                // the debug char
+                cst::Node::Command(_, span)   => self.push_debug_dump(span),
+                cst::Node::Loop(ref body)     => {
+                    self.push_loop_start(body.start);
+                    self.push_nodes(&body.body);
+                    self.push_loop_end(body.end);
+                },
+                cst::Node::Text(..) | cst::Node::Newline => {},
+            }
+        }
+    }
+
+    /// Appends an instruction generated from span. The `push_*` methods are
+    /// used to build programs, e.g. by the optimizer. Jump targets of loops
+    /// are filled in by `push_loop_end`, which panics without a matching
+    /// `push_loop_start`.
+    pub fn push_move(&mut self, val: isize, span: Span) {
         self.code.push(Instruct::Move(val));
+        self.spans.push(span);
     }
 
-    pub fn push_add(&mut self, val: Int) {
+    pub fn push_add(&mut self, val: isize, span: Span) {
         self.code.push(Instruct::Add(val));
+        self.spans.push(span);
     }
 
-    pub fn push_set(&mut self, val: Int) {
+    pub fn push_set(&mut self, val: Int, span: Span) {
         self.code.push(Instruct::Set(val));
+        self.spans.push(span);
     }
 
-    pub fn push_add_to(&mut self, val: isize) {
+    pub fn push_add_to(&mut self, val: isize, span: Span) {
         self.code.push(Instruct::AddTo(val));
+        self.spans.push(span);
     }
 
-    pub fn push_sub_from(&mut self, val: isize) {
+    pub fn push_sub_from(&mut self, val: isize, span: Span) {
         self.code.push(Instruct::SubFrom(val));
+        self.spans.push(span);
     }
 
-    pub fn push_read(&mut self) {
+    pub fn push_read(&mut self, span: Span) {
         self.code.push(Instruct::Read);
+        self.spans.push(span);
     }
 
-    pub fn push_write(&mut self) {
+    pub fn push_write(&mut self, span: Span) {
         self.code.push(Instruct::Write);
+        self.spans.push(span);
     }
 
-    pub fn push_loop_start(&mut self) {
+    pub fn push_loop_start(&mut self, span: Span) {
         self.loop_stack.push(self.code.len());
-        self.code.push(Instruct::LoopStart(std::usize::MAX));
+        self.code.push(Instruct::LoopStart(usize::MAX));
+        self.spans.push(span);
     }
 
-    pub fn push_loop_end(&mut self) {
+    pub fn push_loop_end(&mut self, span: Span) {
         let ptr = self.loop_stack.pop().expect("unmatched ']'");
         self.code.push(Instruct::LoopEnd(ptr));
+        self.spans.push(span);
         let end_ptr = self.code.len();
-        std::mem::replace(&mut self.code[ptr], Instruct::LoopStart(end_ptr));
+        self.code[ptr] = Instruct::LoopStart(end_ptr);
     }
 
-    pub fn push_write_str(&mut self, val: Vec<u8>) {
+    pub fn push_write_str(&mut self, val: Vec<u8>, span: Span) {
         self.code.push(Instruct::WriteStr(val));
+        self.spans.push(span);
+    }
+
+    pub fn push_debug_dump(&mut self, span: Span) {
+        self.code.push(Instruct::DebugDump);
+        self.spans.push(span);
     }
 
-    pub fn push(&mut self, instr: &Instruct<Int>) {
+    /// Appends a copy of instr. Loop jump targets are recomputed.
+    pub fn push(&mut self, instr: &Instruct<Int>, span: Span) {
         match instr {
-            Instruct::Move(off)     => self.push_move(*off),
-            Instruct::Add(val)      => self.push_add(*val),
-            Instruct::Set(val)      => self.push_set(*val),
-            Instruct::AddTo(off)    => self.push_add_to(*off),
-            Instruct::SubFrom(off)  => self.push_sub_from(*off),
-            Instruct::Read          => self.push_read(),
-            Instruct::Write         => self.push_write(),
-            Instruct::LoopStart(_)  => self.push_loop_start(),
-            Instruct::LoopEnd(_)    => self.push_loop_end(),
-            Instruct::WriteStr(val) => self.push_write_str(val.to_vec())
+            Instruct::Move(off)     => self.push_move(*off, span),
+            Instruct::Add(val)      => self.push_add(*val, span),
+            Instruct::Set(val)      => self.push_set(val.clone(), span),
+            Instruct::AddTo(off)    => self.push_add_to(*off, span),
+            Instruct::SubFrom(off)  => self.push_sub_from(*off, span),
+            Instruct::Read          => self.push_read(span),
+            Instruct::Write         => self.push_write(span),
+            Instruct::LoopStart(_)  => self.push_loop_start(span),
+            Instruct::LoopEnd(_)    => self.push_loop_end(span),
+            Instruct::WriteStr(val) => self.push_write_str(val.to_vec(), span),
+            Instruct::DebugDump     => self.push_debug_dump(span),
         }
     }
 
-    pub fn find_set_before(&self, mut index: usize) -> Option<Int> {
+    /// Checks the invariants the optimizer and the code generators rely on:
+    /// every loop is closed, `LoopStart` and `LoopEnd` point at each other,
+    /// there is a span per instruction and no `Move(0)`, `Add(0)` or empty
+    /// `WriteStr` is left behind. Fails with [`Error::MalformedProgram`].
+    pub fn verify(&self) -> std::result::Result<(), Error> {
+        let malformed = |index: usize, message: String| Err(Error::MalformedProgram { pass: None, index, message });
+        if self.spans.len() != self.code.len() {
+            return malformed(self.code.len(), format!("{} instructions but {} spans", self.code.len(), self.spans.len()));
+        }
+        if let Some(&index) = self.loop_stack.first() {
+            return malformed(index, "loop is not closed".to_string());
+        }
+
+        let mut loops: Vec<usize> = Vec::new();
+        for (index, instr) in self.code.iter().enumerate() {
+            match *instr {
+                Instruct::Move(0) => return malformed(index, "move 0".to_string()),
+                Instruct::Add(0)  => return malformed(index, "add 0".to_string()),
+                Instruct::WriteStr(ref data) if data.is_empty() => return malformed(index, "empty write".to_string()),
+                Instruct::LoopStart(end) => {
+                    // points after its LoopEnd
+                    match self.code.get(end.wrapping_sub(1)) {
+                        Some(Instruct::LoopEnd(start)) if end > index + 1 && *start == index => {},
+                        _ => return malformed(index, format!("loop start points to {}, which is no matching loop end", end)),
+                    }
+                    loops.push(index);
+                },
+                // the guard pops the innermost loop
+                Instruct::LoopEnd(start) if loops.pop() != Some(start) => {
+                    return malformed(index, format!("loop end points to {}, which is not the innermost loop start", start));
+                },
+                _ => {}
+            }
+        }
+        if let Some(index) = loops.pop() {
+            return malformed(index, "loop start without loop end".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn find_set_before(&self, index: usize) -> Option<Int> {
+        self.find_set_before_with(index, |_, _| false)
+    }
+
+    // clobbered(index, ptr) tells if the instruction at index destroys the
+    // cell at ptr (relative to the pointer at that instruction) without the
+    // program saying so
+    fn find_set_before_with<F>(&self, mut index: usize, clobbered: F) -> Option<Int>
+        where F: Fn(usize, isize) -> bool {
         if index >= self.len() {
-            if self.len() == 0 {
+            if self.is_empty() {
                 return None;
             }
             index = self.len() - 1;
@@ -200,10 +389,13 @@ impl<Int: BrainfuckInteger + Signed> Brainfuck<Int> {
         let mut ptr = 0isize;
         while index > 0 {
             index -= 1;
+            if clobbered(index, ptr) {
+                return None;
+            }
             match self.code[index] {
-                Instruct::Set(val) => {
+                Instruct::Set(ref val) => {
                     if ptr == 0 {
-                        return Some(val);
+                        return Some(val.clone());
                     }
                 },
                 Instruct::Move(off) => {
@@ -214,9 +406,12 @@ impl<Int: BrainfuckInteger + Signed> Brainfuck<Int> {
                         return None;
                     }
                 },
-                Instruct::AddTo(_) | Instruct::SubFrom(_) => {
-                    // XXX: why does it break when I restrict this return to if ptr + off == 0?
-                    return None;
+                // off is relative to the pointer at the instruction, just like
+                // ptr, so the target is our cell when they are equal
+                Instruct::AddTo(off) | Instruct::SubFrom(off) => {
+                    if off == ptr {
+                        return None;
+                    }
                 },
                 Instruct::LoopStart(_) => return None,
                 // TODO: if loop doesn't move ptr overall and isn't touching *ptr it can be skipped
@@ -226,363 +421,771 @@ impl<Int: BrainfuckInteger + Signed> Brainfuck<Int> {
                     }
                     return None;
                 },
-                Instruct::Write | Instruct::WriteStr(_) => {},
+                Instruct::Write | Instruct::WriteStr(_) | Instruct::DebugDump => {},
             }
         }
-        return None;
+        None
     }
 
+    /// Returns the optimized program. The output of the code evaluated by
+    /// constexpr is echoed to stdout if `options.constexpr_echo` is set.
+    #[inline]
     pub fn optimize(&self, options: optimize::Options) -> std::io::Result<Self> {
-        let mut code = if options.fold { optimize::fold(self) } else { self.clone() };
-        if options.set      { code = optimize::set(&code); }
-        if options.add_to   { code = optimize::add_to(&code); }
-        if options.write    { code = optimize::write(&code); }
-        if options.deadcode { code = optimize::deadcode(&code); }
-        if options.fold     { code = optimize::fold(&code); }
-        if options.skip     { code = optimize::skip(&code); }
-        if options.constexpr {
-            code = optimize::constexpr(&code, options.constexpr_echo)?;
+        self.optimize_with(options, &mut std::io::stdout())
+    }
+
+    /// Like optimize, but echoes to echo instead of stdout.
+    pub fn optimize_with(&self, options: optimize::Options, echo: &mut impl Write) -> std::io::Result<Self> {
+        self.optimize_inspect(options, echo, &mut |_, _, _| {})
+    }
 
-            if options.fold     { code = optimize::fold(&code); }
-            if options.set      { code = optimize::set(&code); }
-            if options.add_to   { code = optimize::add_to(&code); }
-            if options.write    { code = optimize::write(&code); }
-            if options.deadcode { code = optimize::deadcode(&code); }
-            if options.fold     { code = optimize::fold(&code); }
-            if options.skip     { code = optimize::skip(&code); }
+    /// Like optimize_with, but calls inspect with the name of the pass, the
+    /// program before and the program after every pass that runs. Some
+    /// passes run more than once.
+    pub fn optimize_inspect(&self, options: optimize::Options, echo: &mut impl Write, inspect: &mut dyn FnMut(&str, &Self, &Self)) -> std::io::Result<Self> {
+        let mut code = self.clone();
+        let trap = options.trap_overflow;
+        let mut run = |code: &mut Self, name: &str, pass: &dyn Fn(&Self) -> Self| {
+            let new_code = pass(code);
+            // release builds only check this with --verify-ir
+            if cfg!(debug_assertions) {
+                if let Err(err) = new_code.verify() {
+                    panic!("after optimization pass {}: {}", name, err);
+                }
+            }
+            inspect(name, code, &new_code);
+            *code = new_code;
+        };
+        if options.fold     { run(&mut code, "fold", &|code| optimize::fold(code, trap)); }
+        if options.set      { run(&mut code, "set", &|code| optimize::set(code, trap)); }
+        if options.add_to   { run(&mut code, "add_to", &|code| optimize::add_to(code, trap)); }
+        if options.write    { run(&mut code, "write", &optimize::write); }
+        if options.deadcode { run(&mut code, "deadcode", &optimize::deadcode); }
+        if options.fold     { run(&mut code, "fold", &|code| optimize::fold(code, trap)); }
+        if options.skip     { run(&mut code, "skip", &|code| optimize::skip(code, trap)); }
+        if options.constexpr {
+            let echo = if options.constexpr_echo { Some(echo) } else { None };
+            let new_code = optimize::constexpr(&code, echo, &options.tape, options.trap_overflow)?;
+            run(&mut code, "constexpr", &|_| new_code.clone());
+
+            if options.fold     { run(&mut code, "fold", &|code| optimize::fold(code, trap)); }
+            if options.set      { run(&mut code, "set", &|code| optimize::set(code, trap)); }
+            if options.add_to   { run(&mut code, "add_to", &|code| optimize::add_to(code, trap)); }
+            if options.write    { run(&mut code, "write", &optimize::write); }
+            if options.deadcode { run(&mut code, "deadcode", &optimize::deadcode); }
+            if options.fold     { run(&mut code, "fold", &|code| optimize::fold(code, trap)); }
+            if options.skip     { run(&mut code, "skip", &|code| optimize::skip(code, trap)); }
         }
-        return Ok(code);
+        Ok(code)
+    }
+
+    /// Runs the program using stdin and stdout, debug dumps go to stderr.
+    pub fn exec(&self, options: &ExecOptions) -> std::result::Result<(), Error> {
+        self.exec_with(&mut std::io::stdin(), &mut std::io::stdout(), &mut std::io::stderr(), options.clone())?;
+        Ok(())
     }
 
-    pub fn exec(&self) -> std::io::Result<()> {
-        let mut mem = Vec::<Int>::new();
-        let mut ptr = 0usize;
-        let mut pc  = 0usize;
-        let mut need_flush = false;
+    /// Runs the program reading from input and writing to output, the cells
+    /// shown by [`Instruct::DebugDump`] are written to dump. Returns the state
+    /// of the machine at the end of the program.
+    pub fn exec_with(&self, input: &mut impl Read, output: &mut impl Write, dump: &mut impl Write, options: ExecOptions) -> std::result::Result<ExecState<Int>, Error> {
+        let mut state = ExecState::new(&options);
+        while self.step_with(&mut state, &options, input, output, dump)? {}
+        Ok(state)
+    }
 
+    /// Like exec, but records every executed instruction.
+    pub fn exec_trace<W: Write>(&self, options: &ExecOptions, tracer: &mut trace::Tracer<W, Int>) -> std::result::Result<(), Error> {
+        let mut state = ExecState::<Int>::new(options);
         loop {
-            if let Some(instr) = self.code.get(pc) {
-                match *instr {
-                    Instruct::Move(off) => {
-                        pc += 1;
-                        if -(ptr as isize) > off {
-                            let diff = (-(ptr as isize) - off) as usize;
-                            let chunk = vec![Int::zero(); diff];
-                            mem.splice(..0, chunk);
-                            ptr += diff;
-                        }
-                        ptr = ((ptr as isize) + off) as usize;
-                    },
+            let pc = state.pc;
+            let ptr = state.tape.pos();
+            let before = state.tape.get(ptr).clone();
+            if !self.step(&mut state, options)? {
+                break;
+            }
+            tracer.record(state.steps, pc, self.span(pc), ptr, &before, state.tape.get(ptr))?;
+        }
+        Ok(())
+    }
 
-                    Instruct::Add(val) => {
-                        pc += 1;
-                        if ptr >= mem.len() {
-                            mem.resize(ptr + 1, Int::zero());
-                        }
-                        mem[ptr] = mem[ptr].wrapping_add(&val);
-                    },
+    /// Like exec, but counts how often each instruction is executed.
+    pub fn exec_profile(&self, options: &ExecOptions, profile: &mut profile::Profile) -> std::result::Result<(), Error> {
+        let mut state = ExecState::<Int>::new(options);
+        loop {
+            let pc = state.pc;
+            if !self.step(&mut state, options)? {
+                break;
+            }
+            profile.count(pc);
+        }
+        Ok(())
+    }
 
-                    Instruct::Set(val) => {
-                        pc += 1;
-                        if ptr >= mem.len() {
-                            mem.resize(ptr + 1, Int::zero());
-                        }
-                        mem[ptr] = val;
-                    },
-
-                    Instruct::AddTo(off) => {
-                        pc += 1;
-                        if let Some(val) = mem.get(ptr) {
-                            let val = *val;
-                            if val != Int::zero() {
-                                if -(ptr as isize) > off {
-                                    let diff = (-(ptr as isize) - off) as usize;
-                                    let chunk = vec![Int::zero(); diff];
-                                    mem.splice(..0, chunk);
-                                    ptr += diff;
-                                    mem[0] = val;
-                                } else {
-                                    let target_ptr = (ptr as isize + off) as usize;
-                                    if target_ptr >= mem.len() {
-                                        mem.resize(target_ptr + 1, Int::zero());
-                                    }
-                                    mem[target_ptr] = mem[target_ptr].wrapping_add(&val);
+    /// Executes the instruction at `state.pc` using stdin and stdout, debug
+    /// dumps go to stderr. Returns false if the program already ended.
+    #[inline]
+    pub fn step(&self, state: &mut ExecState<Int>, options: &ExecOptions) -> std::result::Result<bool, Error> {
+        self.step_with(state, options, &mut std::io::stdin(), &mut std::io::stdout(), &mut std::io::stderr())
+    }
+
+    /// Like step, but with the given input, output and writer for debug dumps.
+    pub fn step_with<R: Read + ?Sized, W: Write + ?Sized, D: Write>(
+            &self, state: &mut ExecState<Int>, options: &ExecOptions, input: &mut R, output: &mut W, dump: &mut D)
+            -> std::result::Result<bool, Error> {
+        let instr = match self.code.get(state.pc) {
+            Some(instr) => instr,
+            None => {
+                if state.need_flush {
+                    output.flush()?;
+                    state.need_flush = false;
+                }
+                return Ok(false);
+            }
+        };
+
+        self.check_limits(state, instr, options, output)?;
+        state.steps += 1;
+
+        match *instr {
+            Instruct::Move(off) => {
+                if let Err(index) = state.tape.move_by(off) {
+                    output.flush()?;
+                    return Err(self.out_of_bounds(state.pc, index, &options.tape));
+                }
+                state.pc += 1;
+            },
+
+            Instruct::Add(val) if options.trap_overflow => {
+                let cell = state.tape.cell();
+                match cell.checked_add_delta(val) {
+                    Some(new_val) => *cell = new_val,
+                    None => {
+                        output.flush()?;
+                        return Err(self.overflow(state.pc, state.tape.pos()));
+                    }
+                }
+                state.pc += 1;
+            },
+
+            Instruct::Add(val) => {
+                state.pc += 1;
+                let cell = state.tape.cell();
+                *cell = cell.add_delta(val);
+            },
+
+            Instruct::Set(ref val) => {
+                state.pc += 1;
+                *state.tape.cell() = val.clone();
+            },
+
+            Instruct::AddTo(off) => {
+                let val = state.tape.cell().clone();
+                if !val.is_zero() {
+                    let index = state.tape.pos_at(off);
+                    match state.tape.cell_at(off) {
+                        Ok(cell) if options.trap_overflow => {
+                            match cell.checked_add(&val) {
+                                Some(new_val) => *cell = new_val,
+                                None => {
+                                    output.flush()?;
+                                    return Err(self.overflow(state.pc, index));
                                 }
                             }
+                        },
+                        Ok(cell) => *cell = cell.wrapping_add(&val),
+                        Err(index) => {
+                            output.flush()?;
+                            return Err(self.out_of_bounds(state.pc, index, &options.tape));
                         }
-                    },
-
-                    Instruct::SubFrom(off) => {
-                        pc += 1;
-                        if let Some(val) = mem.get(ptr) {
-                            let val = -*val;
-                            if val != Int::zero() {
-                                if -(ptr as isize) > off {
-                                    let diff = (-(ptr as isize) - off) as usize;
-                                    let chunk = vec![Int::zero(); diff];
-                                    mem.splice(..0, chunk);
-                                    ptr += diff;
-                                    mem[0] = val;
-                                } else {
-                                    let target_ptr = (ptr as isize + off) as usize;
-                                    if target_ptr >= mem.len() {
-                                        mem.resize(target_ptr + 1, Int::zero());
-                                    }
-                                    mem[target_ptr] = mem[target_ptr].wrapping_add(&val);
+                    }
+                }
+                state.pc += 1;
+            },
+
+            Instruct::SubFrom(off) => {
+                let val = state.tape.cell().clone();
+                if !val.is_zero() {
+                    let index = state.tape.pos_at(off);
+                    match state.tape.cell_at(off) {
+                        Ok(cell) if options.trap_overflow => {
+                            match cell.checked_sub(&val) {
+                                Some(new_val) => *cell = new_val,
+                                None => {
+                                    output.flush()?;
+                                    return Err(self.overflow(state.pc, index));
                                 }
                             }
+                        },
+                        Ok(cell) => *cell = cell.wrapping_sub(&val),
+                        Err(index) => {
+                            output.flush()?;
+                            return Err(self.out_of_bounds(state.pc, index, &options.tape));
                         }
-                    },
-
-                    Instruct::Read => {
-                        pc += 1;
-                        let mut data = [0u8];
-                        if need_flush {
-                            std::io::stdout().flush()?;
-                            need_flush = false;
-                        }
-                        let count = std::io::stdin().read(&mut data)?;
-                        if ptr >= mem.len() {
-                            mem.resize(ptr + 1, Int::zero());
-                        }
-                        if count < 1 {
-                            mem[ptr] = -Int::one();
-                        } else {
-                            mem[ptr] = Int::from_byte(data[0]);
-                        }
-                    },
+                    }
+                }
+                state.pc += 1;
+            },
+
+            Instruct::Read => {
+                state.pc += 1;
+                let mut data = [0u8];
+                if state.need_flush {
+                    output.flush()?;
+                    state.need_flush = false;
+                }
+                let count = input.read(&mut data)?;
+                if count < 1 {
+                    *state.tape.cell() = Int::eof();
+                } else {
+                    *state.tape.cell() = Int::from_byte(data[0]);
+                }
+            },
+
+            Instruct::Write => {
+                state.pc += 1;
+                let byte = state.tape.cell().get_least_byte();
+                output.write_all(&[byte])?;
+                state.need_flush = byte != b'\n';
+            },
+
+            Instruct::WriteStr(ref data) => {
+                state.pc += 1;
+                if !data.is_empty() {
+                    output.write_all(data)?;
+                    state.need_flush = data[data.len() - 1] != b'\n';
+                }
+            },
 
-                    Instruct::Write => {
-                        pc += 1;
-                        if ptr >= mem.len() {
-                            mem.resize(ptr + 1, Int::zero());
-                        }
-                        let byte = mem[ptr].get_least_byte();
-                        std::io::stdout().write_all(&[byte])?;
-                        need_flush = byte != b'\n';
-                    },
-
-                    Instruct::WriteStr(ref data) => {
-                        pc += 1;
-                        if data.len() > 0 {
-                            std::io::stdout().write_all(data)?;
-                            need_flush = data[data.len() - 1] != b'\n';
-                        }
-                    },
+            Instruct::LoopStart(pc_false) => {
+                if state.tape.cell().is_zero() {
+                    state.pc = pc_false;
+                } else {
+                    state.pc += 1;
+                }
+            },
 
-                    Instruct::LoopStart(pc_false) => {
-                        if ptr >= mem.len() {
-                            mem.resize(ptr + 1, Int::zero());
-                        }
-                        if mem[ptr] == Int::zero() {
-                            pc = pc_false;
-                        } else {
-                            pc += 1;
-                        }
-                    },
+            Instruct::LoopEnd(pc_loop_start) => {
+                state.pc = pc_loop_start;
+            },
 
-                    Instruct::LoopEnd(pc_loop_start) => {
-                        pc = pc_loop_start;
-                    }
+            Instruct::DebugDump => {
+                if state.need_flush {
+                    output.flush()?;
+                    state.need_flush = false;
                 }
-            } else {
-                break;
+                writeln!(dump, "debug dump at {}:", self.span(state.pc))?;
+                state.tape.write_window(dump, 10)?;
+                state.pc += 1;
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Checks the resource limits before the instruction is executed, so that
+    // a growing tape is never extended beyond max_tape.
+    fn check_limits<W: Write + ?Sized>(&self, state: &mut ExecState<Int>, instr: &Instruct<Int>, options: &ExecOptions, output: &mut W) -> std::result::Result<(), Error> {
+        let span = self.span(state.pc);
+
+        if let Some(max_steps) = options.max_steps {
+            if state.steps >= max_steps {
+                output.flush()?;
+                return Err(Error::StepLimitExceeded {
+                    lineno: span.start.lineno,
+                    column: span.start.column,
+                    steps:  state.steps,
+                    cells:  state.tape.cells().len(),
+                });
+            }
+        }
+
+        if let Some(max_tape) = options.max_tape {
+            let cells = match *instr {
+                Instruct::Move(off) | Instruct::AddTo(off) | Instruct::SubFrom(off) => state.tape.cells_with(off),
+                _ => state.tape.cells_with(0),
+            };
+            if cells > max_tape {
+                output.flush()?;
+                return Err(Error::TapeLimitExceeded {
+                    lineno: span.start.lineno,
+                    column: span.start.column,
+                    steps:  state.steps,
+                    cells,
+                    limit:  max_tape,
+                });
             }
         }
 
-        if need_flush {
-            std::io::stdout().flush()?;
+        // reading the clock for every instruction would be too slow
+        if let Some(timeout) = options.timeout {
+            if state.steps & 0xFFF == 0 && state.started.elapsed() > timeout {
+                output.flush()?;
+                return Err(Error::Timeout {
+                    lineno: span.start.lineno,
+                    column: span.start.column,
+                    steps:  state.steps,
+                    cells:  state.tape.cells().len(),
+                    millis: state.started.elapsed().as_millis() as u64,
+                });
+            }
         }
 
         Ok(())
     }
 
-    pub fn write_debug(&self, out: &mut Write) -> std::io::Result<()> {
+    fn out_of_bounds(&self, pc: usize, index: isize, tape: &TapeOptions) -> Error {
+        let span = self.span(pc);
+        Error::TapeOutOfBounds {
+            lineno: span.start.lineno,
+            column: span.start.column,
+            index,
+            size: tape.size,
+        }
+    }
+
+    fn overflow(&self, pc: usize, index: isize) -> Error {
+        let span = self.span(pc);
+        Error::CellOverflow {
+            lineno: span.start.lineno,
+            column: span.start.column,
+            index,
+        }
+    }
+
+    /// Writes the instructions in the text format of [`Instruct`]'s
+    /// `Display`, one per line and indented by loop nesting.
+    pub fn write_debug(&self, out: &mut dyn Write) -> std::io::Result<()> {
         let mut nesting: usize = 0;
         for instr in self.code.iter() {
-            match instr {
-                Instruct::Move(val) => {
-                    indent(out, nesting)?;
-                    write!(out, "move {:?}\n", val)?;
+            if let Instruct::LoopEnd(_) = instr {
+                nesting -= 1;
+            }
+            indent(out, nesting)?;
+            writeln!(out, "{}", instr)?;
+            if let Instruct::LoopStart(_) = instr {
+                nesting += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the program as brainfuck source. Any program can be written,
+    /// unless it needs a cell to work in and there is none near the pointer
+    /// that isn't used anymore.
+    pub fn write_bf(&self, out: &mut dyn Write) -> std::result::Result<(), Error> {
+        // cells that are overwritten while writing the instruction at an index.
+        // An unbounded cell can't be cleared without knowing its sign, so
+        // there only cells that are 0 already are used.
+        let mut scratch: Vec<Option<isize>> = Vec::with_capacity(self.len());
+        for (index, instr) in self.code.iter().enumerate() {
+            let off = match *instr {
+                Instruct::Add(_) | Instruct::Set(_) | Instruct::WriteStr(_) => self.find_scratch(index + 1, &[]),
+                _ => None,
+            };
+            scratch.push(off.filter(|off| Int::size().is_some() || self.value_range(index, *off, &scratch) == Some((0, 0))));
+        }
+
+        let mut index = 0usize;
+        while let Some(instr) = self.code.get(index) {
+            match *instr {
+                Instruct::Move(off) => {
+                    write_move(out, off)?;
+                    index += 1;
                 }
 
                 Instruct::Add(val) => {
-                    indent(out, nesting)?;
-                    write!(out, "add {:?}\n", val)?;
-                },
-
-                Instruct::Set(val) => {
-                    indent(out, nesting)?;
-                    write!(out, "set {:?}\n", val)?;
+                    generate_bf_const(out, Int::wrap_delta(val) as i128, &const_options::<Int>(scratch[index]))?;
+                    index += 1;
                 },
 
-                Instruct::AddTo(off) => {
-                    indent(out, nesting)?;
-                    write!(out, "add_to {:?}\n", off)?;
+                Instruct::Set(ref val) => {
+                    let val = match val.to_delta() {
+                        Some(val) => val,
+                        None => {
+                            let span = self.span(index);
+                            return Err(Error::ConstantTooLarge {
+                                lineno: span.start.lineno,
+                                column: span.start.column,
+                                value:  format!("{:?}", val),
+                            });
+                        }
+                    };
+                    self.write_bf_set(out, index, val as i128, &scratch)?;
+                    index += 1;
                 },
 
-                Instruct::SubFrom(off) => {
-                    indent(out, nesting)?;
-                    write!(out, "sub_from {:?}\n", off)?;
+                Instruct::AddTo(_) | Instruct::SubFrom(_) => {
+                    index = self.write_bf_add_to(out, index, &scratch)?;
                 },
 
                 Instruct::Read => {
-                    indent(out, nesting)?;
-                    out.write_all(b"read\n")?;
+                    out.write_all(b",")?;
+                    index += 1;
                 },
 
                 Instruct::Write => {
-                    indent(out, nesting)?;
-                    out.write_all(b"write\n")?;
+                    out.write_all(b".")?;
+                    index += 1;
                 },
 
                 Instruct::LoopStart(_) => {
-                    indent(out, nesting)?;
-                    out.write_all(b"loop {\n")?;
-                    nesting += 1;
+                    out.write_all(b"[")?;
+                    index += 1;
                 },
 
                 Instruct::LoopEnd(_) => {
-                    nesting -= 1;
-                    indent(out, nesting)?;
-                    out.write_all(b"}\n")?;
+                    out.write_all(b"]")?;
+                    index += 1;
+                },
+
+                Instruct::WriteStr(ref data) => {
+                    self.write_bf_str(out, index, data, &scratch)?;
+                    index += 1;
                 },
 
-                Instruct::WriteStr(val) => {
-                    indent(out, nesting)?;
-                    write!(out, "write {:?}\n", val)?;
+                Instruct::DebugDump => {
+                    out.write_all(b"#")?;
+                    index += 1;
                 }
             }
         }
 
         Ok(())
     }
+}
 
-    pub fn write_bf(&self, out: &mut Write) -> std::io::Result<()> {
-        let mut index = 0usize;
-        loop {
-            if let Some(instr) = self.code.get(index) {
-                match *instr {
-                    Instruct::Move(off) => {
-                        if off > 0 {
-                            print_repeat(out, b">", off as usize)?;
-                        } else {
-                            print_repeat(out, b"<", -off as usize)?;
-                        }
-                        index += 1;
+impl<Int: BrainfuckInteger> Brainfuck<Int> {
+    // offset of a cell near the pointer that nothing reads anymore before it
+    // is overwritten, so write_bf can use it for its own purposes
+    fn find_scratch(&self, index: usize, exclude: &[isize]) -> Option<isize> {
+        [1, 2, 3, -1, -2, -3, 4, 5, 6, 7, 8, -4, -5, -6, -7, -8].iter().cloned().find(|off|
+            !exclude.contains(off) && optimize::is_dead(self, *off, index))
+    }
+
+    // like find_set_before, but a cell write_bf used as scratch cell in
+    // between has lost its value
+    fn known_value(&self, index: usize, scratch: &[Option<isize>]) -> Option<i128> {
+        self.find_set_before_with(index, |index, ptr| scratch[index] == Some(ptr))
+            .and_then(|val| val.to_delta()).map(|val| val as i128)
+    }
+
+    // Smallest and largest value the cell at off (relative to the pointer)
+    // may have before the instruction at index, None if it could be any.
+    // Only used for unbounded cells, where a scratch cell write_bf used for
+    // a constant is 0 again afterwards.
+    fn value_range(&self, mut index: usize, off: isize, scratch: &[Option<isize>]) -> Option<(i128, i128)> {
+        let mut ptr = off;
+        let mut delta = 0i128;
+        while index > 0 {
+            index -= 1;
+            if scratch[index] == Some(ptr) {
+                return match self.code[index] {
+                    Instruct::WriteStr(_) => None,
+                    _ => Some((delta, delta)),
+                };
+            }
+            match self.code[index] {
+                Instruct::Set(ref val) => {
+                    if ptr == 0 {
+                        let val = val.to_delta()? as i128 + delta;
+                        return Some((val, val));
+                    }
+                },
+                Instruct::Move(off) => {
+                    ptr += off;
+                },
+                Instruct::Add(val) => {
+                    if ptr == 0 {
+                        delta += val as i128;
+                    }
+                },
+                Instruct::Read => {
+                    if ptr == 0 {
+                        // a byte or EOF
+                        return Some((delta - 1, delta + 255));
+                    }
+                },
+                Instruct::AddTo(off) | Instruct::SubFrom(off) => {
+                    if ptr == off {
+                        let (min, max) = self.value_range(index, ptr, scratch)?;
+                        let (from, to) = self.value_range(index, 0, scratch)?;
+                        let (from, to) = match self.code[index] {
+                            Instruct::AddTo(_) => (from, to),
+                            _ => (to.checked_neg()?, from.checked_neg()?),
+                        };
+                        return Some((
+                            min.checked_add(from)?.checked_add(delta)?,
+                            max.checked_add(to)?.checked_add(delta)?,
+                        ));
                     }
+                },
+                Instruct::LoopStart(_) => return None,
+                Instruct::LoopEnd(_) => {
+                    if ptr == 0 {
+                        return Some((delta, delta));
+                    }
+                    return None;
+                },
+                Instruct::Write | Instruct::WriteStr(_) | Instruct::DebugDump => {},
+            }
+        }
+        Some((delta, delta))
+    }
 
-                    Instruct::Add(val) => {
-                        if val > Int::zero() {
-                            print_repeat(out, b"+", val.wrapping_usize())?;
-                        } else {
-                            print_repeat(out, b"-", (-val).wrapping_usize())?;
-                        }
-                        index += 1;
-                    },
-
-                    Instruct::Set(val) => {
-                        write!(out, "[-]")?;
-                        if val > Int::zero() {
-                            print_repeat(out, b"+", val.wrapping_usize())?;
-                        } else {
-                            print_repeat(out, b"-", (-val).wrapping_usize())?;
-                        }
-                        index += 1;
-                    },
-
-                    Instruct::AddTo(_) | Instruct::SubFrom(_) => {
-                        let mut offsets = Vec::new();
-                        index += 1;
-                        loop {
-                            match self.code.get(index) {
-                                Some(Instruct::AddTo(off)) => {
-                                    offsets.push((off, b"+"));
-                                    index += 1;
-                                },
-                                Some(Instruct::SubFrom(off)) => {
-                                    offsets.push((off, b"-"));
-                                    index += 1;
-                                },
-                                _ => break
-                            }
-                        }
-                        write!(out, "[-")?;
-                        let mut current_off = 0isize;
-                        for (target_off, instr) in offsets {
-                            let off = target_off - current_off;
-                            if off > 0 {
-                                print_repeat(out, b">", off as usize)?;
-                                out.write_all(instr)?;
-                                print_repeat(out, b"<", off as usize)?;
-                            } else {
-                                print_repeat(out, b"<", -off as usize)?;
-                                out.write_all(instr)?;
-                                print_repeat(out, b">", -off as usize)?;
-                            }
-                            current_off = off;
-                        }
-                        if current_off > 0 {
-                            print_repeat(out, b"<", current_off as usize)?;
-                        } else if current_off < 0 {
-                            print_repeat(out, b">", -current_off as usize)?;
-                        }
-                        write!(out, "]")?;
-
-                        match self.code.get(index) {
-                            Some(Instruct::Set(val)) if *val == Int::zero() => {
-                                index += 1;
-                            },
-                            _ => {
-                                return Err(std::io::Error::new(
-                                    std::io::ErrorKind::Other,
-                                    "this optimized brainfuck program cannot (easily) be converted back to brainfuck anymore"));
-                            }
-                        }
-                    },
-
-                    Instruct::Read => {
-                        out.write_all(b",")?;
-                        index += 1;
-                    },
-
-                    Instruct::Write => {
-                        out.write_all(b".")?;
-                        index += 1;
-                    },
-
-                    Instruct::LoopStart(_) => {
-                        out.write_all(b"[")?;
-                        index += 1;
-                    },
-
-                    Instruct::LoopEnd(_) => {
-                        out.write_all(b"]")?;
-                        index += 1;
-                    },
-
-                    Instruct::WriteStr(ref val) => {
-                        for byte in val.iter() {
-                            out.write_all(b"[-]")?;
-                            print_repeat(out, b"+", *byte as usize)?;
-                            out.write_all(b".")?;
+    // Clears the cell at off before the instruction at index. An unbounded
+    // cell only reaches 0 when counted towards it from its side, so one of
+    // unknown sign is made non-negative first.
+    fn write_bf_clear(&self, out: &mut dyn Write, index: usize, off: isize, scratch: &[Option<isize>]) -> std::result::Result<(), Error> {
+        if Int::size().is_some() {
+            out.write_all(b"[-]")?;
+            return Ok(());
+        }
+        match self.value_range(index, off, scratch) {
+            Some((min, _)) if min >= 0 => out.write_all(b"[-]")?,
+            Some((_, max)) if max <= 0 => out.write_all(b"[+]")?,
+            Some((min, _)) => {
+                write_add(out, -min)?;
+                out.write_all(b"[-]")?;
+            },
+            None => {
+                let span = self.span(index);
+                return Err(Error::NotRepresentable {
+                    lineno: span.start.lineno,
+                    column: span.start.column,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // A run of AddTo and SubFrom adds multiples of the current cell to other
+    // cells, which is a loop that counts the current cell down to 0. If the
+    // value is still needed afterwards it is also moved to a scratch cell and
+    // moved back from there. Returns the index after the run.
+    fn write_bf_add_to(&self, out: &mut dyn Write, mut index: usize, scratch: &[Option<isize>]) -> std::result::Result<usize, Error> {
+        let start = index;
+        // the current cell is multiplied by factor along the way (AddTo(0)
+        // doubles it) and each target gets a multiple of the original value
+        let mut factor = 1i128;
+        let mut targets: Vec<(isize, i128)> = Vec::new();
+        loop {
+            let (off, val) = match self.code.get(index) {
+                Some(Instruct::AddTo(off))   => (*off, factor),
+                Some(Instruct::SubFrom(off)) => (*off, -factor),
+                _ => break
+            };
+            if off == 0 {
+                factor = wrap_value::<Int>(factor + val);
+            } else if let Some(target) = targets.iter_mut().find(|(target_off, _)| *target_off == off) {
+                target.1 = wrap_value::<Int>(target.1 + val);
+            } else {
+                targets.push((off, val));
+            }
+            if factor.unsigned_abs() > u64::MAX as u128 {
+                // only if a huge BigInt multiple is asked for
+                let span = self.span(start);
+                return Err(Error::NotRepresentable {
+                    lineno: span.start.lineno,
+                    column: span.start.column,
+                });
+            }
+            index += 1;
+        }
+        targets.retain(|(_, val)| *val != 0);
+
+        let cleared = match self.code.get(index) {
+            Some(Instruct::Set(val)) if val.is_zero() => {
+                index += 1;
+                true
+            },
+            _ => false,
+        };
+        let keep = !cleared && factor != 0 && !optimize::is_dead(self, 0, index);
+
+        if keep && factor == 1 && targets.is_empty() {
+            return Ok(index);
+        }
+        if !keep && targets.is_empty() && !cleared {
+            // the current cell isn't used anymore
+            return Ok(index);
+        }
+
+        // an unbounded cell is counted towards 0 from its side, one of
+        // unknown sign is shifted up first and the shift is taken back from
+        // the results afterwards
+        let (up, shift) = match Int::size() {
+            Some(_) => (false, 0),
+            None => match self.value_range(start, 0, scratch) {
+                Some((min, _)) if min >= 0 => (false, 0),
+                Some((_, max)) if max <= 0 => (true, 0),
+                Some((min, _)) => (false, -min),
+                None => {
+                    let span = self.span(start);
+                    return Err(Error::NotRepresentable {
+                        lineno: span.start.lineno,
+                        column: span.start.column,
+                    });
+                }
+            },
+        };
+        let shifted = targets.len();
+
+        if keep {
+            let offsets: Vec<isize> = targets.iter().map(|(off, _)| *off).collect();
+            let spare = match self.find_scratch(index, &offsets) {
+                Some(spare) => spare,
+                None => {
+                    let span = self.span(start);
+                    return Err(Error::NotRepresentable {
+                        lineno: span.start.lineno,
+                        column: span.start.column,
+                    });
+                }
+            };
+            write_move(out, spare)?;
+            self.write_bf_clear(out, start, spare, scratch)?;
+            write_move(out, -spare)?;
+            targets.push((spare, factor));
+        }
+
+        write_add(out, shift)?;
+        out.write_all(if up { b"[+" } else { b"[-" })?;
+        let mut current_off = 0isize;
+        for (off, val) in &targets {
+            write_move(out, off - current_off)?;
+            write_add(out, if up { -val } else { *val })?;
+            current_off = *off;
+        }
+        write_move(out, -current_off)?;
+        out.write_all(b"]")?;
+
+        if keep {
+            // the scratch cell is negative if exactly one of factor and the
+            // counted value is, which only matters for unbounded cells
+            let back = Int::size().is_none() && (factor < 0) != up;
+            write_move(out, current_off)?;
+            out.write_all(if back { b"[+" } else { b"[-" })?;
+            write_move(out, -current_off)?;
+            out.write_all(if back { b"-" } else { b"+" })?;
+            write_move(out, current_off)?;
+            out.write_all(b"]")?;
+            write_move(out, -current_off)?;
+            write_add(out, -factor * shift)?;
+        }
+
+        if shift != 0 {
+            let mut current_off = 0isize;
+            for (off, val) in &targets[..shifted] {
+                write_move(out, off - current_off)?;
+                write_add(out, -val * shift)?;
+                current_off = *off;
+            }
+            write_move(out, -current_off)?;
+        }
+
+        Ok(index)
+    }
+
+    // sets the current cell, starting from its known value if that is shorter
+    fn write_bf_set(&self, out: &mut dyn Write, index: usize, value: i128, scratch: &[Option<isize>]) -> std::result::Result<(), Error> {
+        let cleared = ConstOptions { zero: true, ..const_options::<Int>(scratch[index]) };
+        if let Some(known) = self.known_value(index, scratch) {
+            let delta = wrap_value::<Int>(value - known);
+            let options = ConstOptions { zero: known == 0, ..cleared };
+            if bf_const_len(delta, &options) <= 3 + bf_const_len(value, &cleared) {
+                generate_bf_const(out, delta, &options)?;
+                return Ok(());
+            }
+        }
+        self.write_bf_clear(out, index, 0, scratch)?;
+        generate_bf_const(out, value, &cleared)?;
+        Ok(())
+    }
+
+    // Writes the bytes through the current cell. Only the least significant
+    // byte of a cell is written, so every byte is reached by the smallest
+    // change of the cell. A known value of the cell is restored afterwards.
+    // A cell of unknown value that is still needed is left alone and the
+    // bytes are written through the scratch cell instead.
+    fn write_bf_str(&self, out: &mut dyn Write, index: usize, data: &[u8], scratch: &[Option<isize>]) -> std::result::Result<(), Error> {
+        let mut options = const_options::<Int>(scratch[index]);
+        let known = self.known_value(index, scratch);
+        let mut moved = 0isize;
+        let mut current = match known {
+            Some(val) => val,
+            None => {
+                if !optimize::is_dead(self, 0, index + 1) {
+                    moved = match scratch[index] {
+                        Some(off) => off,
+                        None => {
+                            let span = self.span(index);
+                            return Err(Error::NotRepresentable {
+                                lineno: span.start.lineno,
+                                column: span.start.column,
+                            });
                         }
-                        index += 1;
-                    }
+                    };
+                    options.scratch = None;
+                    write_move(out, moved)?;
                 }
+                self.write_bf_clear(out, index, moved, scratch)?;
+                0
+            }
+        };
+
+        for byte in data {
+            let mut delta = (*byte as i128 - current).rem_euclid(256);
+            if delta >= 128 {
+                delta -= 256;
+            }
+            let value = if *byte >= 128 { *byte as i128 - 256 } else { *byte as i128 };
+            options.zero = current == 0;
+            let cleared = ConstOptions { zero: true, ..options };
+
+            if 3 + bf_const_len(value, &cleared) < bf_const_len(delta, &options) {
+                write_clear::<Int>(out, current)?;
+                options.scratch_zero |= generate_bf_const(out, value, &cleared)?;
+                current = value;
             } else {
-                break;
+                options.scratch_zero |= generate_bf_const(out, delta, &options)?;
+                current = wrap_value::<Int>(current + delta);
             }
+            out.write_all(b".")?;
         }
 
+        if let Some(known) = known {
+            options.zero = current == 0;
+            generate_bf_const(out, wrap_value::<Int>(known - current), &options)?;
+        }
+        write_move(out, -moved)?;
         Ok(())
     }
 }
 
-fn print_repeat(out: &mut Write, bytes: &[u8], count: usize) -> std::io::Result<()> {
-    for _ in 0..count {
-        out.write_all(bytes)?;
+// options for a constant that may use the scratch cell, which write_bf only
+// picks for unbounded cells if it is 0
+fn const_options<Int: BrainfuckInteger>(scratch: Option<isize>) -> ConstOptions {
+    ConstOptions { zero: false, scratch, scratch_zero: Int::size().is_none() }
+}
+
+// clears a cell of known value, an unbounded one towards 0 from its side
+fn write_clear<Int: BrainfuckInteger>(out: &mut dyn Write, value: i128) -> std::io::Result<()> {
+    if Int::size().is_none() && value < 0 {
+        out.write_all(b"[+]")
+    } else {
+        out.write_all(b"[-]")
     }
+}
 
-    Ok(())
-}
\ No newline at end of file
+// value wrapped to the cell width, as a signed number
+fn wrap_value<Int: BrainfuckInteger>(value: i128) -> i128 {
+    match Int::size() {
+        Some(size) => {
+            let shift = 128 - size * 8;
+            (value << shift) >> shift
+        },
+        None => value,
+    }
+}
@@ -0,0 +1,128 @@
+//! The Ook! dialect. Each brainfuck command is a pair of the words `Ook.`,
+//! `Ook?` and `Ook!`:
+//!
+//! ```text
+//! Ook. Ook?  >       Ook! Ook!  -       Ook! Ook?  [
+//! Ook? Ook.  <       Ook! Ook.  .       Ook? Ook!  ]
+//! Ook. Ook.  +       Ook. Ook!  ,
+//! ```
+//!
+//! Everything that isn't one of the three words is a comment. Words are
+//! paired in order, so a stray word shifts all following pairs and is
+//! usually reported as an unknown pair (`Ook? Ook?`) or a word left over at
+//! the end.
+
+use std::io::Write;
+use super::{Brainfuck, BrainfuckInteger, Error, Span};
+use super::span::Pos;
+
+const COMMANDS: [(char, &str); 8] = [
+    ('>', "Ook. Ook?"),
+    ('<', "Ook? Ook."),
+    ('+', "Ook. Ook."),
+    ('-', "Ook! Ook!"),
+    ('.', "Ook! Ook."),
+    (',', "Ook. Ook!"),
+    ('[', "Ook! Ook?"),
+    (']', "Ook? Ook!"),
+];
+
+// pairs per line of the written program
+const LINE_PAIRS: usize = 8;
+
+struct Word {
+    punct: char,
+    lineno: usize,
+    column: usize,
+}
+
+fn words(input: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut pos = 0;
+        while pos + 3 < chars.len() {
+            if chars[pos] == 'O' && chars[pos + 1] == 'o' && chars[pos + 2] == 'k' && ".?!".contains(chars[pos + 3]) {
+                words.push(Word { punct: chars[pos + 3], lineno: index + 1, column: pos + 1 });
+                pos += 4;
+            } else {
+                pos += 1;
+            }
+        }
+    }
+    words
+}
+
+fn error(word: &Word, message: String) -> Error {
+    Error::OokSyntax { lineno: word.lineno, column: word.column, message }
+}
+
+/// Appends the instructions of the Ook! program to code.
+pub fn parse<Int: BrainfuckInteger>(code: &mut Brainfuck<Int>, input: &str) -> std::result::Result<(), Error> {
+    let words = words(input);
+    // first words of the open loops
+    let mut loops: Vec<&Word> = Vec::new();
+
+    for pair in words.chunks(2) {
+        let first = &pair[0];
+        let second = match pair.get(1) {
+            Some(second) => second,
+            None => return Err(error(first, format!("'Ook{}' without a second word", first.punct))),
+        };
+        // the span covers both words
+        let span = Span {
+            start: Pos::new(first.lineno, first.column),
+            end:   Pos::new(second.lineno, second.column + 3),
+        };
+        match (first.punct, second.punct) {
+            ('.', '?') => code.push_move(1, span),
+            ('?', '.') => code.push_move(-1, span),
+            ('.', '.') => code.push_add(1, span),
+            ('!', '!') => code.push_add(-1, span),
+            ('!', '.') => code.push_write(span),
+            ('.', '!') => code.push_read(span),
+            ('!', '?') => {
+                loops.push(first);
+                code.push_loop_start(span);
+            },
+            ('?', '!') => {
+                if loops.pop().is_none() {
+                    return Err(error(first, "unmatched 'Ook? Ook!' (loop end)".to_string()));
+                }
+                code.push_loop_end(span);
+            },
+            _ => return Err(error(first, format!("unknown pair 'Ook{} Ook{}'", first.punct, second.punct))),
+        }
+    }
+
+    if let Some(first) = loops.pop() {
+        return Err(error(first, "unmatched 'Ook! Ook?' (loop start)".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Writes the program as Ook!, translated from the brainfuck written by
+/// [`Brainfuck::write_bf`].
+pub fn write_ook<Int: BrainfuckInteger>(code: &Brainfuck<Int>, out: &mut dyn Write) -> std::result::Result<(), Error> {
+    let mut bf = Vec::new();
+    code.write_bf(&mut bf)?;
+
+    let mut count = 0;
+    for byte in bf {
+        let pair = match COMMANDS.iter().find(|(command, _)| *command as u8 == byte) {
+            Some((_, pair)) => pair,
+            None => continue,
+        };
+        if count > 0 {
+            out.write_all(if count % LINE_PAIRS == 0 { b"\n" } else { b" " })?;
+        }
+        out.write_all(pair.as_bytes())?;
+        count += 1;
+    }
+    if count > 0 {
+        out.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
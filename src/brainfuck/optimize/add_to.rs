@@ -7,7 +7,7 @@ enum Op {
     Sub
 }
 
-pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
+pub fn optimize<Int: BrainfuckInteger>(code: &Brainfuck<Int>, trap_overflow: bool) -> Brainfuck<Int> {
     let mut opt_code = Brainfuck::new();
     let mut index = 0usize;
 
@@ -16,6 +16,12 @@ pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int
     // the loop variable is decreased by one
     // other cells are increased by one
 
+    // with trap_overflow a negative signed loop variable underflows, which
+    // add_to wouldn't notice
+    if trap_overflow && Int::size().is_some() && Int::is_signed() {
+        return code.clone();
+    }
+
     loop {
         match code.get(index) {
             Some(Instruct::LoopStart(_)) => {
@@ -30,7 +36,7 @@ pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int
                             end_index += 1;
                         },
                         Some(Instruct::Add(val)) => {
-                            match val.as_i64() {
+                            match *val {
                                 -1 if offset == 0 && !decreased => {
                                     decreased = true;
                                 },
@@ -62,26 +68,27 @@ pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int
                         sorted_offsets.push(*offset);
                     }
                     sorted_offsets.sort_unstable();
+                    let span = code.span_range(index, end_index);
                     for offset in sorted_offsets {
                         match offsets[&offset] {
-                            Op::Add => opt_code.push_add_to(offset),
-                            Op::Sub => opt_code.push_sub_from(offset),
+                            Op::Add => opt_code.push_add_to(offset, span),
+                            Op::Sub => opt_code.push_sub_from(offset, span),
                         }
                     }
-                    opt_code.push_set(Int::zero());
+                    opt_code.push_set(Int::zero(), span);
                     index = end_index;
                 } else {
-                    opt_code.push_loop_start();
+                    opt_code.push_loop_start(code.span(index));
                     index += 1;
                 }
             },
             Some(instr) => {
-                opt_code.push(instr);
+                opt_code.push(instr, code.span(index));
                 index += 1;
             },
             None => break
         }
     }
 
-    return opt_code;
+    opt_code
 }
\ No newline at end of file
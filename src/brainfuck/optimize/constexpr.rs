@@ -1,195 +1,160 @@
 extern crate num_traits;
-use super::super::{Brainfuck, BrainfuckInteger, Instruct};
+use super::super::{Brainfuck, BrainfuckInteger, Instruct, Tape, TapeOptions};
 
 use std::io::Write;
+use std::collections::HashSet;
 
-pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int>, echo: bool) -> std::io::Result<Brainfuck<Int>> {
+/// The output of the evaluated code is also written to echo, if given.
+pub fn optimize<Int: BrainfuckInteger, W: Write + ?Sized>(
+        code: &Brainfuck<Int>, mut echo: Option<&mut W>, tape_options: &TapeOptions, trap_overflow: bool)
+        -> std::io::Result<Brainfuck<Int>> {
     let mut opt_code = Brainfuck::new();
-    let mut dirty = Vec::<bool>::new();
-    let mut mem = Vec::<Int>::new();
-    let mut ptr = 0usize;
+    // cells that depend on input are None
+    let mut tape = Tape::new(*tape_options, Some(Int::zero()));
     let mut pc  = 0usize;
-    let mut current_ptr = 0;
+    let mut max_pc = 0usize;
+    let mut current_ptr = 0isize;
+    // cells that hold input at runtime, even if they are later set again
+    let mut read_cells = HashSet::new();
 
     loop {
+        if pc > max_pc {
+            max_pc = pc;
+        }
         if let Some(instr) = code.get(pc) {
             match *instr {
                 Instruct::Move(off) => {
-                    if let Some(true) = dirty.get(ptr) {
+                    if tape.cell().is_none() {
                         break;
                     }
-                    pc += 1;
-                    if -(ptr as isize) > off {
-                        let diff = (-(ptr as isize) - off) as usize;
-                        let chunk = vec![Int::zero(); diff];
-                        mem.splice(..0, chunk);
-                        let chunk = vec![false; diff];
-                        dirty.splice(..0, chunk);
-                        ptr += diff;
-                    }
-                    ptr = ((ptr as isize) + off) as usize;
-                },
-
-                Instruct::Add(val) => {
-                    if let Some(true) = dirty.get(ptr) {
+                    if tape.move_by(off).is_err() {
+                        // leave the error to the runtime
                         break;
                     }
                     pc += 1;
-                    if ptr >= mem.len() {
-                        mem.resize(ptr + 1, Int::zero());
-                    }
-                    mem[ptr] = mem[ptr].wrapping_add(&val);
                 },
 
-                Instruct::Set(val) => {
-                    if let Some(true) = dirty.get(ptr) {
-                        dirty[ptr] = false;
+                Instruct::Add(val) => {
+                    match tape.cell() {
+                        Some(cell) if trap_overflow => {
+                            match cell.checked_add_delta(val) {
+                                Some(new_val) => *cell = new_val,
+                                // leave the error to the runtime
+                                None => break
+                            }
+                        },
+                        Some(cell) => *cell = cell.add_delta(val),
+                        None => break
                     }
                     pc += 1;
-                    if ptr >= mem.len() {
-                        mem.resize(ptr + 1, Int::zero());
-                    }
-                    mem[ptr] = val;
                 },
 
-                Instruct::AddTo(off) => {
-                    if let Some(true) = dirty.get(ptr) {
-                        break;
-                    }
-                    if let Some(val) = mem.get(ptr) {
-                        let val = *val;
-                        if val != Int::zero() {
-                            if -(ptr as isize) > off {
-                                let diff = (-(ptr as isize) - off) as usize;
-                                let chunk = vec![Int::zero(); diff];
-                                mem.splice(..0, chunk);
-                                let chunk = vec![false; diff];
-                                dirty.splice(..0, chunk);
-                                ptr += diff;
-                                mem[0] = val;
-                            } else {
-                                let target_ptr = (ptr as isize + off) as usize;
-                                if let Some(true) = dirty.get(target_ptr) {
-                                    break;
-                                }
-                                if target_ptr >= mem.len() {
-                                    mem.resize(target_ptr + 1, Int::zero());
-                                }
-                                mem[target_ptr] = mem[target_ptr].wrapping_add(&val);
-                            }
-                        }
-                    }
+                Instruct::Set(ref val) => {
+                    *tape.cell() = Some(val.clone());
                     pc += 1;
                 },
 
-                Instruct::SubFrom(off) => {
-                    if let Some(true) = dirty.get(ptr) {
-                        break;
-                    }
-                    if let Some(val) = mem.get(ptr) {
-                        let val = -*val;
-                        if val != Int::zero() {
-                            if -(ptr as isize) > off {
-                                let diff = (-(ptr as isize) - off) as usize;
-                                let chunk = vec![Int::zero(); diff];
-                                mem.splice(..0, chunk);
-                                let chunk = vec![false; diff];
-                                dirty.splice(..0, chunk);
-                                ptr += diff;
-                                mem[0] = val;
-                            } else {
-                                let target_ptr = (ptr as isize + off) as usize;
-                                if let Some(true) = dirty.get(target_ptr) {
-                                    break;
-                                }
-                                if target_ptr >= mem.len() {
-                                    mem.resize(target_ptr + 1, Int::zero());
-                                }
-                                mem[target_ptr] = mem[target_ptr].wrapping_add(&val);
-                            }
+                Instruct::AddTo(off) | Instruct::SubFrom(off) => {
+                    let val = match *tape.cell() {
+                        Some(ref val) => val.clone(),
+                        None => break
+                    };
+                    if !val.is_zero() {
+                        let cell = match tape.cell_at(off) {
+                            Ok(Some(cell)) => cell,
+                            _ => break
+                        };
+                        let new_val = match (instr, trap_overflow) {
+                            (Instruct::AddTo(_), true)  => cell.checked_add(&val),
+                            (Instruct::AddTo(_), false) => Some(cell.wrapping_add(&val)),
+                            (_, true)  => cell.checked_sub(&val),
+                            (_, false) => Some(cell.wrapping_sub(&val)),
+                        };
+                        match new_val {
+                            Some(new_val) => *cell = new_val,
+                            // leave the error to the runtime
+                            None => break
                         }
                     }
                     pc += 1;
                 },
 
                 Instruct::Read => {
-                    pc += 1;
-                    if ptr >= dirty.len() {
-                        dirty.resize(ptr + 1, false);
-                    }
-                    dirty[ptr] = true;
+                    *tape.cell() = None;
+                    let ptr = tape.pos();
+                    read_cells.insert(ptr);
                     if ptr != current_ptr {
-                        opt_code.push_move(current_ptr as isize - ptr as isize);
+                        opt_code.push_move(ptr - current_ptr, code.span(pc));
                         current_ptr = ptr;
                     }
-                    opt_code.push_read();
+                    opt_code.push_read(code.span(pc));
+                    pc += 1;
                 },
 
                 Instruct::Write => {
-                    if let Some(true) = dirty.get(ptr) {
-                        break;
-                    }
+                    let data = match *tape.cell() {
+                        Some(ref val) => vec![val.get_least_byte()],
+                        None => break
+                    };
+                    if let Some(ref mut echo) = echo {
+                        echo.write_all(&data)?;
+                    }
+                    opt_code.push_write_str(data, code.span(pc));
                     pc += 1;
-                    if ptr >= mem.len() {
-                        mem.resize(ptr + 1, Int::zero());
-                    }
-                    let data = vec![mem[ptr].get_least_byte()];
-                    if echo {
-                        std::io::stdout().write_all(&data)?;
-                    }
-                    opt_code.push_write_str(data);
                 },
 
                 Instruct::WriteStr(ref data) => {
-                    pc += 1;
-                    if echo {
-                        std::io::stdout().write_all(data)?;
+                    if let Some(ref mut echo) = echo {
+                        echo.write_all(data)?;
                     }
-                    opt_code.push_write_str(data.to_vec());
+                    opt_code.push_write_str(data.to_vec(), code.span(pc));
+                    pc += 1;
                 },
 
                 Instruct::LoopStart(pc_false) => {
-                    if let Some(true) = dirty.get(ptr) {
-                        break;
-                    }
-                    if ptr >= mem.len() {
-                        mem.resize(ptr + 1, Int::zero());
-                    }
-                    if mem[ptr] == Int::zero() {
-                        pc = pc_false;
-                    } else {
-                        pc += 1;
+                    match *tape.cell() {
+                        Some(ref val) if val.is_zero() => pc = pc_false,
+                        Some(_) => pc += 1,
+                        None => break
                     }
                 },
 
                 Instruct::LoopEnd(pc_loop_start) => {
                     pc = pc_loop_start;
-                }
+                },
+
+                // the dump has to show the tape at runtime
+                Instruct::DebugDump => break
+
             }
         } else {
             break;
         }
     }
 
-    if echo {
-        std::io::stdout().flush()?;
+    if let Some(echo) = echo {
+        echo.flush()?;
     }
 
     if pc < code.len() {
-        for (target_ptr, val) in mem.iter().enumerate() {
-            if *val != Int::zero() {
-                if current_ptr != target_ptr {
-                    let off = (target_ptr as isize) - (current_ptr as isize);
-                    opt_code.push_move(off);
-                    current_ptr = target_ptr;
+        let span = code.span_range(0, max_pc + 1);
+        let start = tape.start();
+        for (index, val) in tape.cells().iter().enumerate() {
+            let target_ptr = start + index as isize;
+            if let Some(val) = val {
+                if !val.is_zero() || read_cells.contains(&target_ptr) {
+                    if current_ptr != target_ptr {
+                        opt_code.push_move(target_ptr - current_ptr, span);
+                        current_ptr = target_ptr;
+                    }
+                    opt_code.push_set(val.clone(), span);
                 }
-                opt_code.push_set(*val);
             }
         }
 
+        let ptr = tape.pos();
         if current_ptr != ptr {
-            let off = (ptr as isize) - (current_ptr as isize);
-            opt_code.push_move(off);
+            opt_code.push_move(ptr - current_ptr, span);
         }
 
         while let Some(instr) = code.get(pc) {
@@ -198,12 +163,12 @@ pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int
                     pc = pc_loop_start;
                 },
                 _ => {
-                    opt_code.push(instr);
+                    opt_code.push(instr, code.span(pc));
                     pc += 1;
                 }
             }
         }
     }
 
-    return Ok(opt_code);
-}
\ No newline at end of file
+    Ok(opt_code)
+}
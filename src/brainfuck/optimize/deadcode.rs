@@ -1,29 +1,29 @@
 extern crate num_traits;
 use super::super::{Brainfuck, BrainfuckInteger, Instruct};
 
-pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
+pub fn optimize<Int: BrainfuckInteger>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
     let mut opt_code = Brainfuck::new();
     let mut index = 0usize;
 
     loop {
         match (code.get(index), code.get(index + 1)) {
             (Some(Instruct::Set(val)), Some(Instruct::LoopStart(end_index))) => {
-                index += 2;
-
-                if *val == Int::zero() {
+                opt_code.push_set(val.clone(), code.span(index));
+                if val.is_zero() {
+                    // the loop is never entered
                     index = *end_index;
                 } else {
-                    opt_code.push_set(*val);
-                    opt_code.push_loop_start();
+                    opt_code.push_loop_start(code.span(index + 1));
+                    index += 2;
                 }
             },
             (Some(instr), _) => {
-                opt_code.push(instr);
+                opt_code.push(instr, code.span(index));
                 index += 1;
             },
             (None, _) => break
         }
     }
 
-    return opt_code;
+    opt_code
 }
\ No newline at end of file
@@ -1,52 +1,56 @@
 extern crate num_traits;
 use super::super::{Brainfuck, BrainfuckInteger, Instruct};
 
-pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
+pub fn optimize<Int: BrainfuckInteger>(code: &Brainfuck<Int>, trap_overflow: bool) -> Brainfuck<Int> {
+    let trap_overflow = trap_overflow && Int::size().is_some();
     let mut opt_code = Brainfuck::new();
     let mut index = 0usize;
 
-    loop {
-        if let Some(instr) = code.get(index) {
-            index += 1;
-            match *instr {
-                Instruct::Move(val1) => {
-                    let mut val = val1;
-                    while let Some(Instruct::Move(val2)) = code.get(index) {
-                        index += 1;
-                        val += *val2;
+    while let Some(instr) = code.get(index) {
+        let start_index = index;
+        index += 1;
+        match *instr {
+            Instruct::Move(val1) => {
+                let mut val = val1;
+                while let Some(Instruct::Move(val2)) = code.get(index) {
+                    index += 1;
+                    val += *val2;
+                }
+                if val != 0 {
+                    opt_code.push_move(val, code.span_range(start_index, index));
+                }
+            },
+            Instruct::Add(val1) => {
+                let mut val = val1;
+                while let Some(Instruct::Add(val2)) = code.get(index) {
+                    // +- would hide the overflow of + on the largest value
+                    if trap_overflow && (val < 0) != (*val2 < 0) {
+                        break;
                     }
-                    if val != 0 {
-                        opt_code.push_move(val);
-                    }
-                },
-                Instruct::Add(val1) => {
-                    let mut val = val1;
-                    while let Some(Instruct::Add(val2)) = code.get(index) {
-                        index += 1;
-                        val = val + *val2;
-                    }
-                    if val != Int::zero() {
-                        opt_code.push_add(val);
-                    }
-                },
-                Instruct::Set(val1) => {
-                    let before = code.find_set_before(index - 1);
-                    let mut val = val1;
-                    while let Some(Instruct::Set(val2)) = code.get(index) {
-                        index += 1;
-                        val = *val2;
-                    }
-                    match before {
-                        Some(before_val) if before_val == val => {},
-                        _ => opt_code.push_set(val),
-                    }
-                },
-                _ => opt_code.push(instr)
-            }
-        } else {
-            break;
+                    index += 1;
+                    val = val.wrapping_add(*val2);
+                }
+                // not wrapped to the cell width, so --trap-overflow still sees
+                // e.g. 256 times + on a byte cell
+                if val != 0 {
+                    opt_code.push_add(val, code.span_range(start_index, index));
+                }
+            },
+            Instruct::Set(ref val1) => {
+                let before = code.find_set_before(index - 1);
+                let mut val = val1.clone();
+                while let Some(Instruct::Set(val2)) = code.get(index) {
+                    index += 1;
+                    val = val2.clone();
+                }
+                match before {
+                    Some(before_val) if before_val == val => {},
+                    _ => opt_code.push_set(val, code.span_range(start_index, index)),
+                }
+            },
+            _ => opt_code.push(instr, code.span(start_index))
         }
     }
 
-    return opt_code;
+    opt_code
 }
\ No newline at end of file
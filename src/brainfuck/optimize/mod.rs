@@ -1,3 +1,7 @@
+//! Optimization passes. Each pass takes a program and returns the optimized
+//! copy, [`Brainfuck::optimize`](super::Brainfuck::optimize) runs the passes
+//! enabled in [`Options`] in the right order.
+
 mod fold;
 mod set;
 mod add_to;
@@ -13,7 +17,12 @@ pub use write::optimize as write;
 pub use deadcode::optimize as deadcode;
 pub use constexpr::optimize as constexpr;
 pub use skip::optimize as skip;
+pub(crate) use skip::{has_set_after, is_dead};
+
+use super::TapeOptions;
 
+/// Enabled optimizations and the machine they optimize for.
+#[derive(Clone, Default)]
 pub struct Options {
     pub fold:           bool,
     pub set:            bool,
@@ -23,21 +32,8 @@ pub struct Options {
     pub constexpr:      bool,
     pub constexpr_echo: bool,
     pub skip:           bool,
-}
-
-impl std::default::Default for Options {
-    fn default() -> Self {
-        Options {
-            fold:           false,
-            set:            false,
-            add_to:         false,
-            write:          false,
-            deadcode:       false,
-            constexpr:      false,
-            constexpr_echo: false,
-            skip:           false,
-        }
-    }
+    pub tape:           TapeOptions,
+    pub trap_overflow:  bool,
 }
 
 impl Options {
@@ -51,6 +47,8 @@ impl Options {
             constexpr:      true,
             constexpr_echo: true,
             skip:           true,
+            tape:           TapeOptions::default(),
+            trap_overflow:  false,
         }
     }
 
@@ -64,6 +62,75 @@ impl Options {
             constexpr:      false,
             constexpr_echo: false,
             skip:           false,
+            tape:           TapeOptions::default(),
+            trap_overflow:  false,
+        }
+    }
+}
+
+/// Parses a comma separated list like `all,-constexpr` or `fold,set`, starting
+/// with no optimizations. The error is the first unknown name.
+impl std::str::FromStr for Options {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut options = Options::none();
+        for opt in s.split(',') {
+            match opt {
+                "all" | "+all" | "-none" => {
+                    options = Options::all();
+                },
+                "none" | "+none" | "-all" => {
+                    options = Options::none();
+                },
+                "fold" | "+fold" => {
+                    options.fold = true;
+                },
+                "-fold" => {
+                    options.fold = false;
+                },
+                "set" | "+set" => {
+                    options.set = true;
+                },
+                "-set" => {
+                    options.set = false;
+                },
+                "add_to" | "+add_to" | "addto" | "+addto" => {
+                    options.add_to = true;
+                },
+                "-add_to" | "-addto" => {
+                    options.add_to = false;
+                },
+                "write" | "+write" => {
+                    options.write = true;
+                },
+                "-write" => {
+                    options.write = false;
+                },
+                "deadcode" | "+deadcode" => {
+                    options.deadcode = true;
+                },
+                "-deadcode" => {
+                    options.deadcode = false;
+                },
+                "constexpr" | "+constexpr" => {
+                    options.constexpr = true;
+                },
+                "-constexpr" => {
+                    options.constexpr = false;
+                },
+                "skip" | "+skip" => {
+                    options.skip = true;
+                },
+                "-skip" => {
+                    options.skip = false;
+                },
+                "" => {},
+                _ => {
+                    return Err(opt.to_string());
+                }
+            }
         }
+        Ok(options)
     }
 }
\ No newline at end of file
@@ -1,34 +1,46 @@
 extern crate num_traits;
 use super::super::{Brainfuck, BrainfuckInteger, Instruct};
 
-pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
+// With trap_overflow a loop like [-] is only a plain assignment if it can't
+// wrap around on the way to 0, which is only known for [-] on unsigned cells.
+fn clears<Int: BrainfuckInteger>(delta: isize, trap_overflow: bool) -> bool {
+    !trap_overflow || Int::size().is_none() || (delta == -1 && !Int::is_signed())
+}
+
+pub fn optimize<Int: BrainfuckInteger>(code: &Brainfuck<Int>, trap_overflow: bool) -> Brainfuck<Int> {
     let mut opt_code = Brainfuck::new();
     let mut index = 0usize;
 
     loop {
-        match (code.get(index), code.get(index + 1), code.get(index + 2), code.get(index + 3)) {
-            (Some(Instruct::LoopStart(_)), Some(Instruct::Add(_)), Some(Instruct::LoopEnd(_)), Some(Instruct::Add(val))) => {
-                index += 4;
-                opt_code.push_set(*val);
-                continue;
-            },
-            _ => {}
+        if let (Some(Instruct::LoopStart(_)), Some(Instruct::Add(delta)), Some(Instruct::LoopEnd(_)), Some(Instruct::Add(val))) = (code.get(index), code.get(index + 1), code.get(index + 2), code.get(index + 3)) {
+            // the add traps if it leaves the range of the cell
+            let set_val = if trap_overflow { Int::zero().checked_add_delta(*val) } else { Some(Int::from_delta(*val)) };
+            match set_val {
+                Some(set_val) if clears::<Int>(*delta, trap_overflow) => {
+                    opt_code.push_set(set_val, code.span_range(index, index + 4));
+                    index += 4;
+                    continue;
+                },
+                _ => {},
+            }
         }
 
-        if let (Some(Instruct::LoopStart(_)), Some(Instruct::Add(_)), Some(Instruct::LoopEnd(_))) =
+        if let (Some(Instruct::LoopStart(_)), Some(Instruct::Add(delta)), Some(Instruct::LoopEnd(_))) =
                 (code.get(index), code.get(index + 1), code.get(index + 2)) {
-            index += 3;
-            opt_code.push_set(Int::zero());
-            continue;
+            if clears::<Int>(*delta, trap_overflow) {
+                opt_code.push_set(Int::zero(), code.span_range(index, index + 3));
+                index += 3;
+                continue;
+            }
         }
 
         if let Some(instr) = code.get(index) {
+            opt_code.push(instr, code.span(index));
             index += 1;
-            opt_code.push(instr);
         } else {
             break;
         }
     }
 
-    return opt_code;
+    opt_code
 }
\ No newline at end of file
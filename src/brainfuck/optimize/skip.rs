@@ -1,8 +1,11 @@
 extern crate num_traits;
 use super::super::{Brainfuck, BrainfuckInteger, Instruct};
 
-fn unchanged_ptr_loop_end<Int>(code: &Brainfuck<Int>, mut current_off: isize, target_off: isize, mut index: usize) -> Option<usize>
-    where Int: BrainfuckInteger + num_traits::Signed {
+// index after the end of the loop whose body starts at index, if the loop
+// doesn't move the pointer overall and doesn't read the cell at target_off
+fn unchanged_ptr_loop_end<Int>(code: &Brainfuck<Int>, start_off: isize, target_off: isize, mut index: usize) -> Option<usize>
+    where Int: BrainfuckInteger {
+    let mut current_off = start_off;
     loop {
         if let Some(instr) = code.get(index) {
             index += 1;
@@ -11,13 +14,20 @@ fn unchanged_ptr_loop_end<Int>(code: &Brainfuck<Int>, mut current_off: isize, ta
                     // might not happen depending on loop condition
                 },
                 Instruct::AddTo(_) | Instruct::SubFrom(_) | Instruct::Write => {
-                    // might not happen depending on loop condition
+                    // reads the cell
+                    if current_off == target_off {
+                        return None;
+                    }
                 },
                 Instruct::Add(_) | Instruct::WriteStr(_) => {},
                 Instruct::Move(off) => {
                     current_off += off;
                 },
                 Instruct::LoopStart(_) => {
+                    // the loop condition reads the cell
+                    if current_off == target_off {
+                        return None;
+                    }
                     if let Some(end_index) = unchanged_ptr_loop_end(code, current_off, target_off, index) {
                         index = end_index;
                     } else {
@@ -25,10 +35,14 @@ fn unchanged_ptr_loop_end<Int>(code: &Brainfuck<Int>, mut current_off: isize, ta
                     }
                 },
                 Instruct::LoopEnd(_) => {
-                    if current_off == target_off {
+                    if current_off == start_off {
                         return Some(index);
                     }
                     return None;
+                },
+                Instruct::DebugDump => {
+                    // might see the cell
+                    return None;
                 }
             }
         } else {
@@ -37,71 +51,96 @@ fn unchanged_ptr_loop_end<Int>(code: &Brainfuck<Int>, mut current_off: isize, ta
     }
 }
 
-fn has_set_after<Int>(code: &Brainfuck<Int>, target_off: isize, mut index: usize) -> bool
-    where Int: BrainfuckInteger + num_traits::Signed {
+// true if the cell at target_off is overwritten before anything reads it
+pub(crate) fn has_set_after<Int>(code: &Brainfuck<Int>, target_off: isize, index: usize) -> bool
+    where Int: BrainfuckInteger {
+    set_after(code, target_off, index, false)
+}
+
+// true if nothing reads the cell at target_off anymore, because it is
+// overwritten first or the program ends
+pub(crate) fn is_dead<Int>(code: &Brainfuck<Int>, target_off: isize, index: usize) -> bool
+    where Int: BrainfuckInteger {
+    set_after(code, target_off, index, true)
+}
+
+fn set_after<Int>(code: &Brainfuck<Int>, target_off: isize, mut index: usize, dead_at_end: bool) -> bool
+    where Int: BrainfuckInteger {
     let mut current_off = 0;
-    loop {
-        if let Some(instr) = code.get(index) {
-            index += 1;
-            match *instr {
-                Instruct::Set(_) | Instruct::Read => {
-                    if current_off == target_off {
-                        return true;
-                    }
-                },
-                Instruct::AddTo(_) | Instruct::SubFrom(_) | Instruct::Write => {
-                    if current_off == target_off {
-                        return false;
-                    }
-                },
-                Instruct::Add(_) | Instruct::WriteStr(_) => {},
-                Instruct::Move(off) => {
-                    current_off += off;
-                },
-                Instruct::LoopStart(_) => {
-                    if let Some(end_index) = unchanged_ptr_loop_end(code, current_off, target_off, index) {
-                        index = end_index;
-                    } else {
-                        return false;
-                    }
-                },
-                Instruct::LoopEnd(_) => {
+    while let Some(instr) = code.get(index) {
+        index += 1;
+        match *instr {
+            Instruct::Set(_) | Instruct::Read => {
+                if current_off == target_off {
+                    return true;
+                }
+            },
+            Instruct::AddTo(_) | Instruct::SubFrom(_) | Instruct::Write => {
+                if current_off == target_off {
+                    return false;
+                }
+            },
+            Instruct::Add(_) | Instruct::WriteStr(_) => {},
+            Instruct::Move(off) => {
+                current_off += off;
+            },
+            Instruct::LoopStart(_) => {
+                if current_off == target_off {
+                    return false;
+                }
+                if let Some(end_index) = unchanged_ptr_loop_end(code, current_off, target_off, index) {
+                    index = end_index;
+                } else {
+                    return false;
+                }
+            },
+            Instruct::LoopEnd(start_index) => {
+                // the loop either ends or runs its body again
+                if current_off == target_off {
+                    return false;
+                }
+                if unchanged_ptr_loop_end(code, current_off, target_off, start_index + 1).is_none() {
                     return false;
                 }
+            },
+            Instruct::DebugDump => {
+                return false;
             }
-        } else {
-            break;
         }
     }
-    return false;
+    dead_at_end
 }
 
-pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
+pub fn optimize<Int: BrainfuckInteger>(code: &Brainfuck<Int>, trap_overflow: bool) -> Brainfuck<Int> {
+    // with trap_overflow an overwritten add still has to trap
+    let trap_overflow = trap_overflow && Int::size().is_some();
     let mut opt_code = Brainfuck::new();
     let mut index = 0usize;
 
-    loop {
-        // this partially re-scans the same part again and again
-        // could be done more efficiently
-        if let Some(instr) = code.get(index) {
-            index += 1;
-            match *instr {
-                Instruct::Set(_) | Instruct::Add(_) => {
-                    if !has_set_after(code, 0, index) {
-                        opt_code.push(instr);
-                    }
-                },
-                Instruct::AddTo(off) | Instruct::SubFrom(off) => {
-                    if !has_set_after(code, off, index) {
-                        opt_code.push(instr);
-                    }
-                },
-                _ => opt_code.push(instr),
-            }
-        } else {
-            break;
+    // this partially re-scans the same part again and again
+    // could be done more efficiently
+    while let Some(instr) = code.get(index) {
+        let span = code.span(index);
+        index += 1;
+        match *instr {
+            Instruct::Set(_) => {
+                if !has_set_after(code, 0, index) {
+                    opt_code.push(instr, span);
+                }
+            },
+            Instruct::Add(_) => {
+                if trap_overflow || !has_set_after(code, 0, index) {
+                    opt_code.push(instr, span);
+                }
+            },
+            Instruct::AddTo(off) | Instruct::SubFrom(off) => {
+                if trap_overflow || !has_set_after(code, off, index) {
+                    opt_code.push(instr, span);
+                }
+            },
+            _ => opt_code.push(instr, span),
         }
     }
 
-    return opt_code;
+    opt_code
 }
\ No newline at end of file
@@ -1,11 +1,11 @@
 extern crate num_traits;
 use super::super::{Brainfuck, BrainfuckInteger, Instruct};
 
-fn optimize_write_str<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int>, mut index: usize, mut last_val: Int, data: &mut Vec<u8>) -> (usize, Int) {
+fn optimize_write_str<Int: BrainfuckInteger>(code: &Brainfuck<Int>, mut index: usize, mut last_val: Int, data: &mut Vec<u8>) -> (usize, Int) {
     loop {
         if let (Some(Instruct::Set(val)), Some(Instruct::Write)) = (code.get(index), code.get(index + 1)) {
             index += 2;
-            last_val = *val;
+            last_val = val.clone();
             data.push(val.get_least_byte());
         } else if let Some(Instruct::Write) = code.get(index) {
             index += 1;
@@ -17,45 +17,45 @@ fn optimize_write_str<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfu
             break;
         }
     }
-    return (index, last_val);
+    (index, last_val)
 }
 
-pub fn optimize<Int: BrainfuckInteger + num_traits::Signed>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
+pub fn optimize<Int: BrainfuckInteger>(code: &Brainfuck<Int>) -> Brainfuck<Int> {
     let mut opt_code = Brainfuck::new();
     let mut index = 0usize;
 
     loop {
-        match (code.get(index), code.get(index + 1)) {
-            (Some(Instruct::Set(val)), Some(Instruct::Write)) => {
-                index += 2;
-                let mut data = vec![val.get_least_byte()];
-                let (new_index, last_val) = optimize_write_str(code, index, *val, &mut data);
-                index = new_index;
-                // preserve last value, it might be used!
-                opt_code.push_set(last_val);
-                opt_code.push_write_str(data);
-                continue;
-            },
-            _ => {}
+        if let (Some(Instruct::Set(val)), Some(Instruct::Write)) = (code.get(index), code.get(index + 1)) {
+            let start_index = index;
+            index += 2;
+            let mut data = vec![val.get_least_byte()];
+            let (new_index, last_val) = optimize_write_str(code, index, val.clone(), &mut data);
+            index = new_index;
+            let span = code.span_range(start_index, index);
+            // preserve last value, it might be used!
+            opt_code.push_set(last_val, span);
+            opt_code.push_write_str(data, span);
+            continue;
         }
 
         if let Some(instr) = code.get(index) {
+            let start_index = index;
             index += 1;
             if let Instruct::WriteStr(data) = instr {
-                if data.len() > 0 {
+                if !data.is_empty() {
                     let mut data = data.to_vec();
                     let last_val = Int::from_byte(data[data.len() - 1]);
                     let (new_index, _) = optimize_write_str(code, index, last_val, &mut data);
                     index = new_index;
-                    opt_code.push_write_str(data);
+                    opt_code.push_write_str(data, code.span_range(start_index, index));
                 }
             } else {
-                opt_code.push(instr);
+                opt_code.push(instr, code.span(start_index));
             }
         } else {
             break;
         }
     }
 
-    return opt_code;
+    opt_code
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+//! Execution counts of the interpreter. Everything else (loop iterations,
+//! time spent inside of loops, the loop nesting) follows from how often each
+//! instruction ran, so that is all that is recorded during execution.
+
+use std::io::Write;
+use super::{Brainfuck, BrainfuckInteger, Instruct, Span};
+
+pub struct Profile {
+    counts: Vec<u64>,
+}
+
+pub struct LoopStats {
+    pub start: usize,
+    pub span: Span,
+    /// executed instructions inside of the loop, including nested loops
+    pub steps: u64,
+    pub iterations: u64,
+    pub entries: u64,
+}
+
+impl Profile {
+    pub fn new<Int: BrainfuckInteger>(code: &Brainfuck<Int>) -> Self {
+        Profile {
+            counts: vec![0; code.len()],
+        }
+    }
+
+    #[inline]
+    pub fn count(&mut self, pc: usize) {
+        self.counts[pc] += 1;
+    }
+
+    /// execution count of every instruction
+    #[inline]
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// all loops of the program, most expensive first
+    pub fn loops<Int: BrainfuckInteger>(&self, code: &Brainfuck<Int>) -> Vec<LoopStats> {
+        let mut loops = Vec::new();
+        for (start, instr) in code.iter().enumerate() {
+            if let Instruct::LoopStart(end) = *instr {
+                // LoopStart points behind the matching LoopEnd
+                let end = end - 1;
+                let iterations = self.counts[end];
+                loops.push(LoopStats {
+                    start,
+                    span: code.span(start).join(code.span(end)),
+                    steps: self.counts[start..=end].iter().sum(),
+                    iterations,
+                    entries: self.counts[start] - iterations,
+                });
+            }
+        }
+        loops.sort_by(|a, b| b.steps.cmp(&a.steps).then(a.start.cmp(&b.start)));
+        loops
+    }
+
+    pub fn write_report<Int: BrainfuckInteger>(&self, code: &Brainfuck<Int>, out: &mut dyn Write, limit: usize) -> std::io::Result<()> {
+        let total = self.steps();
+        writeln!(out, "profile: {} executed instructions", total)?;
+
+        let loops = self.loops(code);
+        if loops.is_empty() {
+            return Ok(());
+        }
+
+        write!(out, "\nhot loops:\n")?;
+        writeln!(out, "{:>4}  {:>12}  {:>6}  {:>12}  {:>8}  source", "rank", "steps", "%", "iterations", "entries")?;
+        for (rank, stats) in loops.iter().take(limit).enumerate() {
+            writeln!(out, "{:>4}  {:>12}  {:>5.1}%  {:>12}  {:>8}  {}",
+                rank + 1, stats.steps, percent(stats.steps, total), stats.iterations, stats.entries,
+                SpanRange(stats.span))?;
+        }
+
+        let mut instrs: Vec<usize> = (0..self.counts.len()).filter(|pc| self.counts[*pc] > 0).collect();
+        instrs.sort_by(|a, b| self.counts[*b].cmp(&self.counts[*a]).then(a.cmp(b)));
+
+        write!(out, "\nhot instructions:\n")?;
+        writeln!(out, "{:>4}  {:>12}  {:>6}  {:>6}  {:<16}  source", "rank", "count", "%", "pc", "instruction")?;
+        for (rank, pc) in instrs.iter().take(limit).enumerate() {
+            let count = self.counts[*pc];
+            let instr = code.get(*pc).map(|instr| instr.to_string()).unwrap_or_default();
+            writeln!(out, "{:>4}  {:>12}  {:>5.1}%  {:>6}  {:<16}  {}",
+                rank + 1, count, percent(count, total), pc, instr, SpanRange(code.span(*pc)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Folded stacks as used by flamegraph.pl and inferno: one line per loop
+    /// nesting with the instructions executed directly in that loop.
+    pub fn write_folded<Int: BrainfuckInteger>(&self, code: &Brainfuck<Int>, out: &mut dyn Write) -> std::io::Result<()> {
+        let mut stack = vec![String::from("main")];
+        let mut selfs = vec![0u64];
+        for (pc, instr) in code.iter().enumerate() {
+            match *instr {
+                Instruct::LoopStart(_) => {
+                    stack.push(format!("loop@{}", SpanRange(code.span(pc).join(code.span(instr_end(code, pc))))));
+                    selfs.push(self.counts[pc]);
+                },
+                Instruct::LoopEnd(_) => {
+                    let count = selfs.pop().unwrap_or(0) + self.counts[pc];
+                    if count > 0 {
+                        writeln!(out, "{} {}", stack.join(";"), count)?;
+                    }
+                    stack.pop();
+                },
+                _ => {
+                    if let Some(count) = selfs.last_mut() {
+                        *count += self.counts[pc];
+                    }
+                }
+            }
+        }
+
+        let count = selfs.pop().unwrap_or(0);
+        if count > 0 {
+            writeln!(out, "main {}", count)?;
+        }
+
+        Ok(())
+    }
+}
+
+// pc of the LoopEnd matching the LoopStart at pc
+fn instr_end<Int: BrainfuckInteger>(code: &Brainfuck<Int>, pc: usize) -> usize {
+    match code.get(pc) {
+        Some(Instruct::LoopStart(end)) => end - 1,
+        _ => pc,
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+// displays the whole span instead of just its start
+struct SpanRange(Span);
+
+impl std::fmt::Display for SpanRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let span = self.0;
+        if span.is_unknown() {
+            f.write_str("?")
+        } else if span.start == span.end {
+            write!(f, "{}:{}", span.start.lineno, span.start.column)
+        } else {
+            write!(f, "{}:{}-{}:{}", span.start.lineno, span.start.column, span.end.lineno, span.end.column)
+        }
+    }
+}
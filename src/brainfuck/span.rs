@@ -0,0 +1,78 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Pos {
+    pub lineno: usize,
+    pub column: usize,
+}
+
+/// Source range an instruction was generated from. Both ends are inclusive.
+/// Instructions not generated from any source (lineno 0) have an unknown span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: Pos,
+    pub end: Pos,
+}
+
+impl Pos {
+    #[inline]
+    pub fn new(lineno: usize, column: usize) -> Self {
+        Pos { lineno, column }
+    }
+}
+
+impl Span {
+    #[inline]
+    pub fn at(lineno: usize, column: usize) -> Self {
+        let pos = Pos::new(lineno, column);
+        Span { start: pos, end: pos }
+    }
+
+    #[inline]
+    pub fn unknown() -> Self {
+        Span::default()
+    }
+
+    #[inline]
+    pub fn is_unknown(&self) -> bool {
+        self.start.lineno == 0
+    }
+
+    pub fn join(self, other: Span) -> Span {
+        if self.is_unknown() {
+            other
+        } else if other.is_unknown() {
+            self
+        } else {
+            Span {
+                start: std::cmp::min(self.start, other.start),
+                end:   std::cmp::max(self.end, other.end),
+            }
+        }
+    }
+
+    /// Prints the source line the span starts on with a marker below the
+    /// span. line is the text of that line.
+    pub fn write_snippet(&self, out: &mut dyn std::io::Write, line: &str) -> std::io::Result<()> {
+        let prefix = format!("{:5} | ", self.start.lineno);
+        writeln!(out, "{}{}", prefix, line)?;
+
+        // keep tabs so the marker lines up
+        let mut marker: String = " ".repeat(prefix.len() - 2);
+        marker.push_str("| ");
+        for c in line.chars().take(self.start.column - 1) {
+            marker.push(if c == '\t' { '\t' } else { ' ' });
+        }
+        let len = if self.end.lineno == self.start.lineno && self.end.column >= self.start.column {
+            self.end.column - self.start.column + 1
+        } else {
+            1
+        };
+        marker.push_str(&"^".repeat(len));
+        writeln!(out, "{}", marker)
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.start.lineno, self.start.column)
+    }
+}
@@ -0,0 +1,218 @@
+pub const DEFAULT_TAPE_SIZE: usize = 30000;
+
+/// Largest size of a bounded tape, so that logical indices fit into an isize
+/// and the compiled program can address the tape in bytes even with 64 bit
+/// cells.
+pub const MAX_TAPE_SIZE: usize = (isize::MAX / 8) as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapeEdge {
+    /// unbounded tape that grows in both directions (default)
+    Grow,
+    /// classic fixed size tape, moving past one end continues at the other end
+    Wrap,
+    /// fixed size tape, moving past either end is an error
+    Error,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TapeOptions {
+    /// number of cells of a bounded tape, ignored for TapeEdge::Grow
+    pub size: usize,
+    pub edge: TapeEdge,
+}
+
+impl std::default::Default for TapeOptions {
+    fn default() -> Self {
+        TapeOptions {
+            size: DEFAULT_TAPE_SIZE,
+            edge: TapeEdge::Grow,
+        }
+    }
+}
+
+impl TapeOptions {
+    pub fn new(size: usize, edge: TapeEdge) -> Self {
+        TapeOptions { size, edge }
+    }
+
+    #[inline]
+    pub fn is_bounded(&self) -> bool {
+        self.edge != TapeEdge::Grow
+    }
+}
+
+impl std::str::FromStr for TapeEdge {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "grow"  => Ok(TapeEdge::Grow),
+            "wrap"  => Ok(TapeEdge::Wrap),
+            "error" => Ok(TapeEdge::Error),
+            _       => Err(format!("illegal tape edge: {}", s)),
+        }
+    }
+}
+
+/// Memory of the interpreter and of the constexpr evaluator.
+/// Cell indices are logical, i.e. relative to the cell the program started at.
+/// A grow-tape that is extended to the left keeps those indices stable.
+pub struct Tape<T: Clone> {
+    mem:     Vec<T>,
+    ptr:     usize,
+    origin:  usize,
+    blank:   T,
+    options: TapeOptions,
+}
+
+impl<T: Clone> Tape<T> {
+    /// A bounded tape allocates its cells only up to the highest cell that
+    /// was touched, like a tape that grows to the right.
+    pub fn new(options: TapeOptions, blank: T) -> Self {
+        Tape {
+            mem: Vec::new(),
+            ptr: 0,
+            origin: 0,
+            blank,
+            options,
+        }
+    }
+
+    /// logical index of the current cell
+    #[inline]
+    pub fn pos(&self) -> isize {
+        self.ptr as isize - self.origin as isize
+    }
+
+    /// logical index of the cell at `off` relative to the pointer
+    pub fn pos_at(&self, off: isize) -> isize {
+        if self.options.edge == TapeEdge::Wrap {
+            (self.ptr as isize + off).rem_euclid(self.options.size as isize)
+        } else {
+            self.pos() + off
+        }
+    }
+
+    /// logical index of the first cell in cells()
+    #[inline]
+    pub fn start(&self) -> isize {
+        -(self.origin as isize)
+    }
+
+    /// the cell with the given logical index, blank if it was never touched
+    pub fn get(&self, index: isize) -> &T {
+        let index = index + self.origin as isize;
+        if index < 0 {
+            &self.blank
+        } else {
+            self.mem.get(index as usize).unwrap_or(&self.blank)
+        }
+    }
+
+    /// all cells that were touched so far
+    #[inline]
+    pub fn cells(&self) -> &[T] {
+        &self.mem
+    }
+
+    /// number of cells the tape would use once the cell at `off` relative to
+    /// the pointer is touched
+    pub fn cells_with(&self, off: isize) -> usize {
+        let target = self.ptr as isize + off;
+        let target = match self.options.edge {
+            TapeEdge::Grow if target < 0 => return self.mem.len() + (-target) as usize,
+            TapeEdge::Wrap => target.rem_euclid(self.options.size as isize),
+            // moving there fails before anything is allocated
+            TapeEdge::Error if target < 0 || target as usize >= self.options.size => return self.mem.len(),
+            _ => target,
+        };
+        std::cmp::max(self.mem.len(), target as usize + 1)
+    }
+
+    // Physical index of the cell at `off` relative to the pointer. Grows the
+    // tape to the left if needed. Returns the logical index of the cell as the
+    // error if it is outside of a bounded tape.
+    fn index(&mut self, off: isize) -> std::result::Result<usize, isize> {
+        let target = self.ptr as isize + off;
+        match self.options.edge {
+            TapeEdge::Grow => {
+                if target < 0 {
+                    let diff = (-target) as usize;
+                    let chunk = vec![self.blank.clone(); diff];
+                    self.mem.splice(..0, chunk);
+                    self.ptr    += diff;
+                    self.origin += diff;
+                    Ok(0)
+                } else {
+                    Ok(target as usize)
+                }
+            },
+            TapeEdge::Wrap => {
+                Ok(target.rem_euclid(self.options.size as isize) as usize)
+            },
+            TapeEdge::Error => {
+                if target < 0 || target as usize >= self.options.size {
+                    Err(target - self.origin as isize)
+                } else {
+                    Ok(target as usize)
+                }
+            }
+        }
+    }
+
+    pub fn move_by(&mut self, off: isize) -> std::result::Result<(), isize> {
+        self.ptr = self.index(off)?;
+        Ok(())
+    }
+
+    /// the current cell
+    pub fn cell(&mut self) -> &mut T {
+        let ptr = self.ptr;
+        self.cell_at_index(ptr)
+    }
+
+    /// the cell at `off` relative to the pointer
+    pub fn cell_at(&mut self, off: isize) -> std::result::Result<&mut T, isize> {
+        let index = self.index(off)?;
+        Ok(self.cell_at_index(index))
+    }
+
+    fn cell_at_index(&mut self, index: usize) -> &mut T {
+        if index >= self.mem.len() {
+            self.mem.resize(index + 1, self.blank.clone());
+        }
+        &mut self.mem[index]
+    }
+}
+
+impl<T: Clone + std::fmt::Debug> Tape<T> {
+    /// prints the cells within radius around the pointer
+    pub fn write_window(&self, out: &mut dyn std::io::Write, radius: usize) -> std::io::Result<()> {
+        let pos = self.pos();
+        let mut start = pos - radius as isize;
+        let mut end = pos + radius as isize;
+        if self.options.is_bounded() {
+            start = std::cmp::max(start, 0);
+            end = std::cmp::min(end, self.options.size as isize - 1);
+        }
+
+        let mut indices = String::from("cell ");
+        let mut values  = String::from("value");
+        let mut marker  = String::from("     ");
+        for index in start..=end {
+            let index_str = format!("{}", index);
+            let value_str = format!("{:?}", self.get(index));
+            let width = std::cmp::max(index_str.len(), value_str.len()) + 1;
+            indices.push_str(&format!("{:>width$}", index_str, width = width));
+            values.push_str(&format!("{:>width$}", value_str, width = width));
+            if index == pos {
+                marker.push_str(&format!("{:>width$}", "^", width = width));
+            } else {
+                marker.push_str(&" ".repeat(width));
+            }
+        }
+
+        write!(out, "{}\n{}\n{}\n", indices, values, marker.trim_end())
+    }
+}
\ No newline at end of file
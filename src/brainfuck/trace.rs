@@ -0,0 +1,180 @@
+//! Execution trace of the interpreter, one record per executed instruction.
+//!
+//! Text format, one line per record:
+//!
+//! ```text
+//! STEP PC LINE:COLUMN PTR BEFORE AFTER
+//! ```
+//!
+//! BEFORE and AFTER are the values of the cell at PTR (the pointer before the
+//! instruction was executed).
+//!
+//! Binary format: the header "BFT", a version byte (1), the cell size in bits
+//! (0 for big cells) and a signedness byte (0 or 1), followed by the records.
+//! Numbers are LEB128 varints, signed numbers are zigzag encoded first:
+//!
+//! ```text
+//! step delta (to the previous record), pc, line, column, zigzag ptr,
+//! before, after
+//! ```
+//!
+//! Fixed size cell values are zigzag varints of the value reinterpreted as a
+//! signed number of the cell width. Big cell values are a varint length
+//! followed by that many bytes of the value in decimal.
+
+use std::io::Write;
+use super::{BrainfuckInteger, Span};
+use super::span::Pos;
+
+pub const TRACE_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+impl std::str::FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text"   => Ok(TraceFormat::Text),
+            "binary" => Ok(TraceFormat::Binary),
+            _        => Err(format!("illegal trace format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TraceOptions {
+    pub format: TraceFormat,
+    /// only record instructions whose source span overlaps this range
+    pub range: Option<Span>,
+}
+
+impl std::default::Default for TraceOptions {
+    fn default() -> Self {
+        TraceOptions {
+            format: TraceFormat::Text,
+            range: None,
+        }
+    }
+}
+
+/// Parses a source range like "3:5-7:1". A position without column covers
+/// the whole line, so "3-4" is everything from line 3 up to line 4 and a
+/// single position (e.g. "3") is just that line or column.
+pub fn parse_range(s: &str) -> std::result::Result<Span, String> {
+    let (start, end) = match s.find('-') {
+        Some(index) => (&s[..index], &s[index + 1..]),
+        None        => (s, s),
+    };
+
+    let start = parse_pos(start, 1).ok_or_else(|| format!("illegal source range: {}", s))?;
+    let end = parse_pos(end, usize::MAX).ok_or_else(|| format!("illegal source range: {}", s))?;
+
+    if end < start {
+        return Err(format!("illegal source range: {}", s));
+    }
+
+    Ok(Span { start, end })
+}
+
+fn parse_pos(s: &str, default_column: usize) -> Option<Pos> {
+    let mut parts = s.splitn(2, ':');
+    let lineno: usize = parts.next()?.trim().parse().ok()?;
+    let column: usize = match parts.next() {
+        Some(column) => column.trim().parse().ok()?,
+        None         => default_column,
+    };
+    if lineno == 0 || column == 0 {
+        return None;
+    }
+    Some(Pos::new(lineno, column))
+}
+
+pub struct Tracer<W: Write, Int: BrainfuckInteger> {
+    out: W,
+    options: TraceOptions,
+    last_step: u64,
+    phantom: std::marker::PhantomData<Int>,
+}
+
+impl<W: Write, Int: BrainfuckInteger> Tracer<W, Int> {
+    pub fn new(mut out: W, options: TraceOptions) -> std::io::Result<Self> {
+        match options.format {
+            TraceFormat::Text => {
+                out.write_all(b"# step pc pos ptr before after\n")?;
+            },
+            TraceFormat::Binary => {
+                let bits = Int::size().unwrap_or(0) * 8;
+                out.write_all(b"BFT")?;
+                out.write_all(&[TRACE_VERSION, bits as u8, Int::is_signed() as u8])?;
+            }
+        }
+
+        Ok(Tracer {
+            out,
+            options,
+            last_step: 0,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    pub fn record(&mut self, step: u64, pc: usize, span: Span, ptr: isize, before: &Int, after: &Int) -> std::io::Result<()> {
+        if let Some(range) = self.options.range {
+            if span.is_unknown() || span.start > range.end || span.end < range.start {
+                return Ok(());
+            }
+        }
+
+        match self.options.format {
+            TraceFormat::Text => {
+                writeln!(self.out, "{} {} {} {} {:?} {:?}", step, pc, span, ptr, before, after)?;
+            },
+            TraceFormat::Binary => {
+                let mut buf = Vec::with_capacity(32);
+                write_varint(&mut buf, step - self.last_step);
+                write_varint(&mut buf, pc as u64);
+                write_varint(&mut buf, span.start.lineno as u64);
+                write_varint(&mut buf, span.start.column as u64);
+                write_varint(&mut buf, zigzag(ptr as i64));
+                write_value(&mut buf, before);
+                write_value(&mut buf, after);
+                self.out.write_all(&buf)?;
+            }
+        }
+        self.last_step = step;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub(crate) fn write_value<Int: BrainfuckInteger>(buf: &mut Vec<u8>, value: &Int) {
+    match (Int::size(), value.to_delta()) {
+        (Some(_), Some(value)) => write_varint(buf, zigzag(value as i64)),
+        _ => {
+            let value = format!("{:?}", value);
+            write_varint(buf, value.len() as u64);
+            buf.extend_from_slice(value.as_bytes());
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub(crate) fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
@@ -0,0 +1,224 @@
+//! Checks that the optimization passes don't change what a program does
+//! (`--verify-passes`). The programs before and after each pass run on the
+//! same inputs under a step budget. They have to write the same output and
+//! leave the same tape behind, otherwise the pass is reported together with
+//! a diff of the IR it changed.
+//!
+//! Inputs on which the program before the pass doesn't finish within the
+//! budget (or stops with an error) are skipped, they tell nothing about
+//! the pass.
+
+use std::io::Write;
+use super::{Brainfuck, BrainfuckInteger, Error, ExecOptions};
+use super::fuzz::{self, Outcome, Rng};
+
+pub const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+// lines of unchanged IR around the changes
+const CONTEXT: usize = 3;
+
+/// The inputs and limits the passes are verified with.
+pub struct VerifyOptions {
+    pub inputs: Vec<Vec<u8>>,
+    /// Tape, overflow handling and step budget of the runs.
+    pub exec: ExecOptions,
+}
+
+impl VerifyOptions {
+    /// Verifies with the generated inputs followed by the given ones.
+    pub fn new(user_inputs: Vec<Vec<u8>>, exec: ExecOptions) -> Self {
+        let mut inputs = generated_inputs();
+        inputs.extend(user_inputs);
+        VerifyOptions { inputs, exec }
+    }
+}
+
+/// No input, a single newline, a line of text and random bytes.
+pub fn generated_inputs() -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(0);
+    vec![
+        Vec::new(),
+        b"\n".to_vec(),
+        b"Hello, World!\n".to_vec(),
+        rng.bytes(4),
+        rng.bytes(64),
+    ]
+}
+
+/// An optimization pass that changed the behavior of the program.
+pub struct PassFailure<Int: BrainfuckInteger> {
+    pub pass: String,
+    pub input: Vec<u8>,
+    pub expected: Outcome<Int>,
+    pub actual: std::result::Result<Outcome<Int>, Error>,
+    pub before: Brainfuck<Int>,
+    pub after: Brainfuck<Int>,
+}
+
+/// Runs the programs before and after the pass on every input. Returns the
+/// first input on which they behave differently.
+pub fn verify_pass<Int: BrainfuckInteger>(pass: &str, before: &Brainfuck<Int>, after: &Brainfuck<Int>, options: &VerifyOptions) -> Option<PassFailure<Int>> {
+    // a program that constexpr evaluates completely leaves no tape behind
+    let tape = pass != "constexpr";
+    for input in &options.inputs {
+        let expected = match fuzz::run(before, input, &options.exec) {
+            Ok(expected) => expected,
+            Err(_) => continue,
+        };
+        let actual = fuzz::run(after, input, &options.exec);
+        let same = match actual {
+            Ok(ref actual) => actual.same(&expected, tape),
+            Err(_) => false,
+        };
+        if !same {
+            return Some(PassFailure {
+                pass: pass.to_string(),
+                input: input.clone(),
+                expected,
+                actual,
+                before: before.clone(),
+                after: after.clone(),
+            });
+        }
+    }
+    None
+}
+
+impl<Int: BrainfuckInteger> PassFailure<Int> {
+    /// Writes the input, what the programs before and after the pass did
+    /// and the IR diff of the pass.
+    pub fn write_report(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "input:    {:?}", String::from_utf8_lossy(&self.input))?;
+        write_outcome(out, "expected", &self.expected)?;
+        match self.actual {
+            Ok(ref actual) => write_outcome(out, "actual", actual)?,
+            Err(ref err)   => writeln!(out, "actual:   error: {}", err)?,
+        }
+
+        let mut before = Vec::new();
+        self.before.write_debug(&mut before)?;
+        let mut after = Vec::new();
+        self.after.write_debug(&mut after)?;
+        let before = String::from_utf8_lossy(&before);
+        let after = String::from_utf8_lossy(&after);
+        write!(out, "--- before {}\n+++ after {}\n", self.pass, self.pass)?;
+        write_diff(out, &before.lines().collect::<Vec<_>>(), &after.lines().collect::<Vec<_>>())
+    }
+}
+
+fn write_outcome<Int: BrainfuckInteger>(out: &mut dyn Write, label: &str, outcome: &Outcome<Int>) -> std::io::Result<()> {
+    write!(out, "{:9} output {:?}, pointer at {}, cells", format!("{}:", label),
+        String::from_utf8_lossy(&outcome.output), outcome.ptr)?;
+    if outcome.cells.is_empty() {
+        out.write_all(b" all 0")?;
+    }
+    for (index, val) in &outcome.cells {
+        write!(out, " {}={:?}", index, val)?;
+    }
+    out.write_all(b"\n")
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Remove,
+    Insert,
+}
+
+// Shortest edit script from a to b, as (edit, line) pairs.
+fn edit_script<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Edit, &'a str)> {
+    // the common prefix and suffix are kept, so only the changed part needs
+    // the quadratic table
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let mid_a = &a[prefix..a.len() - suffix];
+    let mid_b = &b[prefix..b.len() - suffix];
+
+    let mut script: Vec<(Edit, &str)> = a[..prefix].iter().map(|line| (Edit::Keep, *line)).collect();
+    if mid_a.len().saturating_mul(mid_b.len()) > 4_000_000 {
+        // too big, everything in between changed
+        script.extend(mid_a.iter().map(|line| (Edit::Remove, *line)));
+        script.extend(mid_b.iter().map(|line| (Edit::Insert, *line)));
+    } else {
+        // lcs[i][j]: length of the longest common subsequence of mid_a[i..] and mid_b[j..]
+        let width = mid_b.len() + 1;
+        let mut lcs = vec![0usize; (mid_a.len() + 1) * width];
+        for i in (0..mid_a.len()).rev() {
+            for j in (0..mid_b.len()).rev() {
+                lcs[i * width + j] = if mid_a[i] == mid_b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    std::cmp::max(lcs[(i + 1) * width + j], lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < mid_a.len() || j < mid_b.len() {
+            if i < mid_a.len() && j < mid_b.len() && mid_a[i] == mid_b[j] {
+                script.push((Edit::Keep, mid_a[i]));
+                i += 1;
+                j += 1;
+            } else if j == mid_b.len() || (i < mid_a.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+                script.push((Edit::Remove, mid_a[i]));
+                i += 1;
+            } else {
+                script.push((Edit::Insert, mid_b[j]));
+                j += 1;
+            }
+        }
+    }
+    script.extend(a[a.len() - suffix..].iter().map(|line| (Edit::Keep, *line)));
+    script
+}
+
+/// Writes the changed lines from before to after in the unified diff format.
+pub fn write_diff(out: &mut dyn Write, before: &[&str], after: &[&str]) -> std::io::Result<()> {
+    let script = edit_script(before, after);
+    let mut index = 0;
+    // line numbers in before and after at script[index]
+    let (mut line_a, mut line_b) = (1, 1);
+    while index < script.len() {
+        let change = match script[index..].iter().position(|(edit, _)| *edit != Edit::Keep) {
+            Some(pos) => index + pos,
+            None => break,
+        };
+        // the hunk ends after CONTEXT lines that are followed by no change
+        // within another CONTEXT lines
+        let mut end = change;
+        let mut kept = 0;
+        while end < script.len() && kept < CONTEXT * 2 {
+            if script[end].0 == Edit::Keep {
+                kept += 1;
+            } else {
+                kept = 0;
+            }
+            end += 1;
+        }
+        let end = if kept >= CONTEXT { end - (kept - CONTEXT) } else { end };
+        let start = change.saturating_sub(CONTEXT).max(index);
+
+        for (edit, _) in &script[index..start] {
+            match edit {
+                Edit::Keep   => { line_a += 1; line_b += 1; },
+                Edit::Remove => line_a += 1,
+                Edit::Insert => line_b += 1,
+            }
+        }
+        let hunk = &script[start..end];
+        let len_a = hunk.iter().filter(|(edit, _)| *edit != Edit::Insert).count();
+        let len_b = hunk.iter().filter(|(edit, _)| *edit != Edit::Remove).count();
+        writeln!(out, "@@ -{},{} +{},{} @@", line_a, len_a, line_b, len_b)?;
+        for (edit, line) in hunk {
+            let prefix = match edit {
+                Edit::Keep   => ' ',
+                Edit::Remove => '-',
+                Edit::Insert => '+',
+            };
+            writeln!(out, "{}{}", prefix, line)?;
+        }
+        line_a += len_a;
+        line_b += len_b;
+        index = end;
+    }
+    Ok(())
+}
@@ -0,0 +1,31 @@
+//! A brainfuck compiler and interpreter.
+//!
+//! Programs are parsed into a small intermediate representation
+//! ([`Brainfuck`]), which can be optimized ([`optimize`]), executed by the
+//! interpreter ([`Brainfuck::exec_with`]) or compiled to a Linux x86 64 binary
+//! ([`codegen::linux_x86_64`]). The cell type is a type parameter, see
+//! [`BrainfuckInteger`]. [`command`] runs the subcommands of the `bfc` tool.
+//!
+//! ```
+//! use bfc::{Brainfuck, ExecOptions, ParseOptions};
+//! use bfc::optimize::Options;
+//!
+//! let code = Brainfuck::<u8>::from_str("++++++++[>++++++++<-]>+.", &ParseOptions::default()).unwrap();
+//! let code = code.optimize_with(Options::all(), &mut std::io::sink()).unwrap();
+//!
+//! let mut output = Vec::new();
+//! let state = code.exec_with(&mut std::io::empty(), &mut output, &mut std::io::stderr(), ExecOptions::default()).unwrap();
+//! assert_eq!(output, b"A");
+//! // the whole program was evaluated by the optimizer, leaving a single write
+//! assert_eq!(state.steps(), 1);
+//! ```
+
+extern crate num_traits;
+
+mod brainfuck;
+
+pub use brainfuck::{
+    Brainfuck, BrainfuckInteger, BigInt, Error, Instruct, Span, Tape, TapeOptions, TapeEdge,
+    DEFAULT_TAPE_SIZE, MAX_TAPE_SIZE, ParseOptions, InputFormat, ExecOptions, ExecState,
+};
+pub use brainfuck::{optimize, codegen, fuzz, command};
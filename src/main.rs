@@ -12,25 +12,24 @@
 // ]                      -> }
 // [-{*}]+{n}.[-{*}]+{m}. -> write(STDOUT_FILENO, (unsigned char)[] {n, m}, 2)
 
-extern crate num_traits;
+extern crate bfc;
 extern crate clap;
-use clap::{Arg, App, SubCommand};
-use num_traits::Signed;
+use clap::{Arg, App, ArgMatches, SubCommand};
 use std::io::Write;
 
-mod brainfuck;
+use bfc::Error;
+use bfc::optimize::Options;
+use bfc::command::{self, Settings, Command, Compile, Exec, OutputFormat, TraceFormat, Lint};
 
-use brainfuck::{Brainfuck, Error, BrainfuckInteger};
-use brainfuck::optimize::Options;
-
-fn main() -> std::result::Result<(), std::io::Error> {
+fn main() {
     let matches = App::new("Brainfuck Compiler")
         .version("1.0")
         .author("Mathias Panzenböck")
 
         .arg(Arg::with_name("cell-size")
-            .help("size of a memory cell in bytes (default: 32)")
-            .possible_values(&["8", "16", "32", "64"])
+            .help("size of a memory cell in bits, append 'u' for unsigned cells,
+'big' for cells of arbitrary size (default: 32)")
+            .possible_values(&["8", "16", "32", "64", "8u", "16u", "32u", "64u", "big"])
             .short("s")
             .long("cell-size")
             .takes_value(true))
@@ -55,12 +54,76 @@ to enable all features except constexpr.
             .long("opt")
             .takes_value(true))
 
+        .arg(Arg::with_name("tape-size")
+            .help("number of cells of a bounded tape (default: 30000)")
+            .long("tape-size")
+            .takes_value(true))
+
+        .arg(Arg::with_name("tape-edge")
+            .help("\
+what happens when the pointer moves past the end of the tape:
+ * grow ........ the tape grows in both directions without limit (default)
+ * wrap ........ bounded tape, continue at the other end
+ * error ....... bounded tape, stop with an error
+")
+            .possible_values(&["grow", "wrap", "error"])
+            .long("tape-edge")
+            .takes_value(true))
+
+        .arg(Arg::with_name("input-format")
+            .help("\
+syntax of the input file:
+ * brainfuck ... brainfuck source (default, unless the file name ends in .ook)
+ * ir .......... the text written by compile --format debug
+ * ook ......... the Ook! dialect (default for files ending in .ook)
+")
+            .possible_values(&["brainfuck", "ir", "ook"])
+            .long("input-format")
+            .visible_alias("dialect")
+            .takes_value(true))
+
+        .arg(Arg::with_name("debug-char")
+            .help("character that dumps the tape to stderr when executed, e.g. '#' (default: none)")
+            .long("debug-char")
+            .takes_value(true))
+
+        .arg(Arg::with_name("trap-overflow")
+            .help("stop with an error when a cell over- or underflows instead of wrapping around")
+            .long("trap-overflow")
+            .takes_value(false))
+
         .arg(Arg::with_name("echo-constexpr")
             .help("print program output while evaluating constant part of program")
             .short("e")
             .long("echo-constexpr")
             .takes_value(false))
 
+        .arg(Arg::with_name("verify-ir")
+            .help("check the structure of the program after every optimization pass and stop with exit
+status 10 at the first pass that breaks it (always done by debug builds)")
+            .long("verify-ir")
+            .takes_value(false))
+
+        .arg(Arg::with_name("verify-passes")
+            .help("run the program before and after every optimization pass on some inputs and stop with
+exit status 10 at the first pass that changes the output or the tape")
+            .long("verify-passes")
+            .takes_value(false))
+
+        .arg(Arg::with_name("verify-input")
+            .help("with --verify-passes: also run the program on the content of FILE")
+            .long("verify-input")
+            .value_name("FILE")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+
+        .arg(Arg::with_name("verify-steps")
+            .help("with --verify-passes: maximum number of instructions of a run (default: 1000000)")
+            .long("verify-steps")
+            .value_name("N")
+            .takes_value(true))
+
         .subcommand(SubCommand::with_name("compile")
             .about("compiles a brainfuck program")
 
@@ -71,8 +134,14 @@ output formats:
  * binary ...... x86 64 Linux binary (default)
  * brainfuck ... brainfuck source
  * debug ....... text representation of internal bytecode
+ * bytecode .... bytecode file that exec and compile can load without
+                 parsing and optimizing the program again
+ * json ........ instructions, loops and what the optimizer knows about them
+                 (schema in src/brainfuck/json.rs)
+ * dot ......... control-flow graph in the Graphviz dot language
+ * ook ......... the Ook! dialect of the brainfuck output
 ")
-                .possible_values(&["source", "binary", "brainfuck", "debug"])
+                .possible_values(&["source", "binary", "brainfuck", "debug", "bytecode", "json", "dot", "ook"])
                 .short("f")
                 .long("format")
                 .takes_value(true))
@@ -94,169 +163,257 @@ output formats:
                 .long("c-opt-level")
                 .takes_value(true))
 
+            .arg(Arg::with_name("dot-pass")
+                .help("with --format dot: graphs of the program before and after the first run of this optimization")
+                .long("dot-pass")
+                .possible_values(&["fold", "set", "add_to", "write", "deadcode", "constexpr", "skip"])
+                .takes_value(true))
+
             .arg(Arg::with_name("OUTPUT")
                 .short("o")
                 .long("output")
                 .takes_value(true)))
 
         .subcommand(SubCommand::with_name("exec")
-            .about("executes a brainfuck program using an interpreter"))
+            .about("executes a brainfuck program using an interpreter")
+
+            .arg(Arg::with_name("trace")
+                .help("write a record of every executed instruction to FILE")
+                .long("trace")
+                .value_name("FILE")
+                .takes_value(true))
+
+            .arg(Arg::with_name("trace-format")
+                .help("\
+trace file formats:
+ * text ........ one line per instruction (default)
+ * binary ...... compact binary records
+")
+                .possible_values(&["text", "binary"])
+                .long("trace-format")
+                .takes_value(true))
+
+            .arg(Arg::with_name("trace-range")
+                .help("only trace instructions from the source range LINE[:COLUMN][-LINE[:COLUMN]]")
+                .long("trace-range")
+                .takes_value(true))
+
+            .arg(Arg::with_name("profile")
+                .help("print the most executed loops and instructions to stderr")
+                .long("profile")
+                .conflicts_with("trace")
+                .takes_value(false))
+
+            .arg(Arg::with_name("profile-folded")
+                .help("write the profile as folded stacks for flamegraph tools to FILE, implies --profile")
+                .long("profile-folded")
+                .value_name("FILE")
+                .conflicts_with("trace")
+                .takes_value(true))
+
+            .arg(Arg::with_name("coverage")
+                .help("add the source coverage of this run to the lcov tracefile FILE")
+                .long("coverage")
+                .value_name("FILE")
+                .conflicts_with("trace")
+                .takes_value(true))
+
+            .arg(Arg::with_name("max-steps")
+                .help("stop with exit status 3 after executing N instructions")
+                .long("max-steps")
+                .value_name("N")
+                .takes_value(true))
+
+            .arg(Arg::with_name("max-tape")
+                .help("stop with exit status 4 when the tape would grow beyond N cells")
+                .long("max-tape")
+                .value_name("N")
+                .takes_value(true))
+
+            .arg(Arg::with_name("timeout")
+                .help("stop with exit status 5 after running for SECONDS (fractions allowed)")
+                .long("timeout")
+                .value_name("SECONDS")
+                .takes_value(true)))
+
+        .subcommand(SubCommand::with_name("debug")
+            .about("runs a brainfuck program in an interactive debugger"))
+
+        .subcommand(SubCommand::with_name("fmt")
+            .about("formats a brainfuck program and writes it to stdout")
+
+            .arg(Arg::with_name("check")
+                .help("only check if the program is formatted, exits with status 9 if not")
+                .long("check")
+                .takes_value(false))
+
+            .arg(Arg::with_name("write")
+                .help("overwrite the input file with the formatted program")
+                .short("w")
+                .long("write")
+                .conflicts_with("check")
+                .takes_value(false)))
+
+        .subcommand(SubCommand::with_name("lint")
+            .about("reports suspicious constructs, exits with status 9 if there are any")
+
+            .arg(Arg::with_name("format")
+                .help("\
+output formats:
+ * text ........ compiler style messages with the source line (default)
+ * json ........ a JSON object with all warnings
+")
+                .possible_values(&["text", "json"])
+                .short("f")
+                .long("format")
+                .takes_value(true))
+
+            .arg(Arg::with_name("allow")
+                .help("\
+don't report these lints (comma separated):
+ * dead-loop ....... loop that never runs
+ * infinite-loop ... loop that never terminates
+ * wrap-loop ....... loop that only terminates for some cell values
+ * unreachable ..... code after a loop that never terminates
+ * tape-underflow .. pointer moves left of the first cell
+ * noise ........... commands that cancel each other out, like +- or <>
+ * dead-store ...... cell changes that are overwritten before they are used
+")
+                .short("A")
+                .long("allow")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)))
 
         .arg(Arg::with_name("INPUT")
             .required(true))
 
-        .get_matches();
+        .get_matches_safe()
+        .unwrap_or_else(|err| match err.kind {
+            clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => err.exit(),
+            // same exit status as the other command line errors
+            _ => {
+                let _ = writeln!(std::io::stderr(), "{}", err.message);
+                std::process::exit(2);
+            }
+        });
 
     let input = matches.value_of("INPUT").expect("input file is required");
-    let mut options = Options::none();
-
-    if let Some(opts) = matches.value_of("optimizations") {
-        for opt in opts.split(",") {
-            match opt.as_ref() {
-                "all" | "+all" | "-none" => {
-                    options = Options::all();
-                },
-                "none" | "+none" | "-all" => {
-                    options = Options::none();
-                },
-                "fold" | "+fold" => {
-                    options.fold = true;
-                },
-                "-fold" => {
-                    options.fold = false;
-                },
-                "set" | "+set" => {
-                    options.set = true;
-                },
-                "-set" => {
-                    options.set = false;
-                },
-                "add_to" | "+add_to" | "addto" | "+addto" => {
-                    options.add_to = true;
-                },
-                "-add_to" | "-addto" => {
-                    options.add_to = false;
-                },
-                "write" | "+write" => {
-                    options.write = true;
-                },
-                "-write" => {
-                    options.write = false;
-                },
-                "deadcode" | "+deadcode" => {
-                    options.deadcode = true;
-                },
-                "-deadcode" => {
-                    options.deadcode = false;
-                },
-                "constexpr" | "+constexpr" => {
-                    options.constexpr = true;
-                },
-                "-constexpr" => {
-                    options.constexpr = false;
-                },
-                "skip" | "+skip" => {
-                    options.skip = true;
-                },
-                "-skip" => {
-                    options.skip = false;
-                },
-                "" => {},
-                _ => {
-                    panic!("illegal optimization: {}", opt);
-                }
-            }
-        }
-    }
 
-    let int_size: u32 = matches.value_of("cell-size")
-        .unwrap_or("32")
-        .parse()
-        .expect("cell-size is not a positive integer");
+    if let Err(err) = run(&matches, input) {
+        let _ = command::print_error(&err, input, &mut std::io::stderr());
+        std::process::exit(err.exit_code());
+    }
+}
 
-    options.constexpr_echo = matches.is_present("echo-constexpr");
+// parses the value of an optional argument
+fn parse_arg<T: std::str::FromStr>(matches: &ArgMatches, name: &'static str, expected: &'static str) -> std::result::Result<Option<T>, Error> {
+    match matches.value_of(name) {
+        None => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(|_| Error::IllegalArgument {
+            name,
+            value: value.to_string(),
+            expected,
+        }),
+    }
+}
 
-    let res = match matches.subcommand() {
-        ("compile", Some(sub)) => {
-            let format = sub.value_of("format").unwrap_or("binary");
-            let keep_source = sub.is_present("keep-source");
-            let debug = sub.is_present("debug");
-            let c_opt_level: u32 = sub.value_of("c-opt-level")
-                .unwrap_or("0")
-                .parse()
-                .expect("c-opt-level is positive integer");
-            let output = sub.value_of("OUTPUT").unwrap_or(
-                match format.as_ref() {
-                    "source"    => "a.out",
-                    "binary"    => "a.out",
-                    "brainfuck" => "out.bf",
-                    "debug"     => "out.txt",
-                    _           => panic!("unsupported format: {}", format)
-                });
-
-            match int_size {
-                 8 => compile::< i8>(&input, &output, options, &format, keep_source, debug, c_opt_level),
-                16 => compile::<i16>(&input, &output, options, &format, keep_source, debug, c_opt_level),
-                32 => compile::<i32>(&input, &output, options, &format, keep_source, debug, c_opt_level),
-                64 => compile::<i64>(&input, &output, options, &format, keep_source, debug, c_opt_level),
-                _  => panic!("illegal integer size: {}", int_size)
-            }
-        },
-        ("exec", _) => {
-            match int_size {
-                 8 => exec::< i8>(&input, options),
-                16 => exec::<i16>(&input, options),
-                32 => exec::<i32>(&input, options),
-                64 => exec::<i64>(&input, options),
-                _  => panic!("illegal integer size: {}", int_size)
-            }
-        },
-        ("", _) => {
-            write!(std::io::stderr(), "A sub-command is required!\n")?;
-            std::process::exit(1);
-        },
-        (cmd, _) => {
-            write!(std::io::stderr(), "Illegal sub-command: {}\n", cmd)?;
-            std::process::exit(1);
-        },
+fn run(matches: &ArgMatches, input: &str) -> std::result::Result<(), Error> {
+    let mut optimizations = match matches.value_of("optimizations") {
+        Some(opts) => opts.parse().map_err(Error::IllegalOptimization)?,
+        None       => Options::none(),
     };
+    optimizations.constexpr_echo = matches.is_present("echo-constexpr");
 
-    if let Err(err) = res {
-        err.print(&mut std::io::stderr(), &input)?;
-        std::process::exit(1);
-    }
+    let cell_size = match matches.value_of("cell-size") {
+        Some(size) => Some(size.parse().map_err(|_| Error::IllegalCellSize(size.to_string()))?),
+        None       => None,
+    };
 
-    Ok(())
-}
+    let debug_char = match matches.value_of("debug-char") {
+        None => None,
+        Some(debug_char) => {
+            let mut chars = debug_char.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if !"<>+-[].,".contains(c) => Some(c),
+                _ => return Err(Error::IllegalArgument {
+                    name:     "debug-char",
+                    value:    debug_char.to_string(),
+                    expected: "a single character that isn't a brainfuck command",
+                }),
+            }
+        }
+    };
 
-fn compile<Int: BrainfuckInteger + Signed>(
-        input: &str, output: &str, options: Options, format: &str, keep_source: bool, debug: bool, c_opt_level: u32)
-        -> std::result::Result<(), Error> {
-    let code = Brainfuck::<Int>::from_file(input)?;
-    let code = code.optimize(options)?;
+    let settings = Settings {
+        input:         input.to_string(),
+        cell_size,
+        tape_size:     matches.value_of("tape-size").map(command::parse_tape_size).transpose()?,
+        tape_edge:     parse_arg(matches, "tape-edge", "grow, wrap or error")?,
+        trap_overflow: if matches.is_present("trap-overflow") { Some(true) } else { None },
+        optimizations,
+        debug_char,
+        input_format:  parse_arg(matches, "input-format", "brainfuck, ir or ook")?,
+        verify_ir:     matches.is_present("verify-ir"),
+        verify_passes: matches.is_present("verify-passes"),
+        verify_inputs: matches.values_of("verify-input").into_iter().flatten().map(String::from).collect(),
+        verify_steps:  parse_arg(matches, "verify-steps", "a positive integer")?,
+    };
 
-    match format {
-        "source"    => {
-            brainfuck::codegen::linux_x86_64::generate(&code, output)?;
+    let command = match matches.subcommand() {
+        ("compile", Some(sub)) => {
+            let format = match sub.value_of("format") {
+                Some(format) => format.parse().map_err(|_| Error::UnsupportedFormat(format.to_string()))?,
+                None         => OutputFormat::Binary,
+            };
+            Command::Compile(Compile {
+                format,
+                output:      sub.value_of("OUTPUT").map(String::from),
+                keep_source: sub.is_present("keep-source"),
+                debug:       sub.is_present("debug"),
+                c_opt_level: parse_arg(sub, "c-opt-level", "a positive integer")?.unwrap_or(0),
+                dot_pass:    sub.value_of("dot-pass").map(String::from),
+            })
         },
-        "binary"    => brainfuck::codegen::linux_x86_64::compile(&code, output, debug, c_opt_level, keep_source)?,
-        "brainfuck" => {
-            let mut out = std::fs::File::create(output)?;
-            code.write_bf(&mut out)?;
+        ("exec", Some(sub)) => {
+            let timeout = match parse_arg::<f64>(sub, "timeout", "a number of seconds")? {
+                // negative, not finite or too large for a Duration
+                Some(secs) => Some(std::time::Duration::try_from_secs_f64(secs).map_err(|_| Error::IllegalArgument {
+                    name:     "timeout",
+                    value:    sub.value_of("timeout").unwrap_or_default().to_string(),
+                    expected: "a number of seconds",
+                })?),
+                None => None,
+            };
+            Command::Exec(Exec {
+                max_steps:      parse_arg(sub, "max-steps", "a positive integer")?,
+                max_tape:       parse_arg(sub, "max-tape", "a positive integer")?,
+                timeout,
+                trace:          sub.value_of("trace").map(String::from),
+                trace_format:   parse_arg(sub, "trace-format", "text or binary")?.unwrap_or(TraceFormat::Text),
+                trace_range:    sub.value_of("trace-range").map(String::from),
+                profile:        sub.is_present("profile"),
+                profile_folded: sub.value_of("profile-folded").map(String::from),
+                coverage:       sub.value_of("coverage").map(String::from),
+            })
         },
-        "debug"     => {
-            let mut out = std::fs::File::create(output)?;
-            code.write_debug(&mut out)?;
+        ("debug", _) => Command::Debug,
+        ("fmt", Some(sub)) => Command::Fmt { check: sub.is_present("check"), write: sub.is_present("write") },
+        ("lint", Some(sub)) => {
+            let mut allow = Vec::new();
+            for name in sub.values_of("allow").into_iter().flatten() {
+                let lint: Lint = name.parse().map_err(|_| Error::IllegalArgument {
+                    name:     "lint",
+                    value:    name.to_string(),
+                    expected: "a known lint",
+                })?;
+                allow.push(lint);
+            }
+            Command::Lint { json: sub.value_of("format") == Some("json"), allow }
         },
-        _           => panic!("unsupported format: {}", format),
-    }
+        ("", _) => return Err(Error::MissingSubcommand),
+        (cmd, _) => return Err(Error::IllegalSubcommand(cmd.to_string())),
+    };
 
-    Ok(())
+    command::run(&settings, &command)
 }
-
-fn exec<Int: BrainfuckInteger + Signed>(input: &str, options: Options) -> std::result::Result<(), Error> {
-    let code = Brainfuck::<Int>::from_file(input)?;
-    let code = code.optimize(options)?;
-    code.exec()?;
-    Ok(())
-}
\ No newline at end of file
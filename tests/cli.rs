@@ -0,0 +1,523 @@
+//! Runs the bfc binary, mostly to check that illegal arguments end with an
+//! error message and exit status instead of a panic, and that the formats
+//! bfc writes can be read back.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+// a file in the temp directory that is removed again when dropped
+struct TempFile(String);
+
+impl TempFile {
+    fn new(ext: &str) -> Self {
+        let file = FILES.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("bfc-cli-{}-{}.{}", std::process::id(), file, ext));
+        TempFile(path.to_str().unwrap().to_string())
+    }
+
+    fn with(contents: &[u8], ext: &str) -> Self {
+        let file = TempFile::new(ext);
+        std::fs::write(&file.0, contents).unwrap();
+        file
+    }
+
+    fn read(&self) -> Vec<u8> {
+        std::fs::read(&self.0).unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// runs bfc with input on stdin
+fn run(args: &[String], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bfc"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // bfc may exit without reading all of it
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+// runs bfc with {} in the arguments replaced by a file containing source
+fn bfc_with(source: &[u8], args: &[&str], input: &[u8]) -> Output {
+    let file = TempFile::with(source, "bf");
+    let args: Vec<String> = args.iter().map(|arg| arg.replace("{}", &file.0)).collect();
+    run(&args, input)
+}
+
+fn bfc(source: &str, args: &[&str]) -> Output {
+    bfc_with(source.as_bytes(), args, b"")
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn assert_illegal_argument(output: &Output) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.starts_with("error: illegal"), "{}", stderr);
+}
+
+#[test]
+fn timeout_out_of_range() {
+    for timeout in &["1e30", "nan", "inf"] {
+        assert_illegal_argument(&bfc("+.", &["{}", "exec", "--timeout", timeout]));
+    }
+}
+
+#[test]
+fn tape_size_out_of_range() {
+    for size in &["0", "9223372036854775808", "18446744073709551615"] {
+        assert_illegal_argument(&bfc("+.", &["--tape-size", size, "--tape-edge", "wrap", "{}", "exec"]));
+    }
+}
+
+#[test]
+fn debugger_breakpoint_and_watchpoint() {
+    let commands = "break 2:7\ncontinue\nprint\nprint 1\nwatch 0\nrestart\ncontinue\ninfo\nquit\n";
+    let out = stdout(&bfc_with(b"++\n[->+<]>.", &["-s", "8", "{}", "debug"], commands.as_bytes()));
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines.contains(&"(bfdb) Breakpoint 1 at 2:7"), "{}", out);
+    assert!(lines.contains(&"#8 2:7: move 1"), "{}", out);
+    assert!(lines.contains(&"(bfdb) cell 0 = 0"), "{}", out);
+    assert!(lines.contains(&"(bfdb) cell 1 = 2"), "{}", out);
+    // the watchpoint stops at the first change after the restart
+    assert!(out.contains("Watchpoint 2: cell 0: 0 -> 1\n#1 1:2: add 1\n"), "{}", out);
+    assert!(lines.contains(&"(bfdb) 1 instructions executed"), "{}", out);
+}
+
+// the trace of source with the extra arguments
+fn trace(source: &str, args: &[&str]) -> Vec<u8> {
+    let file = TempFile::new("trace");
+    let mut args = args.to_vec();
+    args.extend(&["--trace", &file.0]);
+    stdout(&bfc(source, &args));
+    file.read()
+}
+
+#[test]
+fn trace_text_and_range() {
+    let source = "++\n[->+<]>.";
+    let out = String::from_utf8(trace(source, &["-s", "8", "{}", "exec"])).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[..4], ["# step pc pos ptr before after", "1 0 1:1 0 0 1", "2 1 1:2 0 1 2", "3 2 2:1 0 2 2"]);
+    assert_eq!(lines.len(), 18);
+    assert_eq!(lines[17], "17 9 2:8 1 2 2");
+
+    let out = String::from_utf8(trace(source, &["-s", "8", "{}", "exec", "--trace-range", "2:2-2:3"])).unwrap();
+    assert_eq!(out, "# step pc pos ptr before after\n4 3 2:2 0 2 1\n5 4 2:3 0 1 1\n10 3 2:2 0 1 0\n11 4 2:3 0 0 0\n");
+
+    let file = TempFile::new("trace");
+    assert_illegal_argument(&bfc(source, &["{}", "exec", "--trace", &file.0, "--trace-range", "2:3-x"]));
+}
+
+#[test]
+fn trace_binary() {
+    let out = trace("+-", &["-s", "8", "{}", "exec", "--trace-format", "binary"]);
+    assert_eq!(out, [
+        b'B', b'F', b'T', 1, 8, 1,
+        // step delta, pc, line, column, ptr, before, after
+        1, 0, 1, 1, 0, 0, 2,
+        1, 1, 1, 2, 0, 2, 0,
+    ]);
+}
+
+// the lint names bfc lint -f json reports for source, in order
+fn lints(source: &str) -> Vec<String> {
+    let output = bfc(source, &["-s", "8", "{}", "lint", "-f", "json"]);
+    let out = String::from_utf8(output.stdout).unwrap();
+    let expected = if out.contains("\"lint\"") { 9 } else { 0 };
+    assert_eq!(output.status.code(), Some(expected), "{}", out);
+    out.split("{\"lint\":\"").skip(1)
+        .map(|rest| rest[..rest.find('"').unwrap()].to_string())
+        .collect()
+}
+
+#[test]
+fn lint_warnings() {
+    assert_eq!(lints("+[-][-.]+."), ["dead-store", "dead-loop"]);
+    assert_eq!(lints("+[]+."), ["infinite-loop", "unreachable"]);
+    assert_eq!(lints(",[--]"), ["wrap-loop"]);
+    assert_eq!(lints("<+."), ["tape-underflow"]);
+    assert_eq!(lints("+.+-<>."), ["noise", "noise"]);
+    assert!(lints("++[->+<]>.").is_empty());
+}
+
+#[test]
+fn lint_text_and_allow() {
+    let output = bfc("+[-][-.]+.", &["{}", "lint"]);
+    assert_eq!(output.status.code(), Some(9));
+    let out = String::from_utf8(output.stdout).unwrap();
+    assert!(out.contains(":1:5: loop never runs, the cell is always 0 here [dead-loop]\n    1 | +[-][-.]+.\n      |     ^^^^\n"), "{}", out);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with(": 2 lint warning(s)\n"), "{}", stderr);
+
+    let output = bfc("+[-][-.]+.", &["{}", "lint", "-A", "dead-loop,dead-store"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_illegal_argument(&bfc("+.", &["{}", "lint", "-A", "no-such-lint"]));
+}
+
+#[test]
+fn lint_json_spans() {
+    let output = bfc("+[-][-.]+.", &["{}", "lint", "-f", "json", "-A", "dead-store"]);
+    assert_eq!(output.status.code(), Some(9));
+    let out = String::from_utf8(output.stdout).unwrap();
+    // the same span objects as the JSON export
+    assert!(out.starts_with("{\"version\":2,"), "{}", out);
+    assert!(out.ends_with("\n{\"lint\":\"dead-loop\",\"span\":{\"start\":{\"line\":1,\"column\":5},\"end\":{\"line\":1,\"column\":8}},\
+        \"message\":\"loop never runs, the cell is always 0 here\"}]}\n"), "{}", out);
+}
+
+#[test]
+fn fmt_keeps_comments_and_is_idempotent() {
+    let source = "print A: ++++++++[>++++++++<-]>+.   done\n\n\n[comment loop, with . and , inside]\n";
+    let formatted = stdout(&bfc(source, &["{}", "fmt"]));
+    assert_eq!(formatted, "print A: ++++++++[>++++++++<-] >+. done\n\n[ comment loop , with . and , inside ]\n");
+
+    for source in &[source, "+[\n>,[>+<-]\n\t<.[-]]", "a[b[c[d]e]f]g", "++ # note\n\n  --", ""] {
+        let formatted = stdout(&bfc(source, &["{}", "fmt"]));
+        assert_eq!(stdout(&bfc(&formatted, &["{}", "fmt"])), formatted, "{:?}", source);
+        assert_eq!(bfc(&formatted, &["{}", "fmt", "--check"]).status.code(), Some(0), "{:?}", source);
+    }
+}
+
+#[test]
+fn fmt_check_and_write() {
+    let source = "+[->+<]  >.";
+    let output = bfc(source, &["{}", "fmt", "--check"]);
+    assert_eq!(output.status.code(), Some(9));
+    assert!(String::from_utf8(output.stderr).unwrap().ends_with(": source is not formatted\n"));
+
+    let formatted = stdout(&bfc(source, &["{}", "fmt"]));
+    let file = TempFile::with(source.as_bytes(), "bf");
+    stdout(&run(&[file.0.clone(), "fmt".to_string(), "--write".to_string()], b""));
+    assert_eq!(file.read(), formatted.as_bytes());
+}
+
+// source compiled to format with the options in args
+fn compile(source: &[u8], args: &[&str], format: &str) -> Vec<u8> {
+    let file = TempFile::new(format);
+    let mut args = args.to_vec();
+    args.extend(&["{}", "compile", "-f", format, "-o", &file.0]);
+    stdout(&bfc_with(source, &args, b""));
+    file.read()
+}
+
+// expects a syntax error with message at line:column of the input
+fn assert_syntax_error(output: &Output, message: &str) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(6), "{}", stderr);
+    assert!(stderr.lines().next().unwrap().ends_with(&format!(".bf:{}", message)), "{}", stderr);
+}
+
+#[test]
+fn ir_round_trip() {
+    let source = b",[->+>++<<]>[-<+>]++++++++[>++++++++<-]>+.<<.";
+    for args in &[["-s", "8", "-O", "none"], ["-s", "8", "-O", "all"], ["-s", "big", "-O", "all"]] {
+        let ir = compile(source, args, "debug");
+        let cell_size = &args[..2];
+        let ir_args = [cell_size, &["--input-format", "ir"]].concat();
+        assert_eq!(String::from_utf8(compile(&ir, &ir_args, "debug")).unwrap(), String::from_utf8(ir.clone()).unwrap());
+
+        let exec = |source: &[u8], args: &[&str]| stdout(&bfc_with(source, &[args, &["{}", "exec"]].concat(), b"a"));
+        assert_eq!(exec(&ir, &ir_args), exec(source, args));
+    }
+}
+
+#[test]
+fn ir_hand_written() {
+    let ir = "set 100000000000000000000000\nloop {\n  sub_from 1\n  set 0\n}\nmove 1\nadd 3  # comment\nwrite [72, 105]\nwrite\n";
+    let out = bfc_with(ir.as_bytes(), &["-s", "big", "--input-format", "ir", "{}", "exec"], b"");
+    assert_eq!(out.stdout, b"Hi\x03");
+    assert_eq!(String::from_utf8(compile(ir.as_bytes(), &["-s", "big", "--input-format", "ir"], "debug")).unwrap(),
+        "set 100000000000000000000000\nloop {\n    sub_from 1\n    set 0\n}\nmove 1\nadd 3\nwrite [72, 105]\nwrite\n");
+}
+
+#[test]
+fn ir_error_positions() {
+    for (ir, message) in &[
+        ("move 0\nwrite\n", "1:6: expected a non-zero offset, found '0'"),
+        ("add 1\nfrob 3\n", "2:1: unknown instruction 'frob'"),
+        ("add 1\nwrite\nloop {\n", "3:1: loop without '}'"),
+        ("loop {\n}\n}\n", "3:1: '}' without loop"),
+        ("write [72, 300]\n", "1:12: expected a byte, found '300'"),
+        ("set 1000\n", "1:5: expected a cell value, found '1000'"),
+        // columns count chars, not bytes
+        ("move\u{a0}1\u{a0}move 0 # ünïcödé\n", "1:13: expected a non-zero offset, found '0'"),
+    ] {
+        assert_syntax_error(&bfc(ir, &["-s", "8", "--input-format", "ir", "{}", "exec"]), message);
+    }
+}
+
+// expects bfc to refuse the bytecode because of a differing option
+fn assert_bytecode_mismatch(output: &Output, message: &str) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert_eq!(stderr, format!("error: the bytecode was compiled with {}\n", message));
+}
+
+#[test]
+fn bytecode_round_trip() {
+    let source = b",[->+>++<<]>[-<+>]++++++++[>++++++++<-]>+.<<.";
+    for args in &[["-s", "8", "-O", "none"], ["-s", "16u", "-O", "fold,set"], ["-s", "big", "-O", "all"]] {
+        let bytecode = compile(source, args, "bytecode");
+        assert_eq!(&bytecode[..4], b"BFBC");
+        assert_eq!(compile(&bytecode, &[], "debug"), compile(source, args, "debug"));
+        let exec = |source: &[u8], args: &[&str]| stdout(&bfc_with(source, &[args, &["{}", "exec"]].concat(), b"a"));
+        assert_eq!(exec(&bytecode, &[]), exec(source, args));
+    }
+}
+
+#[test]
+fn bytecode_header_mismatch() {
+    let bytecode = compile(b"++++++++[>++++++++<-]>+.", &["-s", "8", "--tape-edge", "wrap", "--tape-size", "100", "-O", "all"], "bytecode");
+    let exec = |args: &[&str]| bfc_with(&bytecode, &[args, &["{}", "exec"]].concat(), b"");
+    assert_eq!(stdout(&exec(&["-s", "8", "--tape-edge", "wrap", "--tape-size", "100"])), "A");
+    assert_bytecode_mismatch(&exec(&["-s", "16"]), "cell-size 8, not 16");
+    assert_bytecode_mismatch(&exec(&["--tape-edge", "error"]), "tape-edge wrap, not error");
+    assert_bytecode_mismatch(&exec(&["--tape-size", "200"]), "tape-size 100, not 200");
+    assert_bytecode_mismatch(&exec(&["--trap-overflow"]), "trap-overflow off, not on");
+}
+
+#[test]
+fn bytecode_trap_overflow_from_header() {
+    let bytecode = compile(b"+.-.-.", &["-s", "8u", "--trap-overflow"], "bytecode");
+    for args in &[&[][..], &["--trap-overflow"][..]] {
+        let output = bfc_with(&bytecode, &[*args, &["{}", "exec"]].concat(), b"");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(7), "{}", stderr);
+        assert_eq!(output.stdout, b"\x01\x00");
+        assert!(stderr.ends_with(": arithmetic overflow in cell 0\n"), "{}", stderr);
+    }
+}
+
+#[test]
+fn bytecode_illegal() {
+    let bytecode = compile(b"+.", &["-s", "8"], "bytecode");
+    let mut version = bytecode.clone();
+    version[4] = 99;
+    for (data, message) in &[(version, "unsupported version 99"), (bytecode[..12].to_vec(), "unexpected end of file")] {
+        let output = bfc_with(data, &["{}", "exec"], b"");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{}", stderr);
+        assert!(stderr.ends_with(&format!(".bf: illegal bytecode file: {}\n", message)), "{}", stderr);
+    }
+}
+
+#[test]
+fn json_instructions_and_loops() {
+    let json = String::from_utf8(compile(b"+[>[-]<[-]]", &["-s", "big"], "json")).unwrap();
+    let span = |column: usize| format!("\"span\":{{\"start\":{{\"line\":1,\"column\":{0}}},\"end\":{{\"line\":1,\"column\":{0}}}}}", column);
+    let expected = [
+        "{\"version\":1,\"cell\":{\"bits\":null,\"signed\":true},\"instructions\":[".to_string(),
+        format!("{{\"index\":0,\"op\":\"add\",\"value\":1,{},\"known\":null}},", span(1)),
+        format!("{{\"index\":1,\"op\":\"loop_start\",\"end\":10,{},\"known\":null}},", span(2)),
+        format!("{{\"index\":2,\"op\":\"move\",\"offset\":1,{},\"known\":null}},", span(3)),
+        format!("{{\"index\":3,\"op\":\"loop_start\",\"end\":5,{},\"known\":null}},", span(4)),
+        format!("{{\"index\":4,\"op\":\"add\",\"value\":-1,{},\"known\":null}},", span(5)),
+        format!("{{\"index\":5,\"op\":\"loop_end\",\"start\":3,{},\"known\":null}},", span(6)),
+        format!("{{\"index\":6,\"op\":\"move\",\"offset\":-1,{},\"known\":0}},", span(7)),
+        format!("{{\"index\":7,\"op\":\"loop_start\",\"end\":9,{},\"known\":null}},", span(8)),
+        format!("{{\"index\":8,\"op\":\"add\",\"value\":-1,{},\"known\":null}},", span(9)),
+        format!("{{\"index\":9,\"op\":\"loop_end\",\"start\":7,{},\"known\":null}},", span(10)),
+        format!("{{\"index\":10,\"op\":\"loop_end\",\"start\":1,{},\"known\":0}}],\"loops\":[", span(11)),
+        "{\"start\":1,\"end\":10,\"depth\":0,\"parent\":null,\"if\":true,\"entered\":null},".to_string(),
+        "{\"start\":3,\"end\":5,\"depth\":1,\"parent\":1,\"if\":false,\"entered\":null},".to_string(),
+        "{\"start\":7,\"end\":9,\"depth\":1,\"parent\":1,\"if\":false,\"entered\":null}]}".to_string(),
+    ];
+    assert_eq!(json.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn json_values() {
+    let ir = b"set 100000000000000000000000\nwrite [72, 105]\n";
+    let json = String::from_utf8(compile(ir, &["-s", "big", "--input-format", "ir"], "json")).unwrap();
+    assert!(json.contains("{\"index\":0,\"op\":\"set\",\"value\":100000000000000000000000,"), "{}", json);
+    assert!(json.contains("{\"index\":1,\"op\":\"write_str\",\"bytes\":[72, 105],"), "{}", json);
+    let json = String::from_utf8(compile(b"-", &["-s", "16u"], "json")).unwrap();
+    assert!(json.starts_with("{\"version\":1,\"cell\":{\"bits\":16,\"signed\":false},"), "{}", json);
+}
+
+#[test]
+fn dot_control_flow_graph() {
+    let dot = String::from_utf8(compile(b"++\n[->+<]>.", &[], "dot")).unwrap();
+    assert_eq!(dot, "\
+digraph program {
+    n0 [label=\"start\", shape=oval];
+    n1 [label=\"   0: add 1\\l   1: add 1\\l\", shape=box, fontname=monospace];
+    n2 [label=\"2: loop at 2:1\", shape=diamond];
+    n3 [label=\"   3: add -1\\l   4: move 1\\l   5: add 1\\l   6: move -1\\l\", shape=box, fontname=monospace];
+    n4 [label=\"   8: move 1\\l   9: write\\l\", shape=box, fontname=monospace];
+    n5 [label=\"end\", shape=oval];
+    n0 -> n1;
+    n1 -> n2;
+    n2 -> n3 [label=\"!= 0\"];
+    n3 -> n2 [style=dashed];
+    n2 -> n4 [label=\"== 0\"];
+    n4 -> n5;
+}
+");
+}
+
+#[test]
+fn dot_before_and_after_pass() {
+    let file = TempFile::new("dot");
+    stdout(&bfc("+[-]>.", &["-O", "set", "{}", "compile", "-f", "dot", "--dot-pass", "set", "-o", &file.0]));
+    let dot = String::from_utf8(file.read()).unwrap();
+    assert!(dot.starts_with("digraph program {\n    subgraph cluster_before {\n    label=\"before set\";\n"), "{}", dot);
+    assert!(dot.contains("    b2 [label=\"1: loop at 1:2\", shape=diamond];\n"), "{}", dot);
+    assert!(dot.contains("    subgraph cluster_after {\n    label=\"after set\";\n"), "{}", dot);
+    assert!(dot.contains("    a1 [label=\"   0: add 1\\l   1: set 0\\l   2: move 1\\l   3: write\\l\", shape=box, fontname=monospace];\n"), "{}", dot);
+
+    assert_illegal_argument(&bfc("+[-]>.", &["-O", "fold", "{}", "compile", "-f", "dot", "--dot-pass", "set", "-o", &file.0]));
+}
+
+#[test]
+fn ook_round_trip() {
+    let source = b",[->+>++<<]>[-<+>]++++++++[>++++++++<-]>+.<<.";
+    for args in &[["-s", "8", "-O", "none"], ["-s", "8", "-O", "all"], ["-s", "big", "-O", "fold,set,write"]] {
+        let ook = compile(source, args, "ook");
+        assert!(ook.starts_with(b"Ook. Ook! "), "{}", String::from_utf8_lossy(&ook));
+        let ook_args = [&args[..2], &["--input-format", "ook"]].concat();
+        let exec = |source: &[u8], args: &[&str]| stdout(&bfc_with(source, &[args, &["{}", "exec"]].concat(), b"a"));
+        assert_eq!(exec(&ook, &ook_args), exec(source, args));
+    }
+
+    // unoptimized Ook! is the same program
+    let ook = compile(source, &[], "ook");
+    assert_eq!(compile(&ook, &["--input-format", "ook"], "brainfuck"), source);
+    // and files ending in .ook are read as Ook!
+    let file = TempFile::with(&ook, "ook");
+    assert_eq!(stdout(&run(&[file.0.clone(), "exec".to_string()], b"a")), stdout(&bfc_with(source, &["{}", "exec"], b"a")));
+}
+
+#[test]
+fn ook_error_positions() {
+    for (ook, message) in &[
+        ("Ook. Ook.\nOok! Ook? Ook?\n", "2:11: 'Ook?' without a second word"),
+        ("Ook? Ook? Ook.", "1:1: unknown pair 'Ook? Ook?'"),
+        ("Ook. Ook. Ook? Ook!", "1:11: unmatched 'Ook? Ook!' (loop end)"),
+        ("hi Ook! Ook? Ook. Ook.", "1:4: unmatched 'Ook! Ook?' (loop start)"),
+    ] {
+        assert_syntax_error(&bfc(ook, &["--input-format", "ook", "{}", "exec"]), message);
+    }
+}
+
+// the lcov records of a tracefile starting with key
+fn records(info: &TempFile, key: &str) -> Vec<String> {
+    String::from_utf8(info.read()).unwrap().lines()
+        .filter(|line| line.starts_with(key))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn coverage_loops_in_source_order() {
+    let info = TempFile::new("info");
+    stdout(&bfc("++++++++[-]+++[-]\n[-]", &["{}", "exec", "--coverage", &info.0]));
+    assert_eq!(records(&info, "FN:"), ["FN:1,loop@1:9", "FN:1,loop@1:15", "FN:2,loop@2:1"]);
+}
+
+#[test]
+fn coverage_does_not_depend_on_optimizations() {
+    // all of it is evaluated at compile time with constexpr
+    let source = "++++++++[>++++++++<-]>+.\n[-][>+++<-]";
+    for opt in &["none", "all"] {
+        let info = TempFile::new("info");
+        assert_eq!(stdout(&bfc(source, &["-O", opt, "{}", "exec", "--coverage", &info.0])), "A");
+        assert_eq!(records(&info, "FNH:"), ["FNH:3"], "-O {}", opt);
+        assert_eq!(records(&info, "FNDA:")[0], "FNDA:9,loop@1:9", "-O {}", opt);
+        assert_eq!(records(&info, "DA:")[0], "DA:1,9", "-O {}", opt);
+    }
+}
+
+#[test]
+fn coverage_merges_runs() {
+    let info = TempFile::new("info");
+    let a = TempFile::with(b"+\n[-]", "bf");
+    let b = TempFile::with(b".", "bf");
+    for file in &[&a, &a, &b] {
+        stdout(&run(&[file.0.clone(), "exec".to_string(), "--coverage".to_string(), info.0.clone()], b""));
+    }
+
+    let name = |file: &TempFile| format!("SF:{}", std::fs::canonicalize(&file.0).unwrap().display());
+    assert_eq!(records(&info, "SF:"), [name(&a), name(&b)]);
+    assert_eq!(records(&info, "DA:"), ["DA:1,2", "DA:2,4", "DA:1,1"]);
+    assert_eq!(records(&info, "FNDA:"), ["FNDA:4,loop@2:1"]);
+    assert_eq!(records(&info, "BRDA:"), ["BRDA:1,0,1,2", "BRDA:2,0,1,4", "BRDA:2,0,2,2", "BRDA:2,0,3,2", "BRDA:1,0,1,1"]);
+}
+
+#[test]
+fn coverage_rejects_broken_records() {
+    let info = TempFile::with(b"TN:\nSF:a.bf\nDA:1,x\nend_of_record\n", "info");
+    let output = bfc("+", &["{}", "exec", "--coverage", &info.0]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.trim_end().ends_with(&format!("{}:3: illegal lcov record: DA:1,x", info.0)), "{}", stderr);
+}
+
+#[test]
+fn trap_overflow_sub_i64_min() {
+    let sub = b"add -9223372036854775808\nwrite\n";
+    let args = ["-s", "64", "--trap-overflow", "--input-format", "ir"];
+    // x - 2^63 overflows exactly for negative x
+    let exec = |source: &[u8]| bfc_with(source, &[&args[..], &["{}", "exec"]].concat(), b"");
+    assert_eq!(exec(sub).status.code(), Some(0));
+    assert_eq!(exec(&[&b"add -1\n"[..], sub].concat()).status.code(), Some(7));
+
+    // -2^63 as immediate would invert the overflow flag, so the compiled
+    // program checks the sign before
+    let out = TempFile::new("out");
+    let asm = TempFile(format!("{}.asm", out.0));
+    let _runtime = TempFile(format!("{}-runtime.c", out.0));
+    stdout(&bfc_with(sub, &[&args[..], &["{}", "compile", "--format", "source", "-o", &out.0]].concat(), b""));
+    let asm = String::from_utf8(asm.read()).unwrap();
+    let lines: Vec<&str> = asm.lines().map(str::trim).collect();
+    let index = lines.iter().position(|line| line.starts_with("jl   ovf")).expect(&asm);
+    assert_eq!(lines[index - 1], "cmp  qword [r12], 0");
+    assert!(lines[index + 2].starts_with("sub  qword [r12], rax"), "{}", asm);
+    assert!(!lines[index + 3].starts_with("jo"), "{}", asm);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn binary_runtime_error_exit_status() {
+    if Command::new("gcc").arg("--version").output().is_err() {
+        eprintln!("skipping the binary runtime error test: gcc not found");
+        return;
+    }
+    let binary = TempFile::new("out");
+    stdout(&bfc("+.<", &["-s", "big", "--tape-size", "5", "--tape-edge", "error", "{}", "compile", "-o", &binary.0]));
+    let output = Command::new(&binary.0).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // same as the interpreter
+    assert_eq!(output.status.code(), Some(7), "{}", stderr);
+    assert_eq!(output.stdout, b"\x01");
+    assert_eq!(stderr, "error:1:3: pointer moved out of tape bounds: cell -1 is not in 0...4\n");
+}
+
+#[test]
+fn verify_passes_add_to_target() {
+    // the add_to changes the cell set before, so the second [-] is needed
+    let out = bfc_with(b",>[-]<[->+<]>[-].", &["-O", "all", "--verify-passes", "{}", "exec"], b"A");
+    assert_eq!(stdout(&out), "\0");
+    // but an add_to to another cell keeps it, so fold drops the second [-]
+    let source = b",>[-]<[->>+<<]>[-]>.";
+    let out = bfc_with(source, &["-O", "all", "--verify-passes", "{}", "exec"], b"A");
+    assert_eq!(stdout(&out), "A");
+    let ir = String::from_utf8(compile(source, &["-O", "all"], "debug")).unwrap();
+    assert_eq!(ir, "read\nmove 1\nset 0\nmove -1\nadd_to 2\nset 0\nmove 2\nwrite\n");
+}
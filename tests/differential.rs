@@ -0,0 +1,192 @@
+//! Differential tests of the optimizer and the code generator. Random
+//! programs are run unoptimized, with every single optimization, with all
+//! of them, written back as brainfuck and as compiled binary, all runs have
+//! to agree. Failing programs
+//! are shrunk before they are reported. A compiled binary has to exit
+//! successfully and only its output is compared, its tape can't be seen.
+//!
+//! `BFC_FUZZ_CASES` sets the number of programs per test and
+//! `BFC_FUZZ_SEED` the first seed, to reproduce a reported failure. Compiling
+//! fixed size cells needs nasm and big cells gcc, the binary tests are
+//! skipped with a message on stderr if they are missing.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use bfc::{BigInt, Brainfuck, BrainfuckInteger, Error, ExecOptions, ParseOptions, TapeOptions};
+use bfc::fuzz::{self, GenOptions, Rng};
+use bfc::optimize::Options;
+use bfc::codegen::linux_x86_64;
+
+const MAX_STEPS: u64 = 100_000;
+
+fn env_number(name: &str, default: u64) -> u64 {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| panic!("{} is not a number: {}", name, value)),
+        Err(_) => default,
+    }
+}
+
+// Describes how the program misbehaves, None if it doesn't.
+fn failure<Int: BrainfuckInteger>(source: &str, input: &[u8], binary: Option<&str>) -> Option<String> {
+    let expected = match fuzz::check::<Int>(source, input, MAX_STEPS) {
+        Ok(Some(expected)) => expected,
+        Ok(None) => return None,
+        Err(mismatch) => return Some(mismatch.to_string()),
+    };
+    let code = Brainfuck::<Int>::from_str(source, &ParseOptions::default()).unwrap()
+        .optimize_with(Options::all(), &mut std::io::sink()).unwrap();
+    if let Some(message) = write_bf_failure(&code, input, &expected.output) {
+        return Some(message);
+    }
+    let binary = binary?;
+
+    if let Err(err) = linux_x86_64::compile(&code, binary, &TapeOptions::default(), false, false, 0, false) {
+        return Some(format!("compile failed: {}", err));
+    }
+    let mut child = Command::new(binary)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("running the compiled binary");
+    // a program that doesn't read all of the input may exit before it is
+    // written
+    match child.stdin.take().unwrap().write_all(input) {
+        Err(ref err) if err.kind() == std::io::ErrorKind::BrokenPipe => {},
+        result => result.unwrap(),
+    }
+    let result = child.wait_with_output().unwrap();
+    if !result.status.success() {
+        return Some(format!("binary failed: {}", result.status));
+    }
+    if result.stdout != expected.output {
+        return Some(format!("binary differs from -O none\nexpected: {:?}\nactual:   {:?}", expected.output, result.stdout));
+    }
+    None
+}
+
+// Describes how the optimized code written as brainfuck misbehaves, None if
+// it doesn't or can't be written. It may use scratch cells, so only the
+// output is compared.
+fn write_bf_failure<Int: BrainfuckInteger>(code: &Brainfuck<Int>, input: &[u8], expected: &[u8]) -> Option<String> {
+    let mut source = Vec::new();
+    match code.write_bf(&mut source) {
+        Ok(()) => {},
+        Err(Error::NotRepresentable { .. }) | Err(Error::ConstantTooLarge { .. }) => return None,
+        Err(err) => return Some(format!("write_bf failed: {}", err)),
+    }
+    let source = String::from_utf8(source).unwrap();
+    let written = Brainfuck::<Int>::from_str(&source, &ParseOptions::default()).unwrap();
+    let options = ExecOptions {
+        max_steps: Some(MAX_STEPS * 10),
+        ..ExecOptions::default()
+    };
+    match fuzz::run(&written, input, &options) {
+        Ok(ref outcome) if outcome.output == expected => None,
+        // a clear of a wide cell may count through all its values, only an
+        // unbounded one can run forever
+        Err(Error::StepLimitExceeded { .. }) if Int::size().is_some() => None,
+        Ok(outcome) => Some(format!("written brainfuck differs from -O none\nsource:   {}\nexpected: {:?}\nactual:   {:?}", source, expected, outcome.output)),
+        Err(err) => Some(format!("written brainfuck failed: {}\nsource:   {}", err, source)),
+    }
+}
+
+// Path for the compiled binaries, None if the toolchain isn't installed.
+fn binary_file<Int: BrainfuckInteger>(name: &str) -> Option<String> {
+    let binary = std::env::temp_dir().join(format!("bfc-differential-{}-{}", name, std::process::id()));
+    let binary = binary.to_str().unwrap().to_string();
+    let code = Brainfuck::<Int>::from_str("+.", &ParseOptions::default()).unwrap();
+    match linux_x86_64::compile(&code, &binary, &TapeOptions::default(), false, false, 0, false) {
+        Err(Error::IO(ref err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!("skipping the {} binary test: toolchain not found", name);
+            None
+        },
+        Err(err) => panic!("compiling +. failed: {}", err),
+        Ok(()) => Some(binary),
+    }
+}
+
+// compiling is slow, so fewer programs
+fn binary<Int: BrainfuckInteger>(name: &str) {
+    if let Some(binary) = binary_file::<Int>(name) {
+        differential::<Int>(name, env_number("BFC_FUZZ_CASES", 500) / 10, Some(&binary));
+    }
+}
+
+fn differential<Int: BrainfuckInteger>(name: &str, cases: u64, binary: Option<&str>) {
+    let first = env_number("BFC_FUZZ_SEED", 0);
+    let options = GenOptions::default();
+    for seed in first..first + cases {
+        let mut rng = Rng::new(seed);
+        let source = fuzz::generate(&mut rng, &options);
+        let len = rng.below(4);
+        let input = rng.bytes(len);
+        if failure::<Int>(&source, &input, binary).is_some() {
+            let shrunk = fuzz::shrink(&source, &mut |source| failure::<Int>(source, &input, binary).is_some());
+            let message = failure::<Int>(&shrunk, &input, binary).unwrap();
+            if let Some(binary) = binary {
+                let _ = std::fs::remove_file(binary);
+            }
+            panic!("{} cells, seed {}\nprogram: {}\nshrunk:  {}\ninput:   {:?}\n{}", name, seed, source, shrunk, input, message);
+        }
+    }
+    if let Some(binary) = binary {
+        let _ = std::fs::remove_file(binary);
+    }
+}
+
+// clears of unbounded cells that are or may be negative
+#[test]
+fn write_bf_big_clears() {
+    let set = Options { set: true, ..Options::none() };
+    for (source, input) in &[("-[+]+++.", &b""[..]), (",[+]+++.", b""), (",[+]+++.", b"a"), (",[->+<]>.", b"a"), ("-[+>+<]>.", b""),
+            (",[->+<]>[-<+<+>>]<.<.", b"a"), (",>,<[->-<]>[-<+<+>>]<.<.", b"ab")] {
+        for options in &[set.clone(), Options::all()] {
+            let code = Brainfuck::<BigInt>::from_str(source, &ParseOptions::default()).unwrap()
+                .optimize_with(options.clone(), &mut std::io::sink()).unwrap();
+            let options = ExecOptions { max_steps: Some(MAX_STEPS), ..ExecOptions::default() };
+            let expected = fuzz::run(&code, input, &options).unwrap().output;
+            if let Some(message) = write_bf_failure(&code, input, &expected) {
+                panic!("{}: {}", source, message);
+            }
+        }
+    }
+}
+
+#[test]
+fn optimizer_u8() {
+    differential::<u8>("u8", env_number("BFC_FUZZ_CASES", 500), None);
+}
+
+#[test]
+fn optimizer_i16() {
+    differential::<i16>("i16", env_number("BFC_FUZZ_CASES", 200), None);
+}
+
+#[test]
+fn optimizer_big() {
+    differential::<BigInt>("big", env_number("BFC_FUZZ_CASES", 200), None);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn binary_u8() {
+    binary::<u8>("u8");
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn binary_i16() {
+    binary::<i16>("i16");
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn binary_u32() {
+    binary::<u32>("u32");
+}
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn binary_big() {
+    binary::<BigInt>("big");
+}
@@ -0,0 +1,16 @@
+use bfc::{Brainfuck, ExecOptions, ParseOptions};
+
+#[test]
+fn debug_dump_goes_to_dump_writer() {
+    let options = ParseOptions { debug_char: Some('#'), ..ParseOptions::default() };
+    let code = Brainfuck::<u8>::from_str("++.>+#", &options).unwrap();
+    let mut output = Vec::new();
+    let mut dump = Vec::new();
+    code.exec_with(&mut std::io::empty(), &mut output, &mut dump, ExecOptions::default()).unwrap();
+    assert_eq!(output, b"\x02");
+    let dump = String::from_utf8(dump).unwrap();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines[0], "debug dump at 1:6:");
+    assert!(lines[2].starts_with("value  0"), "{}", dump);
+    assert!(lines[2].ends_with(" 0 2 1 0 0 0 0 0 0 0 0  0  0"), "{}", dump);
+}
@@ -0,0 +1,107 @@
+//! Regression tests of single optimization passes. Each program is run
+//! unoptimized and with the pass, both runs have to write the same output
+//! or, with trap_overflow, both have to trap.
+
+use bfc::{Brainfuck, BrainfuckInteger, Error, ExecOptions, ParseOptions};
+use bfc::optimize::Options;
+
+fn output<Int: BrainfuckInteger>(source: &str, options: Options, input: &[u8]) -> Vec<u8> {
+    let code = Brainfuck::<Int>::from_str(source, &ParseOptions::default()).unwrap()
+        .optimize_with(options, &mut std::io::sink()).unwrap();
+    let mut output = Vec::new();
+    code.exec_with(&mut &input[..], &mut output, &mut std::io::stderr(), ExecOptions::default()).unwrap();
+    output
+}
+
+fn only(pass: &str) -> Options {
+    let mut options = Options::none();
+    match pass {
+        "fold"   => options.fold   = true,
+        "set"    => options.set    = true,
+        "add_to" => options.add_to = true,
+        "skip"   => options.skip   = true,
+        "all"    => options = Options::all(),
+        "none"   => {},
+        _        => panic!("unknown pass {}", pass),
+    }
+    options
+}
+
+// runs the program with --trap-overflow after the optimization pass
+fn traps<Int: BrainfuckInteger>(source: &str, pass: &str) -> bool {
+    let mut options = only(pass);
+    options.trap_overflow = true;
+    let code = Brainfuck::<Int>::from_str(source, &ParseOptions::default()).unwrap()
+        .optimize_with(options, &mut std::io::sink()).unwrap();
+    let exec_options = ExecOptions { trap_overflow: true, ..ExecOptions::default() };
+    match code.exec_with(&mut std::io::empty(), &mut std::io::sink(), &mut std::io::sink(), exec_options) {
+        Err(Error::CellOverflow { .. }) => true,
+        Ok(_) => false,
+        Err(err) => panic!("{}: {}", source, err),
+    }
+}
+
+fn assert_traps<Int: BrainfuckInteger>(source: &str, passes: &[&str]) {
+    assert!(traps::<Int>(source, "none"), "{} doesn't trap unoptimized", source);
+    for pass in passes {
+        assert!(traps::<Int>(source, pass), "{} doesn't trap with {}", source, pass);
+    }
+}
+
+fn assert_same<Int: BrainfuckInteger>(source: &str, options: Options, input: &[u8]) {
+    assert_eq!(output::<Int>(source, options, input), output::<Int>(source, Options::none(), input), "{}", source);
+}
+
+#[test]
+fn skip_keeps_add_written_by_loop() {
+    // the + is read by the . in the loop before the , overwrites it
+    assert_same::<u8>("+[.-],.", only("skip"), b"x");
+}
+
+#[test]
+fn skip_keeps_add_read_by_loop_condition() {
+    // the loop condition reads the + before the , overwrites it
+    assert_same::<u8>("+[>+<-],>.", only("skip"), b"x");
+}
+
+#[test]
+fn skip_tracks_pointer_after_unbalanced_loop() {
+    // [<] ends somewhere else, so the , doesn't overwrite the first cell
+    assert_same::<u8>("+>++[<]<,>>.", only("skip"), b"x");
+}
+
+#[test]
+fn skip_keeps_add_read_by_next_iteration() {
+    // the + in the loop body is read by the . of the next iteration
+    assert_same::<u8>("++[>.+<-]", only("skip"), b"");
+}
+
+#[test]
+fn set_keeps_underflow_after_clear() {
+    // [-]- is not set 255 on an unsigned cell
+    assert_traps::<u8>(">+++[-]-", &["set", "all"]);
+}
+
+#[test]
+fn set_keeps_overflowing_clear() {
+    // [+] on a positive signed cell overflows on the way to 0
+    assert_traps::<i8>("+[+]", &["set", "all"]);
+}
+
+#[test]
+fn fold_keeps_cancelling_adds() {
+    // the - of -+ underflows on a cell holding 0
+    assert_traps::<u8>("++[>+<-]>-->-+", &["fold", "all"]);
+}
+
+#[test]
+fn add_to_keeps_negative_counter() {
+    // [->+<] decrements the negative counter until it underflows
+    assert_traps::<i8>("-[->+<]", &["add_to", "all"]);
+}
+
+#[test]
+fn skip_keeps_overwritten_underflow() {
+    // the - underflows before , overwrites the cell
+    assert_traps::<u8>("-,", &["skip", "all"]);
+}
@@ -0,0 +1,21 @@
+use bfc::{Tape, TapeEdge, TapeOptions, MAX_TAPE_SIZE};
+
+#[test]
+fn bounded_tape_allocates_touched_cells() {
+    let mut tape = Tape::new(TapeOptions::new(MAX_TAPE_SIZE, TapeEdge::Wrap), 0u8);
+    *tape.cell() = 1;
+    tape.move_by(-1).unwrap();
+    assert_eq!(tape.pos(), MAX_TAPE_SIZE as isize - 1);
+    assert_eq!(*tape.get(0), 1);
+    assert_eq!(tape.cells().len(), 1);
+    assert_eq!(tape.cells_with(0), MAX_TAPE_SIZE);
+}
+
+#[test]
+fn error_edge_reports_logical_index() {
+    let mut tape = Tape::new(TapeOptions::new(MAX_TAPE_SIZE, TapeEdge::Error), 0u8);
+    assert_eq!(tape.move_by(-1), Err(-1));
+    tape.move_by(MAX_TAPE_SIZE as isize - 1).unwrap();
+    assert_eq!(tape.move_by(1), Err(MAX_TAPE_SIZE as isize));
+    assert_eq!(tape.cells().len(), 0);
+}
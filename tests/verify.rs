@@ -0,0 +1,40 @@
+use bfc::{Brainfuck, Error, ParseOptions, Span};
+use bfc::optimize::Options;
+
+// index and message of the verify error of the program built by build
+fn malformed(build: &dyn Fn(&mut Brainfuck<u8>)) -> (usize, String) {
+    let mut code = Brainfuck::new();
+    code.push_read(Span::at(1, 1));
+    build(&mut code);
+    match code.verify() {
+        Err(Error::MalformedProgram { pass: None, index, message }) => (index, message),
+        Err(err) => panic!("unexpected error: {}", err),
+        Ok(()) => panic!("malformed program passed"),
+    }
+}
+
+#[test]
+fn accepts_parsed_and_optimized_programs() {
+    let code = Brainfuck::<u8>::from_str("+[->+<[-]]>.,[>+<-]", &ParseOptions::default()).unwrap();
+    code.verify().unwrap();
+    code.optimize(Options::all()).unwrap().verify().unwrap();
+    Brainfuck::<u8>::new().verify().unwrap();
+}
+
+#[test]
+fn rejects_malformed_programs() {
+    assert_eq!(malformed(&|code| code.push_move(0, Span::at(1, 2))), (1, "move 0".to_string()));
+    assert_eq!(malformed(&|code| code.push_add(0, Span::at(1, 2))), (1, "add 0".to_string()));
+    assert_eq!(malformed(&|code| code.push_write_str(Vec::new(), Span::at(1, 2))), (1, "empty write".to_string()));
+    assert_eq!(malformed(&|code| {
+        code.push_loop_start(Span::at(1, 2));
+        code.push_write(Span::at(1, 3));
+    }), (1, "loop is not closed".to_string()));
+}
+
+#[test]
+fn malformed_program_message() {
+    let err = Error::MalformedProgram { pass: Some("fold".to_string()), index: 3, message: "move 0".to_string() };
+    assert_eq!(err.to_string(), "optimization pass fold broke the program at instruction 3: move 0");
+    assert_eq!(err.exit_code(), 10);
+}